@@ -1,12 +1,22 @@
-use std::net::SocketAddr;
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
-use aipim::client::{Client, Message, Response as AipimResponse};
+use aipim::{
+    budget::{self, BudgetExceededError, CostTracker, Spend},
+    cache::ResponseCache,
+    client::{self, AvailableModel, Client, Message, Response as AipimResponse},
+    provider::{Balancer, MemberHealth},
+};
 use axum::{
     debug_handler,
     extract::{rejection::JsonRejection, DefaultBodyLimit, FromRequest, State},
     http,
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{get, post},
     Router,
 };
 use serde::Serialize;
@@ -14,6 +24,8 @@ use serde::Serialize;
 enum ApiError {
     JsonRejection(JsonRejection),
     AnyhowError(anyhow::Error),
+    BadRequest(String),
+    NotFound(String),
 }
 
 impl IntoResponse for ApiError {
@@ -25,9 +37,14 @@ impl IntoResponse for ApiError {
 
         let (status, message) = match self {
             ApiError::JsonRejection(rejection) => (rejection.status(), rejection.body_text()),
+            ApiError::AnyhowError(error) if error.is::<BudgetExceededError>() => {
+                (http::StatusCode::TOO_MANY_REQUESTS, error.to_string())
+            }
             ApiError::AnyhowError(error) => {
                 (http::StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
             }
+            ApiError::BadRequest(message) => (http::StatusCode::BAD_REQUEST, message),
+            ApiError::NotFound(message) => (http::StatusCode::NOT_FOUND, message),
         };
 
         (status, ApiJson(ErrorResponse { message })).into_response()
@@ -59,21 +76,160 @@ where
     }
 }
 
+/// Settings of the API server.
+pub struct Settings {
+    /// Name of the model serving messages that don't specify one.
+    pub default_model: String,
+    /// Pool serving messages that don't specify a model, instead of the default model.
+    pub pool: Option<Balancer>,
+    /// Tracker accounting the cost of every response to the tag of its message, which must have a
+    /// budget of its own.
+    pub costs: CostTracker,
+    /// Cache answering repeated messages, unless they set `bypass_cache`.
+    pub cache: Option<ResponseCache>,
+    /// Whether images are preprocessed to fit the limits of the providers.
+    pub preprocess_images: bool,
+}
+
+/// Maximum number of per-model clients kept, as models are named by callers.
+const MAX_CLIENTS: usize = 64;
+/// How long the list of models is reused before listing them from the providers again.
+const MODELS_TTL: Duration = Duration::from_secs(300);
+
+/// The clients serving each model, evicting the least recently used one when full.
+#[derive(Default)]
+struct Clients {
+    entries: HashMap<String, (Client, u64)>,
+    clock: u64,
+}
+
+impl Clients {
+    fn get(&mut self, model: &str) -> Option<Client> {
+        self.clock += 1;
+        let (client, used) = self.entries.get_mut(model)?;
+        *used = self.clock;
+        Some(client.clone())
+    }
+
+    fn insert(&mut self, model: String, client: Client) {
+        if self.entries.len() >= MAX_CLIENTS {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(model, _)| model.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(model, (client, self.clock));
+    }
+}
+
+/// The models last listed, and when.
+type ListedModels = (Instant, Vec<AvailableModel>);
+
 #[derive(Clone)]
 struct AppState {
     default_model: String,
+    clients: Arc<Mutex<Clients>>,
+    models: Arc<Mutex<Option<ListedModels>>>,
+    pool: Option<Pool>,
+    costs: CostTracker,
+    cache: Option<ResponseCache>,
+    preprocess_images: bool,
+    /// HTTP client shared by the clients of every model, so they share its connection pool.
+    http: reqwest::Client,
+}
+
+impl AppState {
+    /// Returns the client serving the given model, creating it on first use.
+    fn client(&self, model: &str) -> anyhow::Result<Client> {
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(client) = clients.get(model) {
+            return Ok(client);
+        }
+
+        let mut client = Client::from_http_client(model, self.http.clone())?
+            .with_image_preprocessing(self.preprocess_images)
+            .with_cost_tracker(self.costs.clone());
+        if let Some(cache) = &self.cache {
+            client = client.with_cache(cache.clone());
+        }
+        clients.insert(model.to_string(), client.clone());
+        Ok(client)
+    }
+}
+
+/// The JSON body returned for a message, keeping the concatenated `text` next to the content
+/// blocks for clients that only need the text.
+#[derive(Serialize)]
+struct MessageResponse {
+    text: String,
+    #[serde(flatten)]
+    response: AipimResponse,
+}
+
+impl From<AipimResponse> for MessageResponse {
+    fn from(response: AipimResponse) -> Self {
+        Self {
+            text: response.text(),
+            response,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Pool {
+    balancer: Balancer,
+    client: Client,
 }
 
-pub async fn listen(addr: SocketAddr, default_model: impl Into<String>) -> anyhow::Result<()> {
-    let default_model = default_model.into();
+pub async fn listen(addr: SocketAddr, settings: Settings) -> anyhow::Result<()> {
+    let Settings {
+        default_model,
+        pool,
+        costs,
+        cache,
+        preprocess_images,
+    } = settings;
 
     log::info!("Default model: {default_model}");
+    let pool = pool.map(|balancer| {
+        log::info!("Balancing across {} pool members", balancer.health().len());
+        let mut client = Client::from_provider(balancer.clone())
+            .with_image_preprocessing(preprocess_images)
+            .with_cost_tracker(costs.clone());
+        if let Some(cache) = &cache {
+            client = client.with_cache(cache.clone());
+        }
+        Pool { balancer, client }
+    });
+    if preprocess_images {
+        log::info!("Preprocessing images");
+    }
+    if cache.is_some() {
+        log::info!("Caching responses");
+    }
     log::info!("Listening on {addr}...");
 
-    let state = AppState { default_model };
+    let state = AppState {
+        default_model,
+        clients: Arc::default(),
+        models: Arc::default(),
+        pool,
+        costs,
+        cache,
+        preprocess_images,
+        http: client::Timeouts::default().http_client(),
+    };
 
     let app = Router::new()
         .route("/api/messages", post(messages))
+        .route("/api/models", get(models))
+        .route("/api/pool", get(pool_health))
+        .route("/api/spend", get(spend))
         // .layer(RequestBodyLimit::max(1024))
         .layer(DefaultBodyLimit::max(52428800))
         .with_state(state);
@@ -87,16 +243,93 @@ pub async fn listen(addr: SocketAddr, default_model: impl Into<String>) -> anyho
 async fn messages(
     State(state): State<AppState>,
     ApiJson(message): ApiJson<Message>,
-) -> Result<ApiJson<AipimResponse>, ApiError> {
+) -> Result<ApiJson<MessageResponse>, ApiError> {
     log::debug!("Sending message: {message:?}");
+    // Tags are named by callers, so only the ones configured with a budget are accepted, rather
+    // than letting callers open new allowances.
+    if let Some(tag) = &message.tag {
+        if tag != budget::DEFAULT_TAG && !state.costs.has_budget(tag) {
+            return Err(ApiError::BadRequest(format!(
+                "no budget is configured for tag {tag}"
+            )));
+        }
+    }
+    if let (Some(pool), None) = (&state.pool, &message.model) {
+        return pool
+            .client
+            .send_message(message)
+            .await
+            .map(|response| ApiJson(response.into()))
+            .map_err(Into::into);
+    }
+
     let model = match message.model {
         Some(ref model) => model,
         None => &state.default_model,
     };
-    let client = Client::new(model)?;
+    let client = state.client(model)?;
     client
         .send_message(message)
         .await
-        .map(ApiJson)
+        .map(|response| ApiJson(response.into()))
         .map_err(Into::into)
 }
+
+/// Lists the models available to the default model's client and to the pool members, so
+/// frontends can populate model pickers.
+///
+/// Both are listed concurrently, skipping the one that fails, and the list is reused for
+/// `MODELS_TTL`.
+#[debug_handler]
+async fn models(State(state): State<AppState>) -> Result<ApiJson<Vec<AvailableModel>>, ApiError> {
+    if let Some((listed_at, models)) = &*state.models.lock().unwrap() {
+        if listed_at.elapsed() < MODELS_TTL {
+            return Ok(ApiJson(models.clone()));
+        }
+    }
+
+    let client = state.client(&state.default_model)?;
+    let pool = async {
+        match &state.pool {
+            Some(pool) => Some(pool.client.list_models().await),
+            None => None,
+        }
+    };
+    let (listed, pooled) = tokio::join!(client.list_models(), pool);
+
+    let mut models = Vec::new();
+    let mut last_error = None;
+    for (source, listed) in [("default model", Some(listed)), ("pool", pooled)] {
+        match listed {
+            Some(Ok(listed)) => models.extend(listed),
+            Some(Err(err)) => {
+                log::warn!("Failed to list the models of the {source}: {err}");
+                last_error = Some(err);
+            }
+            None => {}
+        }
+    }
+    if let (Some(err), true) = (last_error, models.is_empty()) {
+        return Err(err.into());
+    }
+
+    let models = client::dedup_models(models);
+    *state.models.lock().unwrap() = Some((Instant::now(), models.clone()));
+    Ok(ApiJson(models))
+}
+
+#[debug_handler]
+async fn pool_health(
+    State(state): State<AppState>,
+) -> Result<ApiJson<Vec<MemberHealth>>, ApiError> {
+    let pool = state
+        .pool
+        .ok_or_else(|| ApiError::NotFound("no provider pool is configured".to_string()))?;
+    Ok(ApiJson(pool.balancer.health()))
+}
+
+/// Reports the spend of every tag, in US dollars.
+#[debug_handler]
+async fn spend(State(state): State<AppState>) -> ApiJson<BTreeMap<String, Spend>> {
+    ApiJson(state.costs.report())
+}
@@ -0,0 +1,47 @@
+use std::{collections::HashMap, path::Path};
+
+use aipim::budget::{Budget, CostTracker};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Default)]
+/// Represents the budgets enforced by the server, read from a JSON file.
+///
+/// Requests are accounted to the `tag` of their message, which must be listed in `tags`: messages
+/// with other tags are rejected. Messages without a tag share the `default` budget. Amounts are in
+/// US dollars, and spend is kept in memory, so `total` budgets start over when the server
+/// restarts.
+///
+/// ```json
+/// {
+///   "default": { "daily": 20.0 },
+///   "tags": {
+///     "search-team": { "daily": 100.0 },
+///     "nightly-eval": { "total": 250.0 }
+///   }
+/// }
+/// ```
+pub struct BudgetConfig {
+    default: Option<Budget>,
+    #[serde(default)]
+    tags: HashMap<String, Budget>,
+}
+
+impl BudgetConfig {
+    /// Reads the budget configuration from a JSON file.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Builds the `CostTracker` enforcing these budgets.
+    pub fn build(self) -> CostTracker {
+        let mut tracker = CostTracker::new();
+        if let Some(budget) = self.default {
+            tracker = tracker.default_budget(budget);
+        }
+        for (tag, budget) in self.tags {
+            tracker = tracker.budget(tag, budget);
+        }
+        tracker
+    }
+}
@@ -1,10 +1,13 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
+use aipim::cache::{MemoryCache, ResponseCache, SqliteCache};
 use clap::Parser;
 use log::LevelFilter;
 use simplelog::{ColorChoice, CombinedLogger, Config, TermLogger, TerminalMode};
 
 mod api;
+mod budget;
+mod pool;
 
 #[derive(Parser)]
 #[command(version, about)]
@@ -17,6 +20,38 @@ struct Cli {
     #[arg(short = 'm', long)]
     default_model: String,
 
+    /// Path to a JSON file describing a pool of providers to balance requests across.
+    ///
+    /// When set, messages that don't specify a model are served by the pool.
+    #[arg(short, long)]
+    pool: Option<PathBuf>,
+
+    /// Path to a JSON file describing the spending limits of each tag, in US dollars.
+    ///
+    /// Messages may only be tagged with the tags listed in this file. The spend of every tag is
+    /// tracked and reported by `GET /api/spend` either way, in memory only.
+    #[arg(short, long)]
+    budgets: Option<PathBuf>,
+
+    /// Cache up to this many responses, answering repeated messages without sending them.
+    ///
+    /// Responses are kept in memory, or in the database given with `--cache-db`.
+    #[arg(long)]
+    cache_capacity: Option<usize>,
+
+    /// Path to a SQLite database caching responses on disk, instead of in memory. The number of
+    /// responses is unbounded unless `--cache-capacity` is set.
+    #[arg(long)]
+    cache_db: Option<PathBuf>,
+
+    /// Number of seconds cached responses are kept, forever by default.
+    #[arg(long)]
+    cache_ttl: Option<u64>,
+
+    /// Resize, convert and strip the metadata of images to fit the limits of each provider.
+    #[arg(long)]
+    preprocess_images: bool,
+
     /// Verbose mode, display debug information.
     #[arg(short, long)]
     verbose: bool,
@@ -48,6 +83,40 @@ async fn main() -> anyhow::Result<()> {
     ])
     .unwrap();
 
-    api::listen(cli.address, cli.default_model).await?;
+    let pool = match cli.pool {
+        Some(path) => Some(pool::PoolConfig::load(path)?.build()?),
+        None => None,
+    };
+
+    let costs = match cli.budgets {
+        Some(path) => budget::BudgetConfig::load(path)?.build(),
+        None => budget::BudgetConfig::default().build(),
+    };
+
+    let cache = match (cli.cache_db, cli.cache_capacity) {
+        (Some(path), capacity) => {
+            let store = SqliteCache::open(path)?;
+            let store = match capacity {
+                Some(capacity) => store.with_capacity(capacity),
+                None => store,
+            };
+            Some(ResponseCache::new(store))
+        }
+        (None, Some(capacity)) => Some(ResponseCache::new(MemoryCache::new(capacity))),
+        (None, None) => None,
+    };
+    let cache = match cli.cache_ttl {
+        Some(secs) => cache.map(|cache| cache.with_ttl(Duration::from_secs(secs))),
+        None => cache,
+    };
+
+    let settings = api::Settings {
+        default_model: cli.default_model,
+        pool,
+        costs,
+        cache,
+        preprocess_images: cli.preprocess_images,
+    };
+    api::listen(cli.address, settings).await?;
     Ok(())
 }
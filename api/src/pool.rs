@@ -0,0 +1,77 @@
+use std::{path::Path, time::Duration};
+
+use aipim::{
+    client::Timeouts,
+    provider::{self, Balancer, Strategy},
+};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+/// Represents the configuration of a pool of providers, read from a JSON file.
+///
+/// ```json
+/// {
+///   "strategy": "weighted",
+///   "cooldown_secs": 30,
+///   "members": [
+///     { "label": "openai-1", "model": "gpt-4o", "api_key_env": "OPENAI_API_KEY_1", "weight": 3 },
+///     { "label": "openai-2", "model": "gpt-4o", "api_key_env": "OPENAI_API_KEY_2" }
+///   ]
+/// }
+/// ```
+pub struct PoolConfig {
+    #[serde(default)]
+    strategy: Strategy,
+    cooldown_secs: Option<u64>,
+    members: Vec<MemberConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents a single member of a pool.
+struct MemberConfig {
+    label: String,
+    model: String,
+    /// Name of the environment variable holding the API key, defaults to the provider's own.
+    api_key_env: Option<String>,
+    #[serde(default = "default_weight")]
+    weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+impl PoolConfig {
+    /// Reads the pool configuration from a JSON file.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Builds the `Balancer` described by this configuration.
+    pub fn build(self) -> anyhow::Result<Balancer> {
+        if self.members.is_empty() {
+            anyhow::bail!("pool configuration has no members");
+        }
+
+        let http = Timeouts::default().http_client();
+        let mut balancer = Balancer::new(self.strategy);
+        if let Some(cooldown) = self.cooldown_secs {
+            balancer = balancer.with_cooldown(Duration::from_secs(cooldown));
+        }
+
+        for member in self.members {
+            let api_key = match member.api_key_env {
+                Some(var) => Some(
+                    std::env::var(&var)
+                        .map_err(|_| anyhow::anyhow!("{var} is not set for {}", member.label))?,
+                ),
+                None => None,
+            };
+            let provider = provider::for_model(&member.model, api_key, &http)?;
+            balancer = balancer.boxed_member(member.label, provider, member.weight);
+        }
+
+        Ok(balancer)
+    }
+}
@@ -17,7 +17,7 @@ async fn main() -> anyhow::Result<()> {
     let var_name = Client::new("gpt-4o");
     let cli = var_name?;
     let response = cli.message().text("Why is the sky red?").send().await?;
-    log::debug!("Response:\n{}", response.text);
+    log::debug!("Response:\n{}", response.text());
 
     Ok(())
 }
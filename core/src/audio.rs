@@ -0,0 +1,111 @@
+use std::fmt;
+
+/// Represents an audio format recognized from the first bytes of an audio file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioFormat {
+    Wav,
+    Mp3,
+    Ogg,
+}
+
+impl AudioFormat {
+    /// Returns the MIME type of the format.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "audio/wav",
+            AudioFormat::Mp3 => "audio/mp3",
+            AudioFormat::Ogg => "audio/ogg",
+        }
+    }
+
+    /// Returns the usual file extension of the format, e.g. `mp3`.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "wav",
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Ogg => "ogg",
+        }
+    }
+
+    /// Returns the format matching a MIME type, e.g. `audio/mpeg`.
+    ///
+    /// # Arguments
+    ///
+    /// * `mime_type` - The MIME type of the audio.
+    ///
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        match mime_type {
+            "audio/wav" | "audio/x-wav" | "audio/wave" => Some(AudioFormat::Wav),
+            "audio/mp3" | "audio/mpeg" => Some(AudioFormat::Mp3),
+            "audio/ogg" => Some(AudioFormat::Ogg),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AudioFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.mime_type())
+    }
+}
+
+/// Recognizes the format of an audio file from its first bytes.
+///
+/// # Arguments
+///
+/// * `data` - The content of the audio file.
+///
+pub fn sniff(data: &[u8]) -> Option<AudioFormat> {
+    match data {
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some(AudioFormat::Wav),
+        [b'O', b'g', b'g', b'S', ..] => Some(AudioFormat::Ogg),
+        // MP3 files start with an ID3 tag or directly with an MPEG frame sync.
+        [b'I', b'D', b'3', ..] => Some(AudioFormat::Mp3),
+        [0xFF, second, ..] if second & 0xE0 == 0xE0 => Some(AudioFormat::Mp3),
+        _ => None,
+    }
+}
+
+/// Recognizes the format of an audio file, failing if it is not WAV, MP3 or Ogg.
+///
+/// # Arguments
+///
+/// * `data` - The content of the audio file.
+///
+/// # Errors
+///
+/// Returns an error if the format is not recognized.
+///
+pub fn detect(data: &[u8]) -> anyhow::Result<AudioFormat> {
+    sniff(data).ok_or_else(|| anyhow::anyhow!("unrecognized audio format"))
+}
+
+#[cfg(test)]
+/// Unit tests for the audio module.
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Tests recognizing audio formats from their first bytes.
+    fn test_sniff() {
+        assert_eq!(
+            sniff(b"RIFF\x24\x08\x00\x00WAVEfmt "),
+            Some(AudioFormat::Wav)
+        );
+        assert_eq!(sniff(b"OggS\x00\x02"), Some(AudioFormat::Ogg));
+        assert_eq!(sniff(b"ID3\x04\x00"), Some(AudioFormat::Mp3));
+        assert_eq!(sniff(&[0xFF, 0xFB, 0x90, 0x64]), Some(AudioFormat::Mp3));
+        assert_eq!(sniff(b"RIFF\x24\x08\x00\x00WEBPVP8 "), None);
+        assert_eq!(sniff(b""), None);
+    }
+
+    #[test]
+    /// Tests mapping MIME types to audio formats.
+    fn test_from_mime_type() {
+        assert_eq!(
+            AudioFormat::from_mime_type("audio/mpeg"),
+            Some(AudioFormat::Mp3)
+        );
+        assert_eq!(AudioFormat::from_mime_type("audio/flac"), None);
+    }
+}
@@ -0,0 +1,490 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// The tag of the messages sent without one.
+pub const DEFAULT_TAG: &str = "default";
+
+/// Number of seconds in a day, daily budgets resetting at midnight UTC.
+const SECS_PER_DAY: u64 = 86_400;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+/// Represents the spending limits of a tag, in US dollars.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aipim::budget::Budget;
+///
+/// let budget = Budget::new().daily(10.0).total(250.0);
+/// ```
+pub struct Budget {
+    #[serde(default)]
+    daily: Option<f64>,
+    #[serde(default)]
+    total: Option<f64>,
+}
+
+impl Budget {
+    /// Creates a new `Budget` instance without any limit.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum spend per day, reset at midnight UTC.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The amount allowed per day, in US dollars.
+    ///
+    pub fn daily(self, limit: f64) -> Self {
+        Self {
+            daily: Some(limit),
+            ..self
+        }
+    }
+
+    /// Sets the maximum spend over the lifetime of the tracker, e.g. for a single job. Spend is
+    /// kept in memory, so this budget starts over when the process restarts.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The amount allowed in total, in US dollars.
+    ///
+    pub fn total(self, limit: f64) -> Self {
+        Self {
+            total: Some(limit),
+            ..self
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq)]
+/// The `Spend` struct represents the amount spent by a tag, in US dollars.
+pub struct Spend {
+    /// The amount spent since the tracker was created.
+    pub total: f64,
+    /// The amount spent since midnight UTC.
+    pub today: f64,
+    /// The number of priced responses.
+    pub requests: u64,
+    /// The estimated cost of the requests in flight, see `CostTracker::check`.
+    pub reserved: f64,
+    /// The day `today` refers to, in days since the Unix epoch.
+    #[serde(skip)]
+    day: u64,
+}
+
+impl Spend {
+    /// Resets the daily spend when the day changed.
+    fn roll(&mut self, day: u64) {
+        if self.day != day {
+            self.day = day;
+            self.today = 0.0;
+        }
+    }
+
+    /// Adds the cost of a response.
+    fn add(&mut self, cost: f64, day: u64) {
+        self.roll(day);
+        self.total += cost;
+        self.today += cost;
+        self.requests += 1;
+    }
+
+    /// Releases a reserved estimate.
+    fn release(&mut self, amount: f64) {
+        self.reserved = (self.reserved - amount).max(0.0);
+    }
+}
+
+/// The spend of every tag, and of the tags sharing the default budget together.
+#[derive(Default)]
+struct Ledger {
+    tags: HashMap<String, Spend>,
+    /// The pooled spend of the tags without a budget of their own.
+    pooled: Spend,
+}
+
+impl Ledger {
+    /// Returns the spend the costs of a tag are added to: its own, and the pooled one if it
+    /// shares the default budget.
+    fn entries(&mut self, tag: &str, pooled: bool) -> impl Iterator<Item = &mut Spend> {
+        let Ledger { tags, pooled: pool } = self;
+        let spend = tags.entry(tag.to_string()).or_default();
+        std::iter::once(spend).chain(pooled.then_some(pool))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The `BudgetPeriod` enum represents the period of an exceeded budget.
+pub enum BudgetPeriod {
+    Daily,
+    Total,
+}
+
+impl fmt::Display for BudgetPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetPeriod::Daily => write!(f, "daily"),
+            BudgetPeriod::Total => write!(f, "total"),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+#[error("{period} budget of ${limit:.2} for {tag} is exhausted (${spent:.2} spent)")]
+/// The `BudgetExceededError` struct represents a message rejected because its tag spent its
+/// budget.
+pub struct BudgetExceededError {
+    pub tag: String,
+    pub period: BudgetPeriod,
+    pub limit: f64,
+    /// The amount spent, including the estimated cost of the requests in flight. For the default
+    /// budget, it is the pooled spend of every tag sharing it.
+    pub spent: f64,
+}
+
+/// The `Reservation` struct holds the estimated cost of a request reserved by
+/// `CostTracker::check`, until the request is settled with its actual cost.
+///
+/// Dropping the reservation without recording a cost, e.g. because the request failed, releases
+/// it.
+#[must_use = "dropping a reservation releases it"]
+pub struct Reservation {
+    ledger: Arc<Mutex<Ledger>>,
+    tag: String,
+    pooled: bool,
+    amount: f64,
+    settled: bool,
+}
+
+impl Reservation {
+    /// Replaces the reserved estimate by the actual cost of the request.
+    ///
+    /// # Arguments
+    ///
+    /// * `cost` - The cost of the response, in US dollars.
+    ///
+    pub fn record(mut self, cost: f64) {
+        self.settled = true;
+        let day = today();
+        let mut ledger = self.ledger.lock().unwrap();
+        for spend in ledger.entries(&self.tag, self.pooled) {
+            spend.release(self.amount);
+            spend.add(cost, day);
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if !self.settled {
+            let mut ledger = self.ledger.lock().unwrap();
+            for spend in ledger.entries(&self.tag, self.pooled) {
+                spend.release(self.amount);
+            }
+        }
+    }
+}
+
+/// The `CostTracker` struct accumulates the cost of the responses received by a `Client` per
+/// tag, and rejects messages once a tag has spent its budget.
+///
+/// Budgets are looked up by tag. Tags without a budget of their own share the default one, which
+/// limits their pooled spend (see `pooled_spend`), so new tags cannot dodge it. Spend is still
+/// reported per tag, every tag getting an entry: callers letting users name tags should restrict
+/// them, as the API server does.
+///
+/// `check` reserves the estimated cost of a request until its actual cost is recorded, so
+/// concurrent requests cannot all pass it. A tag can still overspend when the estimates are too
+/// low, e.g. for models missing from the catalog, which are estimated at no cost.
+///
+/// Spend is kept in memory, so it starts over when the process restarts.
+///
+/// The tracker is cheaply cloneable and clones share the same spend, so the same tracker can be
+/// given to several clients.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aipim::budget::{Budget, CostTracker};
+/// use aipim::client::Client;
+///
+/// let tracker = CostTracker::new()
+///     .budget("nightly-eval", Budget::new().total(25.0))
+///     .default_budget(Budget::new().daily(100.0));
+/// let client = Client::new("gpt-4o")
+///     .unwrap()
+///     .with_cost_tracker(tracker.clone())
+///     .with_tag("nightly-eval");
+/// ```
+#[derive(Clone, Default)]
+pub struct CostTracker {
+    budgets: HashMap<String, Budget>,
+    default: Option<Budget>,
+    ledger: Arc<Mutex<Ledger>>,
+}
+
+impl CostTracker {
+    /// Creates a new `CostTracker` instance without any budget.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the budget of a tag.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - The tag of the messages, e.g. the name of a team or job.
+    /// * `budget` - The budget to enforce.
+    ///
+    pub fn budget(mut self, tag: impl Into<String>, budget: Budget) -> Self {
+        self.budgets.insert(tag.into(), budget);
+        self
+    }
+
+    /// Sets the budget shared by the tags without a specific budget, limiting their pooled spend.
+    ///
+    /// # Arguments
+    ///
+    /// * `budget` - The budget to enforce.
+    ///
+    pub fn default_budget(self, budget: Budget) -> Self {
+        Self {
+            default: Some(budget),
+            ..self
+        }
+    }
+
+    /// Returns `true` if the given tag has a budget of its own, rather than sharing the default
+    /// one.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - The tag of the messages.
+    ///
+    pub fn has_budget(&self, tag: &str) -> bool {
+        self.budgets.contains_key(tag)
+    }
+
+    /// Reserves the estimated cost of a request against the budget of its tag, failing if it
+    /// would exceed it.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - The tag of the message about to be sent.
+    /// * `estimate` - The estimated cost of the request, in US dollars.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `BudgetExceededError` if the tag spent its budget, counting the requests in
+    /// flight, or has too little left for this one.
+    ///
+    pub fn check(&self, tag: &str, estimate: f64) -> Result<Reservation, BudgetExceededError> {
+        self.check_on(tag, estimate, today())
+    }
+
+    /// Adds the cost of a response sent without a reservation to the spend of a tag.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - The tag of the message.
+    /// * `cost` - The cost of the response, in US dollars.
+    ///
+    pub fn record(&self, tag: &str, cost: f64) {
+        self.record_on(tag, cost, today());
+    }
+
+    /// Returns the spend of a tag.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - The tag of the messages.
+    ///
+    pub fn spend(&self, tag: &str) -> Spend {
+        self.report().remove(tag).unwrap_or_default()
+    }
+
+    /// Returns the spend of every tag, ordered by tag.
+    pub fn report(&self) -> BTreeMap<String, Spend> {
+        let day = today();
+        let ledger = self.ledger.lock().unwrap();
+        ledger
+            .tags
+            .iter()
+            .map(|(tag, spend)| {
+                let mut spend = *spend;
+                spend.roll(day);
+                (tag.clone(), spend)
+            })
+            .collect()
+    }
+
+    /// Returns the pooled spend of the tags without a budget of their own, which the default
+    /// budget applies to.
+    pub fn pooled_spend(&self) -> Spend {
+        let mut spend = self.ledger.lock().unwrap().pooled;
+        spend.roll(today());
+        spend
+    }
+
+    fn check_on(
+        &self,
+        tag: &str,
+        estimate: f64,
+        day: u64,
+    ) -> Result<Reservation, BudgetExceededError> {
+        let pooled = !self.has_budget(tag);
+        let mut ledger = self.ledger.lock().unwrap();
+        for spend in ledger.entries(tag, pooled) {
+            spend.roll(day);
+        }
+
+        let (budget, spend) = match self.budgets.get(tag) {
+            Some(budget) => (Some(budget), &ledger.tags[tag]),
+            None => (self.default.as_ref(), &ledger.pooled),
+        };
+        if let Some(budget) = budget {
+            let exceeded = |period, limit: Option<f64>, spent: f64| match limit {
+                Some(limit) if spent >= limit || spent + estimate > limit => {
+                    Err(BudgetExceededError {
+                        tag: tag.to_string(),
+                        period,
+                        limit,
+                        spent,
+                    })
+                }
+                _ => Ok(()),
+            };
+            exceeded(
+                BudgetPeriod::Total,
+                budget.total,
+                spend.total + spend.reserved,
+            )?;
+            exceeded(
+                BudgetPeriod::Daily,
+                budget.daily,
+                spend.today + spend.reserved,
+            )?;
+        }
+
+        for spend in ledger.entries(tag, pooled) {
+            spend.reserved += estimate;
+        }
+        Ok(Reservation {
+            ledger: self.ledger.clone(),
+            tag: tag.to_string(),
+            pooled,
+            amount: estimate,
+            settled: false,
+        })
+    }
+
+    fn record_on(&self, tag: &str, cost: f64, day: u64) {
+        let pooled = !self.has_budget(tag);
+        let mut ledger = self.ledger.lock().unwrap();
+        for spend in ledger.entries(tag, pooled) {
+            spend.add(cost, day);
+        }
+    }
+}
+
+/// Returns the current day, in days since the Unix epoch.
+fn today() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    now.as_secs() / SECS_PER_DAY
+}
+
+#[cfg(test)]
+/// Unit tests for the budget module.
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Tests accumulating spend per tag, with the daily spend reset every day and the spend of
+    /// the tags without a budget also pooled.
+    fn test_record() {
+        let tracker = CostTracker::new()
+            .budget("a", Budget::new())
+            .budget("b", Budget::new());
+        tracker.record_on("a", 1.5, 10);
+        tracker.record_on("a", 0.5, 10);
+        tracker.record_on("b", 1.0, 10);
+        tracker.record_on("a", 1.0, 11);
+        tracker.record_on("c", 2.0, 11);
+        tracker.record_on("d", 2.0, 11);
+
+        let spend = tracker.spend("a");
+        assert_eq!(spend.total, 3.0);
+        assert_eq!(spend.requests, 3);
+        assert_eq!(tracker.spend("b").total, 1.0);
+        assert_eq!(tracker.spend("c").total, 2.0);
+        assert_eq!(tracker.spend(DEFAULT_TAG), Spend::default());
+        assert_eq!(tracker.pooled_spend().total, 4.0);
+        assert_eq!(tracker.report().len(), 4);
+    }
+
+    #[test]
+    /// Tests rejecting a tag once it spent its daily or total budget.
+    fn test_check() {
+        let tracker = CostTracker::new()
+            .budget("job", Budget::new().total(2.0))
+            .default_budget(Budget::new().daily(1.0));
+
+        tracker.record_on("team", 1.0, 10);
+        let err = tracker.check_on("team", 0.0, 10).err().unwrap();
+        assert_eq!(err.period, BudgetPeriod::Daily);
+        assert_eq!(
+            err.to_string(),
+            "daily budget of $1.00 for team is exhausted ($1.00 spent)"
+        );
+        // Tags without a budget share the default one.
+        let err = tracker.check_on("other-team", 0.0, 10).err().unwrap();
+        assert_eq!(err.spent, 1.0);
+        assert_eq!(tracker.spend("other-team").total, 0.0);
+        assert!(tracker.check_on("team", 0.0, 11).is_ok());
+
+        tracker.record_on("job", 1.0, 10);
+        tracker.record_on("job", 1.0, 11);
+        assert!(tracker.check_on("job", 0.0, 11).is_err());
+        assert!(tracker.check_on("job", 0.0, 12).is_err());
+        assert!(CostTracker::new().check_on("job", 0.0, 12).is_ok());
+    }
+
+    #[test]
+    /// Tests reserving the estimated cost of requests in flight until they are settled.
+    fn test_reservation() {
+        let tracker = CostTracker::new().budget("job", Budget::new().total(1.0));
+
+        let first = tracker.check_on("job", 0.5, 10).unwrap();
+        assert_eq!(tracker.spend("job").reserved, 0.5);
+        let err = tracker.check_on("job", 0.75, 10).err().unwrap();
+        assert_eq!(err.spent, 0.5);
+
+        // A failed request releases its reservation.
+        let second = tracker.check_on("job", 0.25, 10).unwrap();
+        drop(second);
+        assert_eq!(tracker.spend("job").reserved, 0.5);
+
+        first.record(0.5);
+        let spend = tracker.spend("job");
+        assert_eq!((spend.total, spend.reserved, spend.requests), (0.5, 0.0, 1));
+        assert!(tracker.check_on("job", 0.5, 10).is_ok());
+
+        // Tags without a budget reserve against the pooled spend too.
+        let other = tracker.check_on("other", 0.25, 10).unwrap();
+        assert_eq!(tracker.pooled_spend().reserved, 0.25);
+        other.record(0.5);
+        let pooled = tracker.pooled_spend();
+        assert_eq!((pooled.total, pooled.reserved), (0.5, 0.0));
+        assert_eq!(tracker.spend("other").total, 0.5);
+    }
+}
@@ -0,0 +1,687 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use log::{debug, warn};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    client::{
+        Audio, AvailableModel, Document, GenerationOptions, Image, Message, Response,
+        Transcription, TranscriptionRequest, Turn,
+    },
+    image::ImageLimits,
+    provider::{AIProvider, EmbeddingProvider, ImageGenerationProvider},
+};
+
+/// A storage backend of cached responses, keyed by `cache_key`.
+///
+/// Stores are called on a blocking thread, so they may block, e.g. on disk or network I/O.
+pub trait CacheStore: Send + Sync {
+    /// Returns the response stored under the given key, unless it expired.
+    fn get(&self, key: &str) -> anyhow::Result<Option<Response>>;
+
+    /// Stores a response under the given key, replacing any previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the message.
+    /// * `response` - The response to the message.
+    /// * `ttl` - How long the response is kept, or `None` to keep it until it is evicted.
+    ///
+    fn put(&self, key: &str, response: &Response, ttl: Option<Duration>) -> anyhow::Result<()>;
+}
+
+/// The parts of a message that determine its response, hashed by `cache_key`.
+#[derive(Serialize)]
+struct CacheKey<'a> {
+    provider: &'a str,
+    model: Option<&'a str>,
+    system: Option<&'a str>,
+    history: Option<&'a [Turn]>,
+    text: &'a str,
+    images: Option<&'a [Image]>,
+    documents: Option<Vec<DocumentKey<'a>>>,
+    audio: Option<&'a [Audio]>,
+    options: &'a GenerationOptions,
+}
+
+/// The parts of a document that determine the response, without its cache breakpoint.
+#[derive(Serialize)]
+struct DocumentKey<'a> {
+    data: &'a str,
+    mime_type: &'a str,
+    url: Option<&'a str>,
+    name: Option<&'a str>,
+}
+
+impl<'a> From<&'a Document> for DocumentKey<'a> {
+    fn from(document: &'a Document) -> Self {
+        Self {
+            data: &document.data,
+            mime_type: &document.mime_type,
+            url: document.url.as_deref(),
+            name: document.name.as_deref(),
+        }
+    }
+}
+
+/// Returns the key of a message: a SHA-256 hash of the provider, model, system prompt, history,
+/// text, attachments and generation options, as a hex string.
+///
+/// Settings that don't change the response, like prompt caching breakpoints or the cost tag,
+/// are left out.
+///
+/// # Arguments
+///
+/// * `provider` - The name of the provider serving the message.
+/// * `model` - The model the message is sent to, if known.
+/// * `message` - The message.
+///
+pub fn cache_key(provider: &str, model: Option<&str>, message: &Message) -> String {
+    let key = CacheKey {
+        provider,
+        model: message.model.as_deref().or(model),
+        system: message.system.as_deref(),
+        history: message.history.as_deref(),
+        text: &message.text,
+        images: message.images.as_deref(),
+        documents: message
+            .documents
+            .as_ref()
+            .map(|documents| documents.iter().map(DocumentKey::from).collect()),
+        audio: message.audio.as_deref(),
+        options: &message.options,
+    };
+    let json = serde_json::to_vec(&key).expect("messages serialize to JSON");
+    format!("{:x}", Sha256::digest(json))
+}
+
+/// The `ResponseCache` struct holds a `CacheStore` and the time to live of its responses.
+///
+/// The cache is cheaply cloneable and clones share the same store.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use aipim::cache::{MemoryCache, ResponseCache};
+/// use aipim::client::Client;
+///
+/// let cache = ResponseCache::new(MemoryCache::new(1_000)).with_ttl(Duration::from_secs(3600));
+/// let client = Client::new("gpt-4o").unwrap().with_cache(cache);
+/// ```
+#[derive(Clone)]
+pub struct ResponseCache {
+    store: Arc<dyn CacheStore>,
+    ttl: Option<Duration>,
+}
+
+impl ResponseCache {
+    /// Creates a new `ResponseCache` instance keeping responses until they are evicted.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The backend storing the responses, e.g. a `MemoryCache`.
+    ///
+    pub fn new(store: impl CacheStore + 'static) -> Self {
+        Self {
+            store: Arc::new(store),
+            ttl: None,
+        }
+    }
+
+    /// Sets how long responses are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - The time to live of the responses.
+    ///
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        Self {
+            ttl: Some(ttl),
+            ..self
+        }
+    }
+
+    /// Reads a response from the store on a blocking thread.
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Response>> {
+        let store = self.store.clone();
+        let key = key.to_string();
+        tokio::task::spawn_blocking(move || store.get(&key)).await?
+    }
+
+    /// Writes a response to the store on a blocking thread.
+    async fn put(&self, key: &str, response: &Response) -> anyhow::Result<()> {
+        let (store, ttl) = (self.store.clone(), self.ttl);
+        let (key, response) = (key.to_string(), response.clone());
+        tokio::task::spawn_blocking(move || store.put(&key, &response, ttl)).await?
+    }
+
+    /// Returns the response cached for a message, flagged as cached and free, logging the
+    /// failures to read the store.
+    pub(crate) async fn lookup(&self, key: &str) -> Option<Response> {
+        match self.get(key).await {
+            Ok(Some(mut response)) => {
+                debug!("Answering message {key} from the cache");
+                response.cached = true;
+                response.cost = Some(0.0);
+                Some(response)
+            }
+            Ok(None) => None,
+            Err(err) => {
+                warn!("Failed to read message {key} from the cache: {err}");
+                None
+            }
+        }
+    }
+
+    /// Caches the response to a message, logging the failures to write the store.
+    pub(crate) async fn store(&self, key: &str, response: &Response) {
+        if let Err(err) = self.put(key, response).await {
+            warn!("Failed to cache the response to message {key}: {err}");
+        }
+    }
+}
+
+/// The `CachedProvider` struct wraps an `AIProvider`, answering the messages it already answered
+/// from a `ResponseCache`.
+///
+/// Cached responses are flagged with `Response::cached` and cost nothing. Messages with
+/// `Message::bypass_cache` set are always sent, and their response replaces the cached one.
+/// Failing to read or write the cache is logged and does not fail the message.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aipim::cache::{CachedProvider, MemoryCache, ResponseCache};
+/// use aipim::client::Client;
+/// use aipim::provider::OpenAI;
+///
+/// let cache = ResponseCache::new(MemoryCache::new(1_000));
+/// let provider = CachedProvider::new(OpenAI::new("sk-...", "gpt-4o"), cache);
+/// let client = Client::from_provider(provider);
+/// ```
+pub struct CachedProvider {
+    provider: Arc<dyn AIProvider>,
+    cache: ResponseCache,
+}
+
+impl CachedProvider {
+    /// Creates a new `CachedProvider` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The provider answering the messages missing from the cache.
+    /// * `cache` - The `ResponseCache` storing the responses.
+    ///
+    pub fn new(provider: impl AIProvider + 'static, cache: ResponseCache) -> Self {
+        Self {
+            provider: Arc::new(provider),
+            cache,
+        }
+    }
+}
+
+#[async_trait]
+impl AIProvider for CachedProvider {
+    async fn send_message(&self, message: Message) -> anyhow::Result<Response> {
+        let key = cache_key(self.provider.name(), self.provider.model(), &message);
+        if !message.bypass_cache {
+            if let Some(response) = self.cache.lookup(&key).await {
+                return Ok(response);
+            }
+        }
+
+        let response = self.provider.send_message(message).await?;
+        self.cache.store(&key, &response).await;
+        Ok(response)
+    }
+
+    fn name(&self) -> &str {
+        self.provider.name()
+    }
+
+    fn model(&self) -> Option<&str> {
+        self.provider.model()
+    }
+
+    fn supports_image_urls(&self) -> bool {
+        self.provider.supports_image_urls()
+    }
+
+    fn image_limits(&self) -> ImageLimits {
+        self.provider.image_limits()
+    }
+
+    fn max_tokens(&self, model: &str) -> Option<usize> {
+        self.provider.max_tokens(model)
+    }
+
+    async fn transcribe(&self, request: TranscriptionRequest) -> anyhow::Result<Transcription> {
+        self.provider.transcribe(request).await
+    }
+
+    async fn count_tokens(&self, message: Message) -> anyhow::Result<usize> {
+        self.provider.count_tokens(message).await
+    }
+
+    async fn list_models(&self) -> anyhow::Result<Vec<AvailableModel>> {
+        self.provider.list_models().await
+    }
+
+    fn as_embedding_provider(&self) -> Option<&dyn EmbeddingProvider> {
+        self.provider.as_embedding_provider()
+    }
+
+    fn as_image_generator(&self) -> Option<&dyn ImageGenerationProvider> {
+        self.provider.as_image_generator()
+    }
+}
+
+/// An entry of the `MemoryCache`.
+struct MemoryEntry {
+    response: Response,
+    expires_at: Option<Instant>,
+    /// The position of the entry in the recency order.
+    used: u64,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    entries: HashMap<String, MemoryEntry>,
+    /// The keys of the entries, least recently used first.
+    recency: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl MemoryState {
+    /// Marks an entry as the most recently used.
+    fn touch(&mut self, key: &str) {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.recency.remove(&entry.used);
+            entry.used = self.clock;
+            self.recency.insert(self.clock, key.to_string());
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.used);
+        }
+    }
+}
+
+/// The `MemoryCache` struct stores responses in memory, evicting the least recently used ones
+/// once it holds `capacity` responses.
+///
+/// # Examples
+///
+/// ```
+/// use aipim::cache::{CacheStore, MemoryCache};
+/// use aipim::client::Response;
+///
+/// let cache = MemoryCache::new(100);
+/// cache.put("key", &Response::new("Hello!"), None).unwrap();
+/// assert_eq!(cache.get("key").unwrap().unwrap().text(), "Hello!");
+/// ```
+pub struct MemoryCache {
+    capacity: usize,
+    state: Mutex<MemoryState>,
+}
+
+impl MemoryCache {
+    /// Creates a new `MemoryCache` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The maximum number of responses kept.
+    ///
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::default(),
+        }
+    }
+}
+
+impl CacheStore for MemoryCache {
+    fn get(&self, key: &str) -> anyhow::Result<Option<Response>> {
+        let mut state = self.state.lock().unwrap();
+        let expired = match state.entries.get(key) {
+            Some(entry) => entry
+                .expires_at
+                .is_some_and(|expires_at| expires_at <= Instant::now()),
+            None => return Ok(None),
+        };
+        if expired {
+            state.remove(key);
+            return Ok(None);
+        }
+
+        state.touch(key);
+        Ok(state.entries.get(key).map(|entry| entry.response.clone()))
+    }
+
+    fn put(&self, key: &str, response: &Response, ttl: Option<Duration>) -> anyhow::Result<()> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.remove(key);
+        while state.entries.len() >= self.capacity {
+            let Some((_, oldest)) = state.recency.pop_first() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+        }
+
+        let entry = MemoryEntry {
+            response: response.clone(),
+            expires_at: ttl.map(|ttl| Instant::now() + ttl),
+            used: 0,
+        };
+        state.entries.insert(key.to_string(), entry);
+        state.touch(key);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteCache;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use std::{
+        path::Path,
+        sync::Mutex,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use rusqlite::{params, Connection, OptionalExtension};
+
+    use super::CacheStore;
+    use crate::client::Response;
+
+    /// The `SqliteCache` struct stores responses on disk in a SQLite database, so they survive
+    /// restarts and can be shared by the processes running on the same machine.
+    ///
+    /// Expired responses are removed whenever a response is stored. Once the database holds
+    /// `capacity` responses, if set, the least recently used ones are evicted too.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::cache::{ResponseCache, SqliteCache};
+    ///
+    /// let store = SqliteCache::open("responses.db").unwrap().with_capacity(10_000);
+    /// let cache = ResponseCache::new(store);
+    /// ```
+    pub struct SqliteCache {
+        pub(super) connection: Mutex<Connection>,
+        capacity: Option<usize>,
+    }
+
+    impl SqliteCache {
+        /// Opens the database at the given path, creating it if needed.
+        ///
+        /// # Arguments
+        ///
+        /// * `path` - The path of the database file.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if the database cannot be opened or initialized.
+        ///
+        pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+            let connection = Connection::open(path)?;
+            connection.execute_batch(
+                "CREATE TABLE IF NOT EXISTS responses (
+                    key TEXT PRIMARY KEY,
+                    response TEXT NOT NULL,
+                    expires_at INTEGER,
+                    used INTEGER NOT NULL DEFAULT 0
+                );
+                CREATE INDEX IF NOT EXISTS responses_used ON responses (used);",
+            )?;
+            Ok(Self {
+                connection: Mutex::new(connection),
+                capacity: None,
+            })
+        }
+
+        /// Sets the maximum number of responses kept, evicting the least recently used ones
+        /// beyond it.
+        ///
+        /// # Arguments
+        ///
+        /// * `capacity` - The maximum number of responses.
+        ///
+        pub fn with_capacity(self, capacity: usize) -> Self {
+            Self {
+                capacity: Some(capacity),
+                ..self
+            }
+        }
+    }
+
+    /// The next position in the recency order, increasing with every read and write.
+    const NEXT_USED: &str = "(SELECT COALESCE(MAX(used), 0) + 1 FROM responses)";
+
+    /// Returns the current time, in seconds since the Unix epoch.
+    fn now() -> i64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        now.as_secs() as i64
+    }
+
+    impl CacheStore for SqliteCache {
+        fn get(&self, key: &str) -> anyhow::Result<Option<Response>> {
+            let connection = self.connection.lock().unwrap();
+            let row = connection
+                .query_row(
+                    "SELECT response, expires_at FROM responses WHERE key = ?1",
+                    params![key],
+                    |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<i64>>(1)?)),
+                )
+                .optional()?;
+
+            match row {
+                Some((_, Some(expires_at))) if expires_at <= now() => {
+                    connection.execute("DELETE FROM responses WHERE key = ?1", params![key])?;
+                    Ok(None)
+                }
+                Some((response, _)) => {
+                    connection.execute(
+                        &format!("UPDATE responses SET used = {NEXT_USED} WHERE key = ?1"),
+                        params![key],
+                    )?;
+                    Ok(Some(serde_json::from_str(&response)?))
+                }
+                None => Ok(None),
+            }
+        }
+
+        fn put(&self, key: &str, response: &Response, ttl: Option<Duration>) -> anyhow::Result<()> {
+            if self.capacity == Some(0) {
+                return Ok(());
+            }
+
+            let now = now();
+            let expires_at = ttl.map(|ttl| now.saturating_add(ttl.as_secs() as i64));
+            let mut connection = self.connection.lock().unwrap();
+            let transaction = connection.transaction()?;
+            transaction.execute("DELETE FROM responses WHERE expires_at <= ?1", params![now])?;
+            transaction.execute(
+                &format!(
+                    "INSERT OR REPLACE INTO responses (key, response, expires_at, used) \
+                     VALUES (?1, ?2, ?3, {NEXT_USED})"
+                ),
+                params![key, serde_json::to_string(response)?, expires_at],
+            )?;
+            if let Some(capacity) = self.capacity {
+                transaction.execute(
+                    "DELETE FROM responses WHERE key IN \
+                     (SELECT key FROM responses ORDER BY used DESC LIMIT -1 OFFSET ?1)",
+                    params![capacity as i64],
+                )?;
+            }
+            transaction.commit()?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+/// Unit tests for the cache module.
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// A provider counting the messages it answers.
+    #[derive(Default)]
+    struct Counting {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl AIProvider for Counting {
+        async fn send_message(&self, message: Message) -> anyhow::Result<Response> {
+            let calls = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(Response::new(format!("{} #{calls}", message.text)))
+        }
+    }
+
+    fn message(text: &str) -> Message {
+        Message {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    /// Tests that keys depend on what changes the response, and only on it.
+    fn test_cache_key() {
+        let key = cache_key("openai", Some("gpt-4o"), &message("Hi"));
+        assert_eq!(key.len(), 64);
+        assert_eq!(key, cache_key("openai", Some("gpt-4o"), &message("Hi")));
+        assert_ne!(key, cache_key("openai", Some("gpt-4o"), &message("Hello")));
+        assert_ne!(key, cache_key("openai", Some("gpt-4"), &message("Hi")));
+
+        let mut tagged = message("Hi");
+        tagged.tag = Some("eval".to_string());
+        assert_eq!(key, cache_key("openai", Some("gpt-4o"), &tagged));
+
+        let mut cached = message("Hi");
+        cached.documents = Some(vec![Document {
+            data: "aGk=".to_string(),
+            mime_type: "text/plain".to_string(),
+            url: None,
+            name: None,
+            cache: false,
+        }]);
+        let document_key = cache_key("openai", Some("gpt-4o"), &cached);
+        assert_ne!(key, document_key);
+        cached.documents.as_mut().unwrap()[0].cache = true;
+        assert_eq!(document_key, cache_key("openai", Some("gpt-4o"), &cached));
+
+        let mut sampled = message("Hi");
+        sampled.options = GenerationOptions::new().n(2);
+        assert_ne!(key, cache_key("openai", Some("gpt-4o"), &sampled));
+    }
+
+    #[test]
+    /// Tests evicting the least recently used responses and expiring them.
+    fn test_memory_cache() {
+        let cache = MemoryCache::new(2);
+        cache.put("a", &Response::new("a"), None).unwrap();
+        cache.put("b", &Response::new("b"), None).unwrap();
+        assert!(cache.get("a").unwrap().is_some());
+        cache.put("c", &Response::new("c"), None).unwrap();
+        assert!(cache.get("b").unwrap().is_none());
+        assert!(cache.get("a").unwrap().is_some());
+        assert!(cache.get("c").unwrap().is_some());
+
+        cache
+            .put("a", &Response::new("a"), Some(Duration::ZERO))
+            .unwrap();
+        assert!(cache.get("a").unwrap().is_none());
+    }
+
+    #[tokio::test]
+    /// Tests answering repeated messages from the cache, unless bypassed.
+    async fn test_cached_provider() {
+        let provider = CachedProvider::new(
+            Counting::default(),
+            ResponseCache::new(MemoryCache::new(10)),
+        );
+
+        let response = provider.send_message(message("Hi")).await.unwrap();
+        assert_eq!(response.text(), "Hi #1");
+        assert!(!response.cached);
+
+        let response = provider.send_message(message("Hi")).await.unwrap();
+        assert_eq!(response.text(), "Hi #1");
+        assert!(response.cached);
+        assert_eq!(response.cost, Some(0.0));
+
+        let mut bypass = message("Hi");
+        bypass.bypass_cache = true;
+        let response = provider.send_message(bypass).await.unwrap();
+        assert_eq!(response.text(), "Hi #2");
+        let response = provider.send_message(message("Hi")).await.unwrap();
+        assert_eq!(response.text(), "Hi #2");
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    /// Tests storing, expiring and replacing responses in SQLite.
+    fn test_sqlite_cache() {
+        let cache = SqliteCache::open(":memory:").unwrap();
+        assert!(cache.get("a").unwrap().is_none());
+
+        cache.put("a", &Response::new("a"), None).unwrap();
+        cache.put("a", &Response::new("b"), None).unwrap();
+        assert_eq!(cache.get("a").unwrap().unwrap().text(), "b");
+
+        cache
+            .put("c", &Response::new("c"), Some(Duration::ZERO))
+            .unwrap();
+        assert!(cache.get("c").unwrap().is_none());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    /// Tests purging expired responses and evicting the least recently used ones on writes.
+    fn test_sqlite_cache_capacity() {
+        let cache = SqliteCache::open(":memory:").unwrap().with_capacity(2);
+        let count = |cache: &SqliteCache| -> i64 {
+            let connection = cache.connection.lock().unwrap();
+            connection
+                .query_row("SELECT COUNT(*) FROM responses", [], |row| row.get(0))
+                .unwrap()
+        };
+
+        cache
+            .put("expired", &Response::new("expired"), Some(Duration::ZERO))
+            .unwrap();
+        cache.put("a", &Response::new("a"), None).unwrap();
+        assert_eq!(count(&cache), 1);
+
+        cache.put("b", &Response::new("b"), None).unwrap();
+        assert!(cache.get("a").unwrap().is_some());
+        cache.put("c", &Response::new("c"), None).unwrap();
+        assert_eq!(count(&cache), 2);
+        assert!(cache.get("b").unwrap().is_none());
+        assert!(cache.get("a").unwrap().is_some());
+    }
+}
@@ -1,12 +1,39 @@
-use std::path::Path;
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use base64::{engine::general_purpose, Engine as _};
+use futures_util::future;
+use log::{debug, warn};
 use serde::{Deserialize, Serialize};
+pub use tokio_util::sync::CancellationToken;
 
-use crate::provider::{AIProvider, Anthropic, Google, OpenAI};
+use crate::{
+    audio,
+    budget::{self, CostTracker},
+    cache::{self, ResponseCache},
+    document, image,
+    limiter::{self, RateLimiter},
+    models::{self, ModelInfo},
+    provider::{self, AIProvider, HarmBlockThreshold, HarmCategory, SafetySetting},
+    tokens,
+};
+
+/// The model used to transcribe audio when none is set.
+const DEFAULT_TRANSCRIPTION_MODEL: &str = "whisper-1";
 
 /// The `Client` struct is responsible for interacting with different AI providers.
 ///
+/// Clients are cheap to clone: clones share the same provider, HTTP connection pool and rate
+/// limiter, so a single client can be created upfront and handed to every task.
+///
+/// Messages may override the model of the client. When the override belongs to another vendor
+/// (e.g. `claude-3-opus-20240229` on a client created for `gpt-4o`), the message is sent through
+/// a provider for that vendor, configured from its environment variable.
+///
 /// # Examples
 ///
 /// ```ingore
@@ -16,19 +43,26 @@ use crate::provider::{AIProvider, Anthropic, Google, OpenAI};
 /// async fn main() -> anyhow::Result<()> {
 ///     dotenv::dotenv().ok();
 ///
-///     let client = Client::new("gpt-4o");
+///     let client = Client::new("gpt-4o")?;
 ///     let response = client.message().text("Hello, world!").send().await?;
-///     println!("Response: {}", response.text);
+///     println!("Response: {}", response.text());
 ///     Ok(())
 /// }
 /// ```
+#[derive(Clone)]
 pub struct Client {
-    provider: Box<dyn AIProvider>,
+    provider: Arc<dyn AIProvider>,
+    limiter: Option<RateLimiter>,
+    preprocess_images: bool,
+    inline_image_urls: bool,
+    context_overflow: ContextOverflow,
+    costs: Option<CostTracker>,
+    tag: Option<String>,
+    cache: Option<ResponseCache>,
+    http: reqwest::Client,
+    vendors: Arc<Mutex<HashMap<&'static str, Arc<dyn AIProvider>>>>,
 }
 
-unsafe impl Send for Client {}
-unsafe impl Sync for Client {}
-
 impl Client {
     /// Creates a new `Client` instance based on the provided model.
     ///
@@ -48,263 +82,3243 @@ impl Client {
     /// let client = Client::new("gpt-3.5-turbo").unwrap();
     /// ```
     pub fn new(model: &str) -> anyhow::Result<Self> {
-        if model.starts_with("gpt") {
-            return Ok(Self {
-                provider: Box::new(OpenAI::default().with_model(model)),
-            });
-        }
-
-        if model.starts_with("claude") {
-            return Ok(Self {
-                provider: Box::new(Anthropic::default().with_model(model)),
-            });
-        }
+        Self::with_timeouts(model, Timeouts::default())
+    }
 
-        if model.starts_with("gemini") {
-            return Ok(Self {
-                provider: Box::new(Google::default().with_model(model)),
-            });
-        }
+    /// Creates a new `Client` instance based on the provided model, with custom HTTP timeouts.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - A string slice that holds the name of the model.
+    /// * `timeouts` - The timeouts applied to every request sent by this client.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the model is unsupported.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use aipim::client::{Client, Timeouts};
+    ///
+    /// let timeouts = Timeouts::default().total(Duration::from_secs(60));
+    /// let client = Client::with_timeouts("gpt-4o", timeouts).unwrap();
+    /// ```
+    pub fn with_timeouts(model: &str, timeouts: Timeouts) -> anyhow::Result<Self> {
+        Self::from_http_client(model, timeouts.http_client())
+    }
 
-        Err(anyhow::anyhow!("unsupported model: {model}"))
+    /// Creates a new `Client` instance based on the provided model, sending its requests with the
+    /// given HTTP client.
+    ///
+    /// Clients of different models can share an HTTP client, and with it their connection pool.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - A string slice that holds the name of the model.
+    /// * `http` - The HTTP client, usually built with `Timeouts::http_client`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the model is unsupported.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::client::{Client, Timeouts};
+    ///
+    /// let http = Timeouts::default().http_client();
+    /// let gpt = Client::from_http_client("gpt-4o", http.clone()).unwrap();
+    /// let claude = Client::from_http_client("claude-3-5-sonnet-latest", http).unwrap();
+    /// ```
+    pub fn from_http_client(model: &str, http: reqwest::Client) -> anyhow::Result<Self> {
+        Ok(Self {
+            provider: provider::for_model(model, None, &http)?.into(),
+            limiter: None,
+            preprocess_images: false,
+            inline_image_urls: false,
+            context_overflow: ContextOverflow::default(),
+            costs: None,
+            tag: None,
+            cache: None,
+            http,
+            vendors: Arc::default(),
+        })
     }
 
-    /// Returns a `MessageBuilder` to construct a message.
+    /// Creates a new `Client` instance backed by the given provider.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The provider that serves the messages, e.g. a `Balancer`.
     ///
     /// # Examples
     ///
     /// ```no_run
     /// use aipim::client::Client;
+    /// use aipim::provider::OpenAI;
     ///
-    /// let client = Client::new("gpt-3.5-turbo").unwrap();
-    /// let builder = client.message();
+    /// let client = Client::from_provider(OpenAI::new("sk-...", "gpt-4o"));
     /// ```
-    pub fn message(self) -> MessageBuilder {
-        MessageBuilder::new(self)
+    pub fn from_provider(provider: impl AIProvider + 'static) -> Self {
+        Self {
+            provider: Arc::new(provider),
+            limiter: None,
+            preprocess_images: false,
+            inline_image_urls: false,
+            context_overflow: ContextOverflow::default(),
+            costs: None,
+            tag: None,
+            cache: None,
+            http: Timeouts::default().http_client(),
+            vendors: Arc::default(),
+        }
     }
 
-    pub async fn send_message(&self, message: Message) -> anyhow::Result<Response> {
-        self.provider.send_message(message).await
+    /// Sets the rate limiter applied to the messages sent by this client.
+    ///
+    /// # Arguments
+    ///
+    /// * `limiter` - A `RateLimiter` instance, possibly shared with other clients.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::client::Client;
+    /// use aipim::limiter::{RateLimit, RateLimiter};
+    ///
+    /// let limiter = RateLimiter::new()
+    ///     .default_limit(RateLimit::new().requests_per_minute(60))
+    ///     .max_concurrency(4);
+    /// let client = Client::new("gpt-4o").unwrap().with_rate_limiter(limiter);
+    /// ```
+    pub fn with_rate_limiter(self, limiter: RateLimiter) -> Self {
+        Self {
+            limiter: Some(limiter),
+            ..self
+        }
     }
-}
-
-/// The `MessageBuilder` struct is used to build messages to be sent to the AI provider.
-///
-/// # Examples
-///
-/// ```no_run
-/// use aipim::client::{Client, MessageBuilder};
-///
-/// let client = Client::new("gpt-3.5-turbo").unwrap();
-/// let builder = client.message().text("Hello, world!");
-/// ```
-pub struct MessageBuilder {
-    client: Client,
-    text: Option<String>,
-    images: Vec<Image>,
-    model: Option<String>,
-}
 
-impl MessageBuilder {
-    /// Creates a new `MessageBuilder` instance.
+    /// Sets what happens to messages exceeding the context window of their model, which are sent
+    /// as is by default.
     ///
     /// # Arguments
     ///
-    /// * `client` - A `Client` instance.
+    /// * `overflow` - The `ContextOverflow` policy applied to every message.
     ///
     /// # Examples
     ///
     /// ```no_run
-    /// use aipim::client::{Client, MessageBuilder};
+    /// use aipim::client::{Client, ContextOverflow};
     ///
-    /// let client = Client::new("gpt-3.5-turbo").unwrap();
-    /// let builder = MessageBuilder::new(client);
+    /// let client = Client::new("gpt-4o")
+    ///     .unwrap()
+    ///     .with_context_overflow(ContextOverflow::Truncate);
     /// ```
-    pub fn new(client: Client) -> Self {
+    pub fn with_context_overflow(self, overflow: ContextOverflow) -> Self {
         Self {
-            client,
-            text: None,
-            images: Vec::new(),
-            model: None,
+            context_overflow: overflow,
+            ..self
         }
     }
 
-    /// Sets the text for the message.
+    /// Sets the tracker accumulating the cost of the responses, and enforcing the budgets of their
+    /// tags.
+    ///
+    /// The estimated cost of each message is reserved before sending it, see `CostTracker::check`.
+    /// Messages of tags that spent their budget fail with a `budget::BudgetExceededError`.
     ///
     /// # Arguments
     ///
-    /// * `text` - The text content of the message.
+    /// * `tracker` - The `CostTracker`, which may be shared with other clients.
     ///
     /// # Examples
     ///
     /// ```no_run
-    /// use aipim::client::{Client, MessageBuilder};
+    /// use aipim::budget::{Budget, CostTracker};
+    /// use aipim::client::Client;
     ///
-    /// let client = Client::new("gpt-3.5-turbo").unwrap();
-    /// let builder = client.message().text("Hello, world!");
+    /// let tracker = CostTracker::new().default_budget(Budget::new().daily(5.0));
+    /// let client = Client::new("gpt-4o").unwrap().with_cost_tracker(tracker);
     /// ```
-    #[allow(unused)]
-    pub fn text(mut self, text: impl Into<String>) -> Self {
-        self.text = Some(text.into());
-        self
+    pub fn with_cost_tracker(self, tracker: CostTracker) -> Self {
+        Self {
+            costs: Some(tracker),
+            ..self
+        }
     }
 
-    /// Sets the text for the message from a prompt file.
+    /// Sets the tag the spend of messages without one is accounted to, `budget::DEFAULT_TAG` by
+    /// default.
     ///
     /// # Arguments
     ///
-    /// * `prompt` - The name of the prompt file (without extension).
+    /// * `tag` - The tag, e.g. the name of a team or job.
+    ///
+    pub fn with_tag(self, tag: impl Into<String>) -> Self {
+        Self {
+            tag: Some(tag.into()),
+            ..self
+        }
+    }
+
+    /// Answers the messages the client already answered from a cache, see `CachedProvider`.
+    ///
+    /// The cache is looked up before reserving the budget of the message and waiting for the
+    /// rate limiter, so cached responses are served even once the budget is spent.
+    ///
+    /// # Arguments
+    ///
+    /// * `cache` - The `ResponseCache`, which may be shared with other clients.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::cache::{MemoryCache, ResponseCache};
+    /// use aipim::client::Client;
+    ///
+    /// let cache = ResponseCache::new(MemoryCache::new(10_000));
+    /// let client = Client::new("gpt-4o").unwrap().with_cache(cache);
+    /// ```
+    pub fn with_cache(self, cache: ResponseCache) -> Self {
+        Self {
+            cache: Some(cache),
+            ..self
+        }
+    }
+
+    /// Lists the models available to the client, with their catalog entry when they are known.
+    ///
+    /// Models are listed concurrently by the provider of the client and, like model overrides,
+    /// by the other vendors whose API key is set in their environment variable. Providers failing
+    /// to list their models are skipped.
     ///
     /// # Errors
     ///
-    /// Returns an error if the prompt file cannot be read.
+    /// Returns an error if no provider can list its models.
     ///
     /// # Examples
     ///
-    /// ```ingore
-    /// use aipim::client::{Client, MessageBuilder};
+    /// ```no_run
+    /// use aipim::client::Client;
     ///
-    /// let client = Client::new("gpt-3.5-turbo").unwrap();
-    /// let builder = client.message().prompt("greeting").unwrap();
+    /// # async fn run() -> anyhow::Result<()> {
+    /// let client = Client::new("gpt-4o")?;
+    /// for model in client.list_models().await? {
+    ///     println!("{} ({})", model.id, model.provider);
+    /// }
+    /// # Ok(())
+    /// # }
     /// ```
-    pub fn prompt(mut self, prompt: impl Into<String>) -> anyhow::Result<Self> {
-        let prompt_path = std::env::var("PROMPT_PATH")?;
-        let prompt_file = Path::new(&prompt_path).join(format!("{}.txt", prompt.into()));
-        let prompt = std::fs::read_to_string(prompt_file)?;
+    pub async fn list_models(&self) -> anyhow::Result<Vec<AvailableModel>> {
+        let own = self.provider.name();
+        let mut providers = vec![self.provider.clone()];
+        if provider::VENDORS.contains(&own) {
+            for &vendor in provider::VENDORS.iter().filter(|&&vendor| vendor != own) {
+                let model = models::for_provider(vendor)
+                    .next()
+                    .map_or("", |info| info.name);
+                match self.vendor_provider(vendor, model) {
+                    Ok(provider) => providers.push(provider),
+                    Err(err) => debug!("Not listing the models of {vendor}: {err}"),
+                }
+            }
+        }
 
-        self.text = Some(prompt);
-        Ok(self)
+        let listed =
+            future::join_all(providers.iter().map(|provider| provider.list_models())).await;
+        let mut models = Vec::new();
+        let mut last_error = None;
+        for (provider, listed) in providers.iter().zip(listed) {
+            match listed {
+                Ok(listed) => models.extend(listed),
+                Err(err) => {
+                    warn!("Failed to list the models of {}: {err}", provider.name());
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        match last_error {
+            Some(err) if models.is_empty() => Err(err),
+            _ => Ok(dedup_models(models)),
+        }
     }
 
-    /// Adds an image to the message.
+    /// Returns the catalog entry of the model served by the client, if it is known.
     ///
-    /// # Arguments
+    /// # Examples
     ///
-    /// * `data` - The image data as a byte vector.
-    /// * `mime_type` - The MIME type of the image.
+    /// ```no_run
+    /// use aipim::client::Client;
+    ///
+    /// let client = Client::new("gpt-4o").unwrap();
+    /// let info = client.model_info().unwrap();
+    /// println!("{} accepts images: {}", info.name, info.capabilities.vision);
+    /// ```
+    pub fn model_info(&self) -> Option<&'static ModelInfo> {
+        self.provider.model().and_then(models::info)
+    }
+
+    /// Returns a `MessageBuilder` to construct a message.
     ///
     /// # Examples
     ///
     /// ```no_run
-    /// use aipim::client::{Client, MessageBuilder};
+    /// use aipim::client::Client;
     ///
     /// let client = Client::new("gpt-3.5-turbo").unwrap();
-    /// let image_data = vec![/* image data */];
-    /// let builder = client.message().image(image_data, "image/png");
+    /// let builder = client.message();
     /// ```
-    pub fn image(mut self, data: Vec<u8>, mime_type: impl Into<String>) -> Self {
-        let data = general_purpose::STANDARD.encode(data);
-        self.images.push(Image {
-            data,
-            mime_type: mime_type.into(),
-        });
-        self
+    pub fn message(&self) -> MessageBuilder {
+        MessageBuilder::new(self.clone())
+    }
+
+    /// Sends a message to the AI provider, waiting for the rate limiter if one is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message to send.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message cannot be sent.
+    ///
+    pub async fn send_message(&self, message: Message) -> anyhow::Result<Response> {
+        let options = DispatchOptions {
+            preprocess_images: self.preprocess_images,
+            inline_image_urls: self.inline_image_urls,
+            context_overflow: self.context_overflow,
+        };
+        self.dispatch(message, options).await
     }
 
-    // We currently support the base64 source type for images, and the image/jpeg, image/png,
-    // image/gif, and image/webp media types.
-    /// Adds an image to the message from a file.
+    /// Counts the input tokens of a message with the provider serving its model.
+    ///
+    /// Anthropic and Gemini count tokens with their API, while OpenAI models are counted locally,
+    /// see `tokens::count_message_tokens`.
     ///
     /// # Arguments
     ///
-    /// * `file` - The path to the image file.
+    /// * `message` - The message to count.
     ///
     /// # Errors
     ///
-    /// Returns an error if the image format is unsupported or the file cannot be read.
+    /// Returns an error if the provider does not support counting tokens or the request fails.
+    ///
+    pub async fn count_tokens(&self, message: Message) -> anyhow::Result<usize> {
+        let provider = self.provider_for(message.model.as_deref())?;
+        provider.count_tokens(message).await
+    }
+
+    /// Enables or disables downloading and inlining the images attached by URL, instead of
+    /// letting the providers fetch them.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether images attached by URL are inlined.
     ///
     /// # Examples
     ///
-    /// ```ingore
-    /// use aipim::client::{Client, MessageBuilder};
+    /// ```no_run
+    /// use aipim::client::Client;
     ///
-    /// let client = Client::new("gpt-3.5-turbo").unwrap();
-    /// let builder = client.message().image_file("path/to/image.png").unwrap();
+    /// let client = Client::new("gpt-4o").unwrap().with_image_url_inlining(true);
     /// ```
-    pub fn image_file(self, file: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
-        let mime_type = match file.as_ref().extension().and_then(|ext| ext.to_str()) {
-            Some("jpg") | Some("jpeg") => "image/jpeg",
-            Some("png") => "image/png",
-            Some("gif") => "image/gif",
-            Some("webp") => "image/webp",
-            _ => return Err(anyhow::anyhow!("unsupported image format")),
-        };
-        let data = std::fs::read(file)?;
-        Ok(self.image(data, mime_type))
+    pub fn with_image_url_inlining(self, enabled: bool) -> Self {
+        Self {
+            inline_image_urls: enabled,
+            ..self
+        }
     }
 
-    /// Sets the model for the message.
+    /// Enables or disables the preprocessing of the images of every message, so they fit the
+    /// limits of the provider they are sent to. See `image::preprocess`.
     ///
     /// # Arguments
     ///
-    /// * `model` - The name of the model.
+    /// * `enabled` - Whether images are preprocessed.
     ///
     /// # Examples
+    ///
     /// ```no_run
-    /// use aipim::client::{Client, MessageBuilder};
+    /// use aipim::client::Client;
     ///
-    /// let client = Client::new("gpt-3.5-turbo").unwrap();
-    /// let builder = client.message().text("Hello, world!").model("gpt-3.5-turbo");
+    /// let client = Client::new("gpt-4o").unwrap().with_image_preprocessing(true);
     /// ```
-    pub fn model(mut self, model: impl Into<String>) -> Self {
-        self.model = Some(model.into());
-        self
+    #[cfg(feature = "preprocess")]
+    pub fn with_image_preprocessing(self, enabled: bool) -> Self {
+        Self {
+            preprocess_images: enabled,
+            ..self
+        }
     }
 
-    /// Sends the message to the AI provider.
+    /// Returns a `TranscriptionBuilder` to transcribe audio to text, with `whisper-1` unless
+    /// another model is set.
     ///
-    /// # Errors
+    /// # Arguments
     ///
-    /// Returns an error if the message cannot be sent.
+    /// * `data` - The audio data (WAV, MP3 or Ogg) as a byte vector.
     ///
-    pub async fn send(self) -> anyhow::Result<Response> {
-        let msg = Message {
-            text: self.text.expect("text is required"),
-            images: Some(self.images),
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::client::Client;
+    ///
+    /// # async fn run() -> anyhow::Result<()> {
+    /// let client = Client::new("gpt-4o")?;
+    /// let audio = std::fs::read("call.mp3")?;
+    /// let transcription = client.transcribe(audio).language("en").send().await?;
+    /// println!("{}", transcription.text);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn transcribe(&self, data: Vec<u8>) -> TranscriptionBuilder {
+        TranscriptionBuilder {
+            client: self.clone(),
+            data,
             model: None,
-        };
-
-        self.client.provider.send_message(msg).await
+            language: None,
+            prompt: None,
+        }
     }
-}
-
-#[derive(Deserialize, Debug)]
-/// The `Message` struct represents a message to be sent to the AI provider.
-pub struct Message {
-    pub text: String,
-    pub images: Option<Vec<Image>>,
-    pub model: Option<String>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-/// The `Image` struct represents an image to be sent to the AI provider.
-pub struct Image {
-    pub data: String,
-    pub mime_type: String,
-}
 
-#[derive(Serialize, Deserialize, Debug)]
-/// The `Response` struct represents a response from the AI provider.
-pub struct Response {
-    pub text: String,
-}
+    /// Returns an `EmbeddingBuilder` to compute the embeddings of the given texts, with the
+    /// default embedding model of the provider unless another model is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `texts` - The texts to embed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::client::Client;
+    ///
+    /// # async fn run() -> anyhow::Result<()> {
+    /// let client = Client::new("gpt-4o")?;
+    /// let embeddings = client
+    ///     .embed(["first document", "second document"])
+    ///     .model("text-embedding-3-small")
+    ///     .send()
+    ///     .await?;
+    /// println!("{} dimensions", embeddings.dimensions);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn embed<I, S>(&self, texts: I) -> EmbeddingBuilder
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        EmbeddingBuilder {
+            client: self.clone(),
+            texts: texts.into_iter().map(Into::into).collect(),
+            model: None,
+            dimensions: None,
+        }
+    }
 
-impl Response {
-    /// Creates a new `Response` instance.
+    /// Returns an `ImageGenerationBuilder` to generate images from a prompt, with the default
+    /// image model of the provider unless another model is set.
     ///
     /// # Arguments
     ///
-    /// * `text` - The text content of the response.
+    /// * `prompt` - The description of the images.
+    /// * `size` - The size of the images. Imagen models use the closest aspect ratio instead.
+    /// * `n` - The number of images to generate.
     ///
     /// # Examples
     ///
     /// ```no_run
-    /// use aipim::client::Response;
+    /// use aipim::client::Client;
+    /// use aipim::image::ImageSize;
     ///
-    /// let response = Response::new("Hello, world!");
-    /// println!("{}", response.text);
+    /// # async fn run() -> anyhow::Result<()> {
+    /// let client = Client::new("gpt-4o")?;
+    /// let images = client
+    ///     .generate_image("A lighthouse at dawn, watercolor", ImageSize::new(1536, 1024), 2)
+    ///     .send()
+    ///     .await?;
+    /// for (index, image) in images.iter().enumerate() {
+    ///     std::fs::write(format!("banner-{index}.png"), &image.data)?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn generate_image(
+        &self,
+        prompt: impl Into<String>,
+        size: image::ImageSize,
+        n: usize,
+    ) -> ImageGenerationBuilder {
+        ImageGenerationBuilder {
+            client: self.clone(),
+            request: ImageGenerationRequest {
+                prompt: prompt.into(),
+                size,
+                n,
+                model: None,
+            },
+        }
+    }
+
+    async fn dispatch(
+        &self,
+        mut message: Message,
+        options: DispatchOptions,
+    ) -> anyhow::Result<Response> {
+        let provider = self.provider_for(message.model.as_deref())?;
+        let model = message
+            .model
+            .as_deref()
+            .or(provider.model())
+            .map(str::to_string);
+        let info = model.as_deref().and_then(models::info);
+        if let Some(info) = info {
+            check_capabilities(&message, info)?;
+        }
+
+        if let Some(mut images) = message.images.take() {
+            let limits = provider.image_limits();
+
+            let inline = options.inline_image_urls
+                || options.preprocess_images
+                || !provider.supports_image_urls();
+            // URLs without a recognizable extension are inlined too, as providers require the
+            // MIME type of the images they fetch.
+            for image in images
+                .iter_mut()
+                .filter(|image| image.url.is_some() && (inline || image.mime_type.is_empty()))
+            {
+                inline_image(image, &limits, options.preprocess_images).await?;
+            }
+
+            #[cfg(feature = "preprocess")]
+            if options.preprocess_images {
+                images = tokio::task::spawn_blocking(move || {
+                    for image in images.iter_mut() {
+                        image::preprocess_image(image, &limits)?;
+                    }
+                    Ok::<_, image::ImageError>(images)
+                })
+                .await??;
+            }
+
+            // Images in formats only preprocessing converts are attached whatever the setting,
+            // and HEIC images are never converted, so they are rejected here, including the
+            // ones passed by URL.
+            if let Some(format) = images
+                .iter()
+                .filter_map(|image| image::ImageFormat::from_mime_type(&image.mime_type))
+                .find(|format| !format.is_supported())
+            {
+                return Err(image::ImageError::unsupported(format).into());
+            }
+
+            limits.check(&images)?;
+            message.images = Some(images);
+        }
+
+        if let (Some(model), Some(info)) = (model.clone(), info) {
+            if options.context_overflow != ContextOverflow::Send {
+                // Counting extracts the text of PDF documents, which is CPU-bound.
+                let overflow = options.context_overflow;
+                let output_tokens = output_tokens(provider.as_ref(), &message, &model, info);
+                message = tokio::task::spawn_blocking(move || {
+                    fit_context_window(&mut message, &model, info, output_tokens, overflow)
+                        .map(|()| message)
+                })
+                .await??;
+            }
+        }
+
+        // Cached responses are free and not sent, so they neither need budget nor wait for the
+        // rate limiter.
+        let cache = self.cache.as_ref().map(|cache| {
+            let key = cache::cache_key(provider.name(), provider.model(), &message);
+            (cache, key)
+        });
+        if let (Some((cache, key)), false) = (&cache, message.bypass_cache) {
+            if let Some(response) = cache.lookup(key).await {
+                return Ok(response);
+            }
+        }
+
+        let tag = message
+            .tag
+            .take()
+            .or_else(|| self.tag.clone())
+            .unwrap_or_else(|| budget::DEFAULT_TAG.to_string());
+        let reservation = match &self.costs {
+            Some(costs) => {
+                let estimate = estimate_cost(provider.as_ref(), &message, model.as_deref(), info);
+                Some(costs.check(&tag, estimate)?)
+            }
+            None => None,
+        };
+
+        let _permit = match &self.limiter {
+            Some(limiter) => {
+                let tokens = limiter::estimate_tokens(&message);
+                let model = message.model.as_deref().or(provider.model());
+                Some(limiter.acquire(provider.name(), model, tokens).await)
+            }
+            None => None,
+        };
+
+        let response = provider
+            .send_message(message)
+            .await?
+            .priced(model.as_deref());
+        if let Some((cache, key)) = &cache {
+            cache.store(key, &response).await;
+        }
+        // Cached responses cost nothing, so their reservation is released.
+        if let (Some(reservation), Some(cost), false) =
+            (reservation, response.cost, response.cached)
+        {
+            reservation.record(cost);
+        }
+        Ok(response)
+    }
+
+    /// Returns the provider serving the given model override.
+    ///
+    /// Overrides for the vendor of the client's own provider, or for models of unknown vendors,
+    /// are sent through the client's provider, which honors `Message::model`.
+    fn provider_for(&self, model: Option<&str>) -> anyhow::Result<Arc<dyn AIProvider>> {
+        let Some(vendor) = model.and_then(provider::vendor) else {
+            return Ok(self.provider.clone());
+        };
+        let own = self.provider.name();
+        if own == vendor || !provider::VENDORS.contains(&own) {
+            // Custom providers, like a `Balancer`, route messages on their own.
+            return Ok(self.provider.clone());
+        }
+
+        self.vendor_provider(vendor, model.unwrap())
+    }
+
+    /// Returns the provider of another vendor, configured from its environment variable on first
+    /// use.
+    fn vendor_provider(
+        &self,
+        vendor: &'static str,
+        model: &str,
+    ) -> anyhow::Result<Arc<dyn AIProvider>> {
+        let mut vendors = self.vendors.lock().unwrap();
+        if let Some(provider) = vendors.get(vendor) {
+            return Ok(provider.clone());
+        }
+
+        let provider: Arc<dyn AIProvider> =
+            provider::for_vendor(vendor, model, None, &self.http)?.into();
+        vendors.insert(vendor, provider.clone());
+        Ok(provider)
+    }
+}
+
+/// Sorts models by provider and name, removing duplicates, e.g. listed by several pool members.
+///
+/// # Arguments
+///
+/// * `models` - The models to sort.
+///
+pub fn dedup_models(mut models: Vec<AvailableModel>) -> Vec<AvailableModel> {
+    models.sort_by(|a, b| (&a.provider, &a.id).cmp(&(&b.provider, &b.id)));
+    models.dedup_by(|a, b| a.provider == b.provider && a.id == b.id);
+    models
+}
+
+/// Downloads an image attached by URL and replaces the URL with its data, see `image::download`.
+///
+/// Images are capped at the size the provider accepts, or at `image::MAX_DOWNLOAD_BYTES` when
+/// they are preprocessed, and shrunk afterwards.
+async fn inline_image(
+    image: &mut Image,
+    limits: &image::ImageLimits,
+    convertible: bool,
+) -> anyhow::Result<()> {
+    let Some(url) = image.url.take() else {
+        return Ok(());
+    };
+
+    let max_bytes = match limits.max_bytes {
+        Some(max_bytes) if !convertible => max_bytes.min(image::MAX_DOWNLOAD_BYTES),
+        _ => image::MAX_DOWNLOAD_BYTES,
+    };
+    let data = image::download(&url, max_bytes).await?;
+    let format = if convertible {
+        image::detect_convertible(&data)?
+    } else {
+        image::detect(&data)?
+    };
+
+    image.data = general_purpose::STANDARD.encode(data);
+    image.mime_type = format.mime_type().to_string();
+    Ok(())
+}
+
+/// Per-message settings applied by the client before sending a message.
+#[derive(Clone, Copy, Debug)]
+struct DispatchOptions {
+    preprocess_images: bool,
+    inline_image_urls: bool,
+    context_overflow: ContextOverflow,
+}
+
+/// Checks that the model of a message supports the features it uses, e.g. images.
+///
+/// Response formats are only checked for OpenAI models, as other providers ignore them.
+fn check_capabilities(message: &Message, info: &ModelInfo) -> anyhow::Result<()> {
+    let has_images = message
+        .images
+        .as_ref()
+        .is_some_and(|images| !images.is_empty());
+    if has_images && !info.capabilities.vision {
+        anyhow::bail!("{} does not accept images", info.name);
+    }
+    if message.options.response_format.is_some()
+        && info.provider == provider::OpenAI::NAME
+        && !info.capabilities.json
+    {
+        anyhow::bail!("{} does not support JSON response formats", info.name);
+    }
+    Ok(())
+}
+
+/// Returns the number of tokens of the context window reserved for the response to a message: the
+/// maximum number of tokens the provider requests, capped by the model, plus the reasoning
+/// budget.
+fn output_tokens(
+    provider: &dyn AIProvider,
+    message: &Message,
+    model: &str,
+    info: &ModelInfo,
+) -> usize {
+    let max_tokens = provider
+        .max_tokens(model)
+        .map_or(info.max_output_tokens, |max_tokens| {
+            max_tokens.min(info.max_output_tokens)
+        });
+    let reasoning = message
+        .options
+        .reasoning
+        .filter(|_| info.capabilities.reasoning)
+        .map_or(0, |reasoning| reasoning.budget_tokens() as usize);
+    max_tokens.saturating_add(reasoning)
+}
+
+/// Estimates the cost of a message before sending it, assuming the longest response. Messages to
+/// models missing from the catalog are estimated at no cost.
+fn estimate_cost(
+    provider: &dyn AIProvider,
+    message: &Message,
+    model: Option<&str>,
+    info: Option<&ModelInfo>,
+) -> f64 {
+    let (Some(model), Some(info)) = (model, info) else {
+        return 0.0;
+    };
+    let usage = Usage {
+        input_tokens: limiter::estimate_tokens(message),
+        output_tokens: output_tokens(provider, message, model, info),
+        ..Default::default()
+    };
+    info.pricing.cost(&usage)
+}
+
+/// Checks that a message and its response fit the context window of its model, counted locally.
+///
+/// With `ContextOverflow::Truncate`, the oldest turns of the history are dropped until it fits.
+fn fit_context_window(
+    message: &mut Message,
+    model: &str,
+    info: &ModelInfo,
+    output_tokens: usize,
+    overflow: ContextOverflow,
+) -> Result<(), ContextWindowError> {
+    let context_window = info.context_window.saturating_sub(output_tokens);
+    let mut tokens = tokens::count_message_tokens(model, message);
+    let history = message.history.get_or_insert_with(Vec::new);
+    while tokens > context_window && overflow == ContextOverflow::Truncate {
+        if history.is_empty() {
+            break;
+        }
+        tokens -= tokens::count_turn_tokens(model, &history.remove(0));
+        // Conversations must start with a user turn.
+        while history
+            .first()
+            .is_some_and(|turn| turn.role == Role::Assistant)
+        {
+            tokens -= tokens::count_turn_tokens(model, &history.remove(0));
+        }
+    }
+
+    if tokens > context_window {
+        return Err(ContextWindowError {
+            model: model.to_string(),
+            tokens,
+            output_tokens,
+            context_window: info.context_window,
+        });
+    }
+    Ok(())
+}
+
+/// The `MessageBuilder` struct is used to build messages to be sent to the AI provider.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aipim::client::{Client, MessageBuilder};
+///
+/// let client = Client::new("gpt-3.5-turbo").unwrap();
+/// let builder = client.message().text("Hello, world!");
+/// ```
+pub struct MessageBuilder {
+    client: Client,
+    system: Option<String>,
+    text: Option<String>,
+    images: Vec<Image>,
+    documents: Vec<Document>,
+    audio: Vec<Audio>,
+    model: Option<String>,
+    options: GenerationOptions,
+    history: Vec<Turn>,
+    tag: Option<String>,
+    bypass_cache: bool,
+    timeout: Option<Duration>,
+    cancellation: Option<CancellationToken>,
+    preprocess_images: bool,
+    inline_image_urls: bool,
+    context_overflow: ContextOverflow,
+    cache_system: bool,
+    cache_text: bool,
+    last_part: Option<LastPart>,
+}
+
+/// The last part added to a `MessageBuilder`, marked as a cache breakpoint by `cache`.
+#[derive(Clone, Copy)]
+enum LastPart {
+    System,
+    Text,
+    Document(usize),
+}
+
+impl MessageBuilder {
+    /// Creates a new `MessageBuilder` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `Client` instance.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::client::{Client, MessageBuilder};
+    ///
+    /// let client = Client::new("gpt-3.5-turbo").unwrap();
+    /// let builder = MessageBuilder::new(client);
+    /// ```
+    pub fn new(client: Client) -> Self {
+        Self {
+            preprocess_images: client.preprocess_images,
+            inline_image_urls: client.inline_image_urls,
+            context_overflow: client.context_overflow,
+            client,
+            system: None,
+            text: None,
+            images: Vec::new(),
+            documents: Vec::new(),
+            audio: Vec::new(),
+            model: None,
+            options: GenerationOptions::default(),
+            history: Vec::new(),
+            tag: None,
+            bypass_cache: false,
+            timeout: None,
+            cancellation: None,
+            cache_system: false,
+            cache_text: false,
+            last_part: None,
+        }
+    }
+
+    /// Sets the system prompt for the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `system` - The instructions given to the model.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::client::{Client, MessageBuilder};
+    ///
+    /// let client = Client::new("gpt-4o").unwrap();
+    /// let builder = client
+    ///     .message()
+    ///     .system("You are a terse assistant.")
+    ///     .text("Hello, world!");
+    /// ```
+    pub fn system(mut self, system: impl Into<String>) -> Self {
+        self.system = Some(system.into());
+        self.last_part = Some(LastPart::System);
+        self
+    }
+
+    /// Marks the system prompt, text or document added last as a cache breakpoint, so providers
+    /// supporting prompt caching (Anthropic) cache the prompt up to and including it. Does
+    /// nothing if none of them was added yet.
+    ///
+    /// The tokens written to and read from the cache are reported in `Response::usage`.
+    ///
+    /// # Examples
+    ///
+    /// ```ingore
+    /// use aipim::client::{Client, MessageBuilder};
+    ///
+    /// let client = Client::new("claude-3-5-sonnet-20240620").unwrap();
+    /// let builder = client
+    ///     .message()
+    ///     .system(std::fs::read_to_string("style-guide.md").unwrap())
+    ///     .cache()
+    ///     .document_file("contract.pdf")
+    ///     .unwrap()
+    ///     .cache()
+    ///     .text("Summarize the termination clauses.");
+    /// ```
+    pub fn cache(mut self) -> Self {
+        match self.last_part {
+            Some(LastPart::System) => self.cache_system = true,
+            Some(LastPart::Text) => self.cache_text = true,
+            Some(LastPart::Document(index)) => self.documents[index].cache = true,
+            None => {}
+        }
+        self
+    }
+
+    /// Sets the text for the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text content of the message.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::client::{Client, MessageBuilder};
+    ///
+    /// let client = Client::new("gpt-3.5-turbo").unwrap();
+    /// let builder = client.message().text("Hello, world!");
+    /// ```
+    #[allow(unused)]
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.text = Some(text.into());
+        self.last_part = Some(LastPart::Text);
+        self
+    }
+
+    /// Sets the text for the message from a prompt file.
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt` - The name of the prompt file (without extension).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the prompt file cannot be read.
+    ///
+    /// # Examples
+    ///
+    /// ```ingore
+    /// use aipim::client::{Client, MessageBuilder};
+    ///
+    /// let client = Client::new("gpt-3.5-turbo").unwrap();
+    /// let builder = client.message().prompt("greeting").unwrap();
+    /// ```
+    pub fn prompt(mut self, prompt: impl Into<String>) -> anyhow::Result<Self> {
+        let prompt_path = std::env::var("PROMPT_PATH")?;
+        let prompt_file = Path::new(&prompt_path).join(format!("{}.txt", prompt.into()));
+        let prompt = std::fs::read_to_string(prompt_file)?;
+
+        self.text = Some(prompt);
+        Ok(self)
+    }
+
+    /// Adds an image to the message, detecting its format from its content.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The image data as a byte vector.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `ImageError` if the format cannot be detected or is not one of JPEG, PNG, GIF
+    /// or WebP, or BMP and TIFF with the `preprocess` feature. HEIC images are rejected with
+    /// `ImageError::Heic`, as they cannot be converted.
+    /// BMP and TIFF images are only sent if images are preprocessed, see `preprocess_images`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::client::{Client, MessageBuilder};
+    ///
+    /// let client = Client::new("gpt-3.5-turbo").unwrap();
+    /// let image_data = vec![/* image data */];
+    /// let builder = client.message().image(image_data).unwrap();
+    /// ```
+    pub fn image(mut self, data: Vec<u8>) -> anyhow::Result<Self> {
+        #[cfg(feature = "preprocess")]
+        let format = image::detect_convertible(&data)?;
+        #[cfg(not(feature = "preprocess"))]
+        let format = image::detect(&data)?;
+        self.images.push(Image {
+            data: general_purpose::STANDARD.encode(data),
+            mime_type: format.mime_type().to_string(),
+            url: None,
+        });
+        Ok(self)
+    }
+
+    /// Adds an image to the message by URL.
+    ///
+    /// The URL is passed through to the providers that support it, otherwise the image is
+    /// downloaded and inlined before sending the message. URLs without an image extension are
+    /// always inlined, as their MIME type is unknown. See `inline_image_urls` to always inline
+    /// images, e.g. so providers never fetch URLs themselves.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL of the image.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::client::{Client, MessageBuilder};
+    ///
+    /// let client = Client::new("gpt-3.5-turbo").unwrap();
+    /// let builder = client.message().image_url("https://example.com/image.png");
+    /// ```
+    pub fn image_url(mut self, url: impl Into<String>) -> Self {
+        let url = url.into();
+        let mime_type = reqwest::Url::parse(&url)
+            .ok()
+            .and_then(|url| {
+                let extension = url.path().rsplit_once('.')?.1.to_string();
+                image::ImageFormat::from_extension(&extension)
+            })
+            .map(|format| format.mime_type().to_string())
+            .unwrap_or_default();
+
+        self.images.push(Image {
+            data: String::new(),
+            mime_type,
+            url: Some(url),
+        });
+        self
+    }
+
+    /// Enables or disables inlining the images attached by URL for this message, overriding the
+    /// setting of the client.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether images attached by URL are downloaded and inlined.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::client::{Client, MessageBuilder};
+    ///
+    /// let client = Client::new("gpt-3.5-turbo").unwrap();
+    /// let builder = client
+    ///     .message()
+    ///     .inline_image_urls(true)
+    ///     .image_url("https://example.com/image.png");
+    /// ```
+    pub fn inline_image_urls(mut self, enabled: bool) -> Self {
+        self.inline_image_urls = enabled;
+        self
+    }
+
+    /// Adds an image to the message from a file, detecting its format from its content.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The path to the image file, with or without an extension.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, or an `ImageError` if the image format is
+    /// unsupported.
+    ///
+    /// # Examples
+    ///
+    /// ```ingore
+    /// use aipim::client::{Client, MessageBuilder};
+    ///
+    /// let client = Client::new("gpt-3.5-turbo").unwrap();
+    /// let builder = client.message().image_file("path/to/image.png").unwrap();
+    /// ```
+    pub fn image_file(self, file: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let data = std::fs::read(file)?;
+        self.image(data)
+    }
+
+    /// Adds a document to the message.
+    ///
+    /// PDF documents are read natively by OpenAI, Anthropic and Gemini models, while their text
+    /// is extracted locally for Ollama and OpenAI-compatible servers.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The document data as a byte vector.
+    /// * `mime_type` - The MIME type of the document, e.g. `application/pdf`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::client::{Client, MessageBuilder};
+    ///
+    /// let client = Client::new("gpt-3.5-turbo").unwrap();
+    /// let pdf_data = vec![/* document data */];
+    /// let builder = client.message().document(pdf_data, "application/pdf");
+    /// ```
+    pub fn document(mut self, data: Vec<u8>, mime_type: impl Into<String>) -> Self {
+        self.documents.push(Document {
+            data: general_purpose::STANDARD.encode(data),
+            mime_type: mime_type.into(),
+            name: None,
+            url: None,
+            cache: false,
+        });
+        self.last_part = Some(LastPart::Document(self.documents.len() - 1));
+        self
+    }
+
+    /// Adds a document to the message by reference, e.g. a file uploaded with
+    /// `Google::upload_file`. Only Gemini and Anthropic models read documents by URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL of the document.
+    /// * `mime_type` - The MIME type of the document, e.g. `application/pdf`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::client::{Client, MessageBuilder};
+    ///
+    /// let client = Client::new("gemini-2.0-flash").unwrap();
+    /// let builder = client.message().document_url(
+    ///     "https://generativelanguage.googleapis.com/v1beta/files/abc-123",
+    ///     "application/pdf",
+    /// );
+    /// ```
+    pub fn document_url(mut self, url: impl Into<String>, mime_type: impl Into<String>) -> Self {
+        self.documents.push(Document {
+            data: String::new(),
+            mime_type: mime_type.into(),
+            name: None,
+            url: Some(url.into()),
+            cache: false,
+        });
+        self.last_part = Some(LastPart::Document(self.documents.len() - 1));
+        self
+    }
+
+    /// Adds a document to the message from a file, detecting its MIME type from its content or
+    /// extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The path to the document file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or its type is unsupported.
+    ///
+    /// # Examples
+    ///
+    /// ```ingore
+    /// use aipim::client::{Client, MessageBuilder};
+    ///
+    /// let client = Client::new("gpt-3.5-turbo").unwrap();
+    /// let builder = client.message().document_file("path/to/fax.pdf").unwrap();
+    /// ```
+    pub fn document_file(mut self, file: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let file = file.as_ref();
+        let data = std::fs::read(file)?;
+        let mime_type = document::mime_type(file, &data)
+            .ok_or_else(|| anyhow::anyhow!("unsupported document format"))?;
+
+        self.documents.push(Document {
+            data: general_purpose::STANDARD.encode(data),
+            mime_type: mime_type.to_string(),
+            name: file
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+            url: None,
+            cache: false,
+        });
+        self.last_part = Some(LastPart::Document(self.documents.len() - 1));
+        Ok(self)
+    }
+
+    /// Adds an audio clip to the message, detecting its format from its content.
+    ///
+    /// Audio is accepted by Gemini models and by OpenAI audio models (e.g. `gpt-4o-audio-preview`),
+    /// the latter only in the WAV and MP3 formats.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The audio data as a byte vector.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the format is not one of WAV, MP3 or Ogg.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::client::{Client, MessageBuilder};
+    ///
+    /// let client = Client::new("gemini-2.0-flash").unwrap();
+    /// let audio_data = vec![/* audio data */];
+    /// let builder = client.message().audio(audio_data).unwrap();
+    /// ```
+    pub fn audio(mut self, data: Vec<u8>) -> anyhow::Result<Self> {
+        let format = audio::detect(&data)?;
+        self.audio.push(Audio {
+            data: general_purpose::STANDARD.encode(data),
+            mime_type: format.mime_type().to_string(),
+        });
+        Ok(self)
+    }
+
+    /// Adds an audio clip to the message from a file, detecting its format from its content.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The path to the audio file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or its format is unsupported.
+    ///
+    /// # Examples
+    ///
+    /// ```ingore
+    /// use aipim::client::{Client, MessageBuilder};
+    ///
+    /// let client = Client::new("gemini-2.0-flash").unwrap();
+    /// let builder = client.message().audio_file("path/to/call.wav").unwrap();
+    /// ```
+    pub fn audio_file(self, file: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let data = std::fs::read(file)?;
+        self.audio(data)
+    }
+
+    /// Sets the model for the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - The name of the model.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use aipim::client::{Client, MessageBuilder};
+    ///
+    /// let client = Client::new("gpt-3.5-turbo").unwrap();
+    /// let builder = client.message().text("Hello, world!").model("gpt-3.5-turbo");
+    /// ```
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Sets the generation options of the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - A `GenerationOptions` instance.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use aipim::client::{Client, GenerationOptions, MessageBuilder};
+    ///
+    /// let client = Client::new("gpt-4o").unwrap();
+    /// let builder = client
+    ///     .message()
+    ///     .text("What is 17 * 23?")
+    ///     .options(GenerationOptions::new().n(5));
+    /// ```
+    pub fn options(mut self, options: GenerationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets how much the model reasons before answering, overriding the reasoning set in the
+    /// options. The thinking blocks are returned in `Response::content`.
+    ///
+    /// # Arguments
+    ///
+    /// * `reasoning` - An effort level or a budget of tokens.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use aipim::client::{Client, MessageBuilder, Reasoning};
+    ///
+    /// let client = Client::new("claude-3-7-sonnet-20250219").unwrap();
+    /// let builder = client
+    ///     .message()
+    ///     .text("Prove that there are infinitely many primes.")
+    ///     .reasoning(Reasoning::BudgetTokens(4096));
+    /// ```
+    pub fn reasoning(mut self, reasoning: Reasoning) -> Self {
+        self.options.reasoning = Some(reasoning);
+        self
+    }
+
+    /// Sets the previous turns of the conversation, oldest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `history` - The previous turns.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use aipim::client::{Client, MessageBuilder, Turn};
+    ///
+    /// # async fn run() -> anyhow::Result<()> {
+    /// let client = Client::new("claude-3-7-sonnet-20250219")?;
+    /// let first = client.message().text("Pick a number.").send().await?;
+    /// let second = client
+    ///     .message()
+    ///     .history([Turn::user("Pick a number."), Turn::assistant(&first)])
+    ///     .text("Double it.")
+    ///     .send()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn history(mut self, history: impl IntoIterator<Item = Turn>) -> Self {
+        self.history = history.into_iter().collect();
+        self
+    }
+
+    /// Enables or disables the preprocessing of the images of this message, overriding the
+    /// setting of the client. It applies to every image of the message when it is sent, see
+    /// `image::preprocess`.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether images are preprocessed.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use aipim::client::{Client, MessageBuilder};
+    ///
+    /// let client = Client::new("gpt-3.5-turbo").unwrap();
+    /// let builder = client
+    ///     .message()
+    ///     .preprocess_images(true)
+    ///     .image_file("path/to/photo.tiff")
+    ///     .unwrap();
+    /// ```
+    #[cfg(feature = "preprocess")]
+    pub fn preprocess_images(mut self, enabled: bool) -> Self {
+        self.preprocess_images = enabled;
+        self
+    }
+
+    /// Sets what happens to the message if it exceeds the context window of its model, overriding
+    /// the policy of the client.
+    ///
+    /// # Arguments
+    ///
+    /// * `overflow` - The `ContextOverflow` policy applied to the message.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use aipim::client::{Client, ContextOverflow};
+    ///
+    /// let client = Client::new("gpt-4o").unwrap();
+    /// let builder = client
+    ///     .message()
+    ///     .text("Hello, world!")
+    ///     .context_overflow(ContextOverflow::Reject);
+    /// ```
+    pub fn context_overflow(mut self, overflow: ContextOverflow) -> Self {
+        self.context_overflow = overflow;
+        self
+    }
+
+    /// Sets the tag the cost of the response is accounted to, overriding the tag of the client.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - The tag, e.g. the name of a team or job.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use aipim::client::Client;
+    ///
+    /// let client = Client::new("gpt-4o").unwrap();
+    /// let builder = client.message().text("Hello, world!").tag("nightly-eval");
+    /// ```
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = Some(tag.into());
+        self
+    }
+
+    /// Sends the message even if its response is cached, replacing the cached response with the
+    /// new one.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use aipim::client::Client;
+    ///
+    /// let client = Client::new("gpt-4o").unwrap();
+    /// let builder = client.message().text("Hello, world!").bypass_cache();
+    /// ```
+    pub fn bypass_cache(mut self) -> Self {
+        self.bypass_cache = true;
+        self
+    }
+
+    /// Sets the total time allowed for sending the message and receiving the response.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The maximum duration of the request.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use aipim::client::{Client, MessageBuilder};
+    ///
+    /// let client = Client::new("gpt-3.5-turbo").unwrap();
+    /// let builder = client.message().text("Hello, world!").timeout(Duration::from_secs(30));
+    /// ```
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets a token that aborts the in-flight request when cancelled.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - A `CancellationToken` instance.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use aipim::client::{CancellationToken, Client, MessageBuilder};
+    ///
+    /// let token = CancellationToken::new();
+    /// let client = Client::new("gpt-3.5-turbo").unwrap();
+    /// let builder = client.message().text("Hello, world!").cancellation(token.clone());
+    /// token.cancel();
+    /// ```
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Sends the message to the AI provider.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message cannot be sent, or a `SendError` if the request timed out
+    /// or was cancelled.
+    ///
+    pub async fn send(mut self) -> anyhow::Result<Response> {
+        let msg = self.take_message();
+
+        let send = async {
+            let options = DispatchOptions {
+                preprocess_images: self.preprocess_images,
+                inline_image_urls: self.inline_image_urls,
+                context_overflow: self.context_overflow,
+            };
+            let dispatch = self.client.dispatch(msg, options);
+            match self.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, dispatch)
+                    .await
+                    .map_err(|_| SendError::Timeout(timeout))?,
+                None => dispatch.await,
+            }
+        };
+
+        // Dropping the request future aborts the underlying HTTP request.
+        match self.cancellation {
+            Some(token) => tokio::select! {
+                biased;
+                _ = token.cancelled() => Err(SendError::Cancelled.into()),
+                response = send => response,
+            },
+            None => send.await,
+        }
+    }
+
+    /// Counts the input tokens of the message, see `Client::count_tokens`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the provider does not support counting tokens or the request fails.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use aipim::client::Client;
+    ///
+    /// # async fn run() -> anyhow::Result<()> {
+    /// let client = Client::new("claude-3-5-sonnet-20240620")?;
+    /// let tokens = client.message().text("Hello, world!").count_tokens().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn count_tokens(mut self) -> anyhow::Result<usize> {
+        let msg = self.take_message();
+        self.client.count_tokens(msg).await
+    }
+
+    /// Moves the parts of the message out of the builder.
+    fn take_message(&mut self) -> Message {
+        Message {
+            system: self.system.take(),
+            cache_system: self.cache_system,
+            text: self.text.take().expect("text is required"),
+            cache_text: self.cache_text,
+            images: Some(std::mem::take(&mut self.images)),
+            documents: Some(std::mem::take(&mut self.documents)),
+            audio: Some(std::mem::take(&mut self.audio)),
+            model: self.model.take(),
+            options: std::mem::take(&mut self.options),
+            history: Some(std::mem::take(&mut self.history)),
+            tag: self.tag.take(),
+            bypass_cache: self.bypass_cache,
+        }
+    }
+}
+
+/// The `TranscriptionBuilder` struct is used to build audio transcription requests.
+///
+/// Transcriptions are sent to an OpenAI-style `/audio/transcriptions` endpoint. When the client
+/// serves another vendor, the request goes through an OpenAI provider configured from its
+/// environment variable.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aipim::client::Client;
+///
+/// let client = Client::new("gpt-4o").unwrap();
+/// let audio = vec![/* audio data */];
+/// let builder = client.transcribe(audio).model("gpt-4o-transcribe").language("pt");
+/// ```
+pub struct TranscriptionBuilder {
+    client: Client,
+    data: Vec<u8>,
+    model: Option<String>,
+    language: Option<String>,
+    prompt: Option<String>,
+}
+
+impl TranscriptionBuilder {
+    /// Sets the transcription model, e.g. `gpt-4o-transcribe`.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - The name of the model.
+    ///
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Sets the language spoken in the audio, as an ISO-639-1 code (e.g. `en`), which improves
+    /// accuracy and latency.
+    ///
+    /// # Arguments
+    ///
+    /// * `language` - The language code.
+    ///
+    pub fn language(mut self, language: impl Into<String>) -> Self {
+        self.language = Some(language.into());
+        self
+    }
+
+    /// Sets a prompt guiding the transcription, e.g. the spelling of product names.
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt` - The text of the prompt.
+    ///
+    pub fn prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = Some(prompt.into());
+        self
+    }
+
+    /// Sends the audio to be transcribed, waiting for the rate limiter of the client if one is
+    /// set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the audio format is unsupported, the provider does not support
+    /// transcription, or the request fails.
+    ///
+    pub async fn send(self) -> anyhow::Result<Transcription> {
+        let format = audio::detect(&self.data)?;
+        let model = self
+            .model
+            .unwrap_or_else(|| DEFAULT_TRANSCRIPTION_MODEL.to_string());
+        let provider = self.client.provider_for(Some(&model))?;
+
+        let request = TranscriptionRequest {
+            audio: Audio {
+                data: general_purpose::STANDARD.encode(self.data),
+                mime_type: format.mime_type().to_string(),
+            },
+            model,
+            language: self.language,
+            prompt: self.prompt,
+        };
+
+        let _permit = match &self.client.limiter {
+            Some(limiter) => Some(
+                limiter
+                    .acquire(provider.name(), Some(&request.model), 0)
+                    .await,
+            ),
+            None => None,
+        };
+
+        provider.transcribe(request).await
+    }
+}
+
+/// The `EmbeddingBuilder` struct is used to build embedding requests.
+///
+/// Large inputs are split into batches the provider accepts, sent one after the other, and the
+/// returned vectors are normalized to unit length.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aipim::client::Client;
+///
+/// let client = Client::new("gemini-2.0-flash").unwrap();
+/// let builder = client.embed(["Hello, world!"]).model("text-embedding-004").dimensions(256);
+/// ```
+pub struct EmbeddingBuilder {
+    client: Client,
+    texts: Vec<String>,
+    model: Option<String>,
+    dimensions: Option<usize>,
+}
+
+impl EmbeddingBuilder {
+    /// Sets the embedding model, e.g. `text-embedding-3-small`.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - The name of the model.
+    ///
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Sets the number of dimensions of the vectors, for models that can shorten them.
+    ///
+    /// # Arguments
+    ///
+    /// * `dimensions` - The number of dimensions.
+    ///
+    pub fn dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    /// Sends the texts to be embedded, waiting for the rate limiter of the client if one is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the provider does not support embeddings or a request fails.
+    ///
+    pub async fn send(self) -> anyhow::Result<Embeddings> {
+        let provider = self.client.provider_for(self.model.as_deref())?;
+        let embedder = provider
+            .as_embedding_provider()
+            .ok_or_else(|| anyhow::anyhow!("{} does not support embeddings", provider.name()))?;
+
+        let mut embeddings = Embeddings::default();
+        for batch in self.texts.chunks(embedder.max_batch_size().max(1)) {
+            let request = EmbeddingRequest {
+                texts: batch.to_vec(),
+                model: self.model.clone(),
+                dimensions: self.dimensions,
+            };
+
+            let _permit = match &self.client.limiter {
+                Some(limiter) => {
+                    let tokens = batch.iter().map(|text| limiter::estimate_text_tokens(text));
+                    let model = self.model.as_deref().or(provider.model());
+                    Some(limiter.acquire(provider.name(), model, tokens.sum()).await)
+                }
+                None => None,
+            };
+
+            embeddings.extend(embedder.embed(request).await?);
+        }
+
+        for vector in embeddings.vectors.iter_mut() {
+            normalize(vector);
+        }
+        Ok(embeddings)
+    }
+}
+
+/// Scales a vector to unit length, leaving zero vectors untouched.
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        for x in vector.iter_mut() {
+            *x /= norm;
+        }
+    }
+}
+
+/// The `ImageGenerationBuilder` struct is used to build image generation requests.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aipim::client::Client;
+/// use aipim::image::ImageSize;
+///
+/// let client = Client::new("gemini-2.0-flash").unwrap();
+/// let builder = client
+///     .generate_image("A lighthouse at dawn", ImageSize::SQUARE, 1)
+///     .model("imagen-3.0-generate-002");
+/// ```
+pub struct ImageGenerationBuilder {
+    client: Client,
+    request: ImageGenerationRequest,
+}
+
+impl ImageGenerationBuilder {
+    /// Sets the image model, e.g. `gpt-image-1` or `imagen-3.0-generate-002`.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - The name of the model.
+    ///
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.request.model = Some(model.into());
+        self
+    }
+
+    /// Sends the prompt to generate the images, waiting for the rate limiter of the client if
+    /// one is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the provider does not support image generation or the request fails.
+    ///
+    pub async fn send(self) -> anyhow::Result<Vec<GeneratedImage>> {
+        let provider = self.client.provider_for(self.request.model.as_deref())?;
+        let generator = provider.as_image_generator().ok_or_else(|| {
+            anyhow::anyhow!("{} does not support image generation", provider.name())
+        })?;
+
+        let _permit = match &self.client.limiter {
+            Some(limiter) => {
+                let tokens = limiter::estimate_text_tokens(&self.request.prompt);
+                let model = self.request.model.as_deref().or(provider.model());
+                Some(limiter.acquire(provider.name(), model, tokens).await)
+            }
+            None => None,
+        };
+
+        generator.generate_image(self.request).await
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The `Timeouts` struct holds the HTTP timeouts applied to the requests of a `Client`.
+///
+/// By default, connecting times out after 10 seconds and reading after 5 minutes without
+/// receiving any data, with no limit on the total duration of a request.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use aipim::client::Timeouts;
+///
+/// let timeouts = Timeouts::default()
+///     .connect(Duration::from_secs(5))
+///     .total(Duration::from_secs(120));
+/// ```
+pub struct Timeouts {
+    pub connect: Option<Duration>,
+    pub read: Option<Duration>,
+    pub total: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Some(Duration::from_secs(10)),
+            read: Some(Duration::from_secs(300)),
+            total: None,
+        }
+    }
+}
+
+impl Timeouts {
+    /// Sets the time allowed to establish a connection.
+    pub fn connect(self, timeout: Duration) -> Self {
+        Self {
+            connect: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Sets the time allowed between two reads of the response.
+    pub fn read(self, timeout: Duration) -> Self {
+        Self {
+            read: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Sets the total time allowed for a request, from connecting to reading the whole response.
+    pub fn total(self, timeout: Duration) -> Self {
+        Self {
+            total: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Builds a `reqwest::Client` applying these timeouts.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the TLS backend cannot be initialized, like `reqwest::Client::new`.
+    ///
+    pub fn http_client(&self) -> reqwest::Client {
+        let mut builder = reqwest::Client::builder();
+        if let Some(timeout) = self.connect {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.read {
+            builder = builder.read_timeout(timeout);
+        }
+        if let Some(timeout) = self.total {
+            builder = builder.timeout(timeout);
+        }
+        builder.build().expect("failed to build HTTP client")
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// The `ContextOverflow` enum controls what happens to messages exceeding the context window of
+/// their model, as counted by `tokens::count_message_tokens`, once the tokens of the response
+/// are reserved.
+pub enum ContextOverflow {
+    /// Sends the message as is, leaving it to the provider to reject it.
+    #[default]
+    Send,
+    /// Rejects the message with a `ContextWindowError` before sending it.
+    Reject,
+    /// Drops the oldest turns of the history until the message fits, and rejects it if it still
+    /// doesn't without any history.
+    Truncate,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error(
+    "message of {tokens} tokens, with {output_tokens} tokens reserved for the response, exceeds \
+     the context window of {model} ({context_window} tokens)"
+)]
+/// The `ContextWindowError` struct represents a message rejected for exceeding the context window
+/// of its model.
+pub struct ContextWindowError {
+    pub model: String,
+    pub tokens: usize,
+    /// The number of tokens reserved for the response, reasoning included.
+    pub output_tokens: usize,
+    pub context_window: usize,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+/// The `SendError` enum represents the ways sending a message can be interrupted.
+pub enum SendError {
+    #[error("request timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("request was cancelled")]
+    Cancelled,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+/// The `Message` struct represents a message to be sent to the AI provider.
+pub struct Message {
+    /// The system prompt, i.e. instructions given to the model.
+    #[serde(default)]
+    pub system: Option<String>,
+    /// Whether the system prompt is a cache breakpoint, see `MessageBuilder::cache`.
+    #[serde(default)]
+    pub cache_system: bool,
+    pub text: String,
+    /// Whether the text is a cache breakpoint, see `MessageBuilder::cache`.
+    #[serde(default)]
+    pub cache_text: bool,
+    pub images: Option<Vec<Image>>,
+    #[serde(default)]
+    pub documents: Option<Vec<Document>>,
+    #[serde(default)]
+    pub audio: Option<Vec<Audio>>,
+    pub model: Option<String>,
+    #[serde(default)]
+    pub options: GenerationOptions,
+    /// The previous turns of the conversation, oldest first.
+    #[serde(default)]
+    pub history: Option<Vec<Turn>>,
+    /// The tag the cost of the response is accounted to, see `Client::with_cost_tracker`.
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Whether the message is sent even if its response is cached, see `Client::with_cache`.
+    #[serde(default)]
+    pub bypass_cache: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+/// The `GenerationOptions` struct holds the settings controlling how a response is generated.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aipim::client::GenerationOptions;
+///
+/// let options = GenerationOptions::new().n(3);
+/// ```
+pub struct GenerationOptions {
+    /// The number of alternative responses to generate, see `Response::candidates`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<usize>,
+    /// How much the model reasons before answering, for models that support it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<Reasoning>,
+    /// The blocking thresholds per harm category, for Gemini models.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub safety_settings: Vec<SafetySetting>,
+    /// The name of the content cached with `Google::create_cached_content`, for Gemini models.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_content: Option<String>,
+    /// The format the model must answer in, for OpenAI models.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    /// The `Response::id` of the previous turn, continuing its conversation with the OpenAI
+    /// Responses API instead of sending the history.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_response_id: Option<String>,
+    /// The number of most likely alternatives returned with the log probability of each token,
+    /// see `Candidate::logprobs`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<usize>,
+}
+
+impl GenerationOptions {
+    /// Creates a new `GenerationOptions` instance with the defaults of the provider.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of alternative responses to generate. Only OpenAI and Gemini models
+    /// generate more than one.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of responses.
+    ///
+    pub fn n(mut self, n: usize) -> Self {
+        self.n = Some(n);
+        self
+    }
+
+    /// Sets how much the model reasons before answering. See `Reasoning`.
+    ///
+    /// # Arguments
+    ///
+    /// * `reasoning` - An effort level or a budget of tokens.
+    ///
+    pub fn reasoning(mut self, reasoning: Reasoning) -> Self {
+        self.reasoning = Some(reasoning);
+        self
+    }
+
+    /// Sets the format the model must answer in, for OpenAI models. Other providers ignore it.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - Any JSON object, or JSON matching a schema.
+    ///
+    pub fn response_format(mut self, format: ResponseFormat) -> Self {
+        self.response_format = Some(format);
+        self
+    }
+
+    /// Continues the conversation of a previous response of the OpenAI Responses API, see
+    /// `OpenAIApi::Responses`. Other providers ignore it.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The `Response::id` of the previous turn.
+    ///
+    pub fn previous_response_id(mut self, id: impl Into<String>) -> Self {
+        self.previous_response_id = Some(id.into());
+        self
+    }
+
+    /// Uses content cached with `Google::create_cached_content` for Gemini models. The message
+    /// has to be sent to the model of the cached content, and its system prompt has to be cached
+    /// with the content rather than set on the message. Other providers ignore it.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the cached content, e.g. `cachedContents/abc-123`.
+    ///
+    pub fn cached_content(mut self, name: impl Into<String>) -> Self {
+        self.cached_content = Some(name.into());
+        self
+    }
+
+    /// Sets the blocking threshold of a harm category for Gemini models, overriding the one set
+    /// on the `Google` provider. Other providers ignore it.
+    ///
+    /// # Arguments
+    ///
+    /// * `category` - The harm category.
+    /// * `threshold` - The probability of harm from which content is blocked.
+    ///
+    pub fn safety_setting(mut self, category: HarmCategory, threshold: HarmBlockThreshold) -> Self {
+        self.safety_settings
+            .retain(|setting| setting.category != category);
+        self.safety_settings
+            .push(SafetySetting::new(category, threshold));
+        self
+    }
+
+    /// Returns the log probability of each generated token with its `top_k` most likely
+    /// alternatives, see `Candidate::logprobs`. Only OpenAI and Gemini models return them.
+    ///
+    /// # Arguments
+    ///
+    /// * `top_k` - The number of alternatives per token, up to 20, or 0 for none.
+    ///
+    pub fn logprobs(mut self, top_k: usize) -> Self {
+        self.logprobs = Some(top_k);
+        self
+    }
+
+    /// Fails if log probabilities are requested from a provider that does not return them.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The name of the provider.
+    ///
+    pub fn check_no_logprobs(&self, provider: &str) -> anyhow::Result<()> {
+        match self.logprobs {
+            Some(_) => Err(anyhow::anyhow!("{provider} does not support logprobs")),
+            None => Ok(()),
+        }
+    }
+
+    /// Fails if more than one response is requested from a provider that generates only one.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The name of the provider.
+    ///
+    pub fn check_single_candidate(&self, provider: &str) -> anyhow::Result<()> {
+        match self.n {
+            Some(n) if n > 1 => Err(anyhow::anyhow!(
+                "{provider} does not support generating {n} candidates"
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// The `ResponseFormat` enum represents the format a model must answer in.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aipim::client::{GenerationOptions, ResponseFormat};
+///
+/// let schema = serde_json::json!({
+///     "type": "object",
+///     "properties": { "sender": { "type": "string" } },
+///     "required": ["sender"],
+///     "additionalProperties": false
+/// });
+/// let options = GenerationOptions::new().response_format(ResponseFormat::json_schema("fax", schema));
+/// ```
+pub enum ResponseFormat {
+    /// Any JSON object.
+    Json,
+    /// JSON matching a schema, strictly if `strict` is set.
+    JsonSchema {
+        name: String,
+        schema: serde_json::Value,
+        #[serde(default)]
+        strict: bool,
+    },
+}
+
+impl ResponseFormat {
+    /// Returns a format strictly matching the given JSON schema.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the schema.
+    /// * `schema` - The JSON schema of the answer.
+    ///
+    pub fn json_schema(name: impl Into<String>, schema: serde_json::Value) -> Self {
+        ResponseFormat::JsonSchema {
+            name: name.into(),
+            schema,
+            strict: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+/// The `Reasoning` enum sets how much a model reasons before answering, in a provider-neutral
+/// way.
+///
+/// OpenAI o-series models take an effort level, while Claude and Gemini 2.5 models take a budget
+/// of thinking tokens. Each form is converted to the other for the providers that need it. Models
+/// without reasoning, see `models::Capabilities::reasoning`, ignore it, and Claude budgets are
+/// raised to the 1,024 tokens it requires.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aipim::client::{GenerationOptions, Reasoning, ReasoningEffort};
+///
+/// let options = GenerationOptions::new().reasoning(Reasoning::Effort(ReasoningEffort::High));
+/// let options = GenerationOptions::new().reasoning(Reasoning::BudgetTokens(4096));
+/// ```
+pub enum Reasoning {
+    Effort(ReasoningEffort),
+    BudgetTokens(u32),
+}
+
+impl Reasoning {
+    /// Returns the effort level, mapping budgets to the closest level.
+    pub fn effort(&self) -> ReasoningEffort {
+        match *self {
+            Reasoning::Effort(effort) => effort,
+            Reasoning::BudgetTokens(budget) if budget <= ReasoningEffort::Low.budget_tokens() => {
+                ReasoningEffort::Low
+            }
+            Reasoning::BudgetTokens(budget)
+                if budget <= ReasoningEffort::Medium.budget_tokens() =>
+            {
+                ReasoningEffort::Medium
+            }
+            Reasoning::BudgetTokens(_) => ReasoningEffort::High,
+        }
+    }
+
+    /// Returns the budget of thinking tokens, mapping effort levels to a typical budget.
+    pub fn budget_tokens(&self) -> u32 {
+        match *self {
+            Reasoning::Effort(effort) => effort.budget_tokens(),
+            Reasoning::BudgetTokens(budget) => budget,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+/// The `ReasoningEffort` enum represents the effort levels of reasoning models.
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+impl ReasoningEffort {
+    /// Returns the budget of thinking tokens matching the effort level.
+    pub fn budget_tokens(&self) -> u32 {
+        match self {
+            ReasoningEffort::Low => 2048,
+            ReasoningEffort::Medium => 8192,
+            ReasoningEffort::High => 24576,
+        }
+    }
+
+    /// Returns the name of the level, e.g. `medium`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReasoningEffort::Low => "low",
+            ReasoningEffort::Medium => "medium",
+            ReasoningEffort::High => "high",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+/// The `Role` enum represents the author of a turn of a conversation.
+pub enum Role {
+    User,
+    Assistant,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// The `Turn` struct represents a previous turn of a conversation, sent before the message.
+///
+/// Assistant turns keep the content blocks of the response, including signed thinking blocks,
+/// which Anthropic requires to be sent back unchanged.
+pub struct Turn {
+    pub role: Role,
+    pub content: Vec<ContentBlock>,
+}
+
+impl Turn {
+    /// Creates a user turn holding the given text.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text of the turn.
+    ///
+    pub fn user(text: impl Into<String>) -> Self {
+        Self {
+            role: Role::User,
+            content: vec![ContentBlock::Text { text: text.into() }],
+        }
+    }
+
+    /// Creates an assistant turn from a response, keeping all its content blocks.
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - The response of the assistant.
+    ///
+    pub fn assistant(response: &Response) -> Self {
+        Self {
+            role: Role::Assistant,
+            content: response.content.clone(),
+        }
+    }
+
+    /// Returns the text of the turn, concatenating all its text blocks.
+    pub fn text(&self) -> String {
+        concat_text(&self.content)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// The `Image` struct represents an image to be sent to the AI provider.
+///
+/// Images hold either base64 encoded `data`, or a `url` that is passed through to the providers
+/// supporting it and downloaded otherwise.
+pub struct Image {
+    #[serde(default)]
+    pub data: String,
+    #[serde(default)]
+    pub mime_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// The `Document` struct represents a document (e.g. a PDF) to be sent to the AI provider.
+///
+/// Documents hold either base64 encoded `data`, or a `url` referencing a file uploaded to the
+/// provider (e.g. with `Google::upload_file`), which is only read by Gemini and Anthropic.
+pub struct Document {
+    #[serde(default)]
+    pub data: String,
+    pub mime_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Whether the document is a cache breakpoint, see `MessageBuilder::cache`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cache: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// The `Audio` struct represents an audio clip (WAV, MP3 or Ogg) to be sent to the AI provider.
+pub struct Audio {
+    pub data: String,
+    pub mime_type: String,
+}
+
+#[derive(Debug, Clone)]
+/// The `TranscriptionRequest` struct represents audio to be transcribed by the AI provider.
+pub struct TranscriptionRequest {
+    pub audio: Audio,
+    pub model: String,
+    pub language: Option<String>,
+    pub prompt: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+/// The `Transcription` struct represents the text transcribed from audio by the AI provider.
+pub struct Transcription {
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+/// The `EmbeddingRequest` struct represents texts to be embedded by the AI provider.
+pub struct EmbeddingRequest {
+    pub texts: Vec<String>,
+    /// The embedding model, or `None` for the default model of the provider.
+    pub model: Option<String>,
+    pub dimensions: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+/// The `Embeddings` struct represents the vectors computed by the AI provider, in the order of
+/// the texts.
+pub struct Embeddings {
+    pub vectors: Vec<Vec<f32>>,
+    pub dimensions: usize,
+    /// The tokens used, when reported by the provider.
+    pub usage: Option<EmbeddingUsage>,
+}
+
+impl Embeddings {
+    /// Creates a new `Embeddings` instance, taking the dimensions from the first vector.
+    ///
+    /// # Arguments
+    ///
+    /// * `vectors` - The vectors, in the order of the texts.
+    /// * `usage` - The tokens used, if reported.
+    ///
+    pub fn new(vectors: Vec<Vec<f32>>, usage: Option<EmbeddingUsage>) -> Self {
+        Self {
+            dimensions: vectors.first().map_or(0, Vec::len),
+            vectors,
+            usage,
+        }
+    }
+
+    /// Appends the vectors of another batch, adding up their usage.
+    fn extend(&mut self, other: Embeddings) {
+        if self.vectors.is_empty() {
+            self.dimensions = other.dimensions;
+        }
+        self.vectors.extend(other.vectors);
+        self.usage = match (self.usage, other.usage) {
+            (Some(usage), Some(other)) => Some(EmbeddingUsage {
+                prompt_tokens: usage.prompt_tokens + other.prompt_tokens,
+            }),
+            (usage, other) => usage.or(other),
+        };
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+/// The `EmbeddingUsage` struct represents the tokens used to compute embeddings.
+pub struct EmbeddingUsage {
+    pub prompt_tokens: usize,
+}
+
+#[derive(Debug, Clone)]
+/// The `ImageGenerationRequest` struct represents a prompt to generate images from.
+pub struct ImageGenerationRequest {
+    pub prompt: String,
+    pub size: image::ImageSize,
+    pub n: usize,
+    /// The image model, or `None` for the default model of the provider.
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+/// The `GeneratedImage` struct represents an image generated by the AI provider.
+pub struct GeneratedImage {
+    pub data: Vec<u8>,
+    pub mime_type: String,
+    /// The prompt as rewritten by the provider before generating the image, if any.
+    pub revised_prompt: Option<String>,
+}
+
+impl GeneratedImage {
+    /// Creates a new `GeneratedImage` instance from base64 encoded data, detecting its MIME type
+    /// from its content when the provider does not report it.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The base64 encoded image data.
+    /// * `mime_type` - The MIME type reported by the provider, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data is not valid base64.
+    ///
+    pub fn from_base64(data: &str, mime_type: Option<String>) -> anyhow::Result<Self> {
+        let data = general_purpose::STANDARD.decode(data)?;
+        let mime_type = mime_type.unwrap_or_else(|| {
+            image::sniff(&data)
+                .unwrap_or(image::ImageFormat::Png)
+                .mime_type()
+                .to_string()
+        });
+
+        Ok(Self {
+            data,
+            mime_type,
+            revised_prompt: None,
+        })
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+/// The `AvailableModel` struct represents a model listed by a provider, see `Client::list_models`.
+pub struct AvailableModel {
+    /// The name of the model, as passed to `MessageBuilder::model`.
+    pub id: String,
+    /// The name of the provider serving the model, e.g. `openai`.
+    pub provider: String,
+    /// The human readable name of the model, if the provider returns one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// The catalog entry of the model, if it is known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub info: Option<ModelInfo>,
+}
+
+impl AvailableModel {
+    /// Creates a new `AvailableModel` instance, looking the model up in the catalog.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The name of the model.
+    /// * `provider` - The name of the provider serving the model.
+    ///
+    pub fn new(id: impl Into<String>, provider: impl Into<String>) -> Self {
+        let id = id.into();
+        Self {
+            info: models::info(&id).copied(),
+            id,
+            provider: provider.into(),
+            display_name: None,
+        }
+    }
+
+    /// Sets the human readable name of the model.
+    ///
+    /// # Arguments
+    ///
+    /// * `display_name` - The name shown to users, e.g. `Claude 3.5 Sonnet`.
+    ///
+    pub fn with_display_name(mut self, display_name: impl Into<String>) -> Self {
+        self.display_name = Some(display_name.into());
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// The `Response` struct represents a response from the AI provider.
+///
+/// `content` holds the blocks of the first candidate in order, while `candidates` returns every
+/// alternative generated when `GenerationOptions::n` is set.
+pub struct Response {
+    /// The identifier given to the response by the provider, when it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub content: Vec<ContentBlock>,
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    /// The safety ratings of the prompt, when reported by the provider.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prompt_safety_ratings: Vec<SafetyRating>,
+    /// The tokens used, when reported by the provider.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    /// The cost of the response in US dollars, when its usage and the pricing of its model are
+    /// known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    /// Whether the response was answered from the cache, see `Client::with_cache`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// The `Usage` struct represents the tokens used by a message.
+pub struct Usage {
+    /// The tokens of the prompt, including the tokens written to or read from the cache.
+    pub input_tokens: usize,
+    /// The tokens generated, including the reasoning tokens.
+    pub output_tokens: usize,
+    /// The tokens spent reasoning, when reported separately by the provider.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_tokens: Option<usize>,
+    /// The input tokens written to the prompt cache, included in `input_tokens`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation_input_tokens: Option<usize>,
+    /// The input tokens read from the prompt cache, included in `input_tokens`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_input_tokens: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// The `Candidate` struct represents one of the alternative responses of the AI provider.
+pub struct Candidate {
+    pub content: Vec<ContentBlock>,
+    /// Why the provider stopped generating, as reported by it (e.g. `stop`, `length`, `STOP`).
+    /// Candidates blocked by safety filters finish with `SAFETY`.
+    pub finish_reason: Option<String>,
+    /// The safety ratings of the candidate, when reported by the provider.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub safety_ratings: Vec<SafetyRating>,
+    /// The log probability of each generated token, when requested with
+    /// `GenerationOptions::logprobs`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<Vec<TokenLogprob>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// The `TokenLogprob` struct represents a generated token with its log probability and the most
+/// likely alternatives at its position.
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f32,
+    /// The most likely tokens at this position, most likely first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// The `TopLogprob` struct represents an alternative token with its log probability.
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f32,
+}
+
+impl TokenLogprob {
+    /// Returns the probability of the token, between 0 and 1.
+    pub fn probability(&self) -> f32 {
+        self.logprob.exp()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+/// The `SafetyRating` struct represents the probability that content is harmful in a category,
+/// as rated by the safety filters of the provider.
+pub struct SafetyRating {
+    /// The harm category, e.g. `HARM_CATEGORY_HARASSMENT`.
+    pub category: String,
+    /// The probability of harm, e.g. `NEGLIGIBLE` or `HIGH`.
+    pub probability: String,
+    /// Whether the content was blocked because of this rating.
+    #[serde(default)]
+    pub blocked: bool,
+}
+
+impl Candidate {
+    /// Returns the text of the candidate, concatenating all its text blocks.
+    pub fn text(&self) -> String {
+        concat_text(&self.content)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// The `ContentBlock` enum represents a typed block of the content of a response.
+pub enum ContentBlock {
+    Text {
+        text: String,
+    },
+    /// An image generated by the model, base64 encoded.
+    Image {
+        data: String,
+        mime_type: String,
+    },
+    /// A call to a tool requested by the model, with its arguments.
+    ToolCall {
+        id: Option<String>,
+        name: String,
+        arguments: serde_json::Value,
+    },
+    /// The reasoning of the model, and the signature some providers require to replay it.
+    Thinking {
+        text: String,
+        signature: Option<String>,
+    },
+}
+
+impl ContentBlock {
+    /// Returns the text of the block, if it is a text block.
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            ContentBlock::Text { text } => Some(text),
+            _ => None,
+        }
+    }
+}
+
+/// Concatenates the text blocks of a content, skipping the other blocks.
+fn concat_text(content: &[ContentBlock]) -> String {
+    content.iter().filter_map(ContentBlock::as_text).collect()
+}
+
+impl Response {
+    /// Creates a new `Response` instance holding a single text block.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text content of the response.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::client::Response;
+    ///
+    /// let response = Response::new("Hello, world!");
+    /// println!("{}", response.text());
     /// ```
     pub fn new(text: impl Into<String>) -> Self {
-        Self { text: text.into() }
+        Self::from_content(vec![ContentBlock::Text { text: text.into() }])
+    }
+
+    /// Creates a new `Response` instance from the blocks of a single candidate.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content blocks, in the order returned by the provider.
+    ///
+    pub fn from_content(content: Vec<ContentBlock>) -> Self {
+        Self::from_candidates(vec![Candidate {
+            safety_ratings: Vec::new(),
+            logprobs: None,
+            content,
+            finish_reason: None,
+        }])
+    }
+
+    /// Creates a new `Response` instance from the alternatives generated by the provider, the
+    /// first one becoming the content of the response.
+    ///
+    /// # Arguments
+    ///
+    /// * `candidates` - The alternative responses, in the order returned by the provider.
+    ///
+    pub fn from_candidates(candidates: Vec<Candidate>) -> Self {
+        Self {
+            id: None,
+            content: candidates
+                .first()
+                .map(|candidate| candidate.content.clone())
+                .unwrap_or_default(),
+            candidates,
+            prompt_safety_ratings: Vec::new(),
+            usage: None,
+            cost: None,
+            cached: false,
+        }
+    }
+
+    /// Sets the identifier given to the response by the provider.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the response.
+    ///
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Sets the tokens used by the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `usage` - The usage reported by the provider.
+    ///
+    pub fn with_usage(mut self, usage: Usage) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// Sets the cost of the response from its usage and the catalog pricing of the model that
+    /// generated it, unless it is already set.
+    pub(crate) fn priced(mut self, model: Option<&str>) -> Self {
+        if self.cost.is_none() {
+            let pricing = model.and_then(models::info).map(|info| info.pricing);
+            self.cost = pricing
+                .zip(self.usage)
+                .map(|(pricing, usage)| pricing.cost(&usage));
+        }
+        self
+    }
+
+    /// Returns the text of the response, concatenating all its text blocks.
+    pub fn text(&self) -> String {
+        concat_text(&self.content)
+    }
+
+    /// Returns the reasoning of the model, concatenating all its thinking blocks.
+    pub fn thinking(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Thinking { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns every alternative response, the first one matching `content`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::client::{Client, GenerationOptions};
+    ///
+    /// # async fn run() -> anyhow::Result<()> {
+    /// let client = Client::new("gpt-4o")?;
+    /// let response = client
+    ///     .message()
+    ///     .text("What is 17 * 23? Answer with the number only.")
+    ///     .options(GenerationOptions::new().n(5))
+    ///     .send()
+    ///     .await?;
+    /// for candidate in response.candidates() {
+    ///     println!("{} ({:?})", candidate.text(), candidate.finish_reason);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+
+    /// Returns the log probability of each token of the first candidate, when requested with
+    /// `GenerationOptions::logprobs`.
+    pub fn logprobs(&self) -> Option<&[TokenLogprob]> {
+        self.candidates.first()?.logprobs.as_deref()
+    }
+}
+
+#[cfg(test)]
+/// Unit tests for the client module.
+mod tests {
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::provider::Anthropic;
+
+    /// A provider that never answers.
+    struct Hanging;
+
+    #[async_trait]
+    impl AIProvider for Hanging {
+        async fn send_message(&self, _message: Message) -> anyhow::Result<Response> {
+            std::future::pending().await
+        }
+    }
+
+    /// A provider impersonating the OpenAI provider.
+    struct FakeOpenAI;
+
+    #[async_trait]
+    impl AIProvider for FakeOpenAI {
+        async fn send_message(&self, _message: Message) -> anyhow::Result<Response> {
+            Ok(Response::new("openai").with_usage(Usage {
+                input_tokens: 1_000_000,
+                output_tokens: 100_000,
+                ..Default::default()
+            }))
+        }
+
+        fn name(&self) -> &str {
+            "openai"
+        }
+
+        fn model(&self) -> Option<&str> {
+            Some("gpt-4o")
+        }
+    }
+
+    /// A provider embedding every text as `[3, 4]`, two texts at a time.
+    struct FakeEmbedder;
+
+    #[async_trait]
+    impl AIProvider for FakeEmbedder {
+        async fn send_message(&self, _message: Message) -> anyhow::Result<Response> {
+            unimplemented!()
+        }
+
+        fn as_embedding_provider(&self) -> Option<&dyn provider::EmbeddingProvider> {
+            Some(self)
+        }
+    }
+
+    #[async_trait]
+    impl provider::EmbeddingProvider for FakeEmbedder {
+        async fn embed(&self, request: EmbeddingRequest) -> anyhow::Result<Embeddings> {
+            assert!(request.texts.len() <= 2);
+            let vectors = request.texts.iter().map(|_| vec![3.0, 4.0]).collect();
+            let usage = EmbeddingUsage {
+                prompt_tokens: request.texts.len(),
+            };
+            Ok(Embeddings::new(vectors, Some(usage)))
+        }
+
+        fn max_batch_size(&self) -> usize {
+            2
+        }
+    }
+
+    #[test]
+    /// Tests that `cache` marks the part added last as a cache breakpoint.
+    fn test_cache() {
+        let builder = Client::from_provider(Hanging)
+            .message()
+            .system("Be terse.")
+            .cache()
+            .document(b"a,b".to_vec(), "text/csv")
+            .document(b"c,d".to_vec(), "text/csv")
+            .cache()
+            .text("Hello, world!");
+        assert!(builder.cache_system);
+        assert!(!builder.cache_text);
+        assert!(!builder.documents[0].cache);
+        assert!(builder.documents[1].cache);
+
+        let builder = builder.cache();
+        assert!(builder.cache_text);
+    }
+
+    #[test]
+    /// Tests converting reasoning efforts and budgets into one another.
+    fn test_reasoning() {
+        let reasoning = Reasoning::Effort(ReasoningEffort::Medium);
+        assert_eq!(reasoning.budget_tokens(), 8192);
+        assert_eq!(reasoning.effort(), ReasoningEffort::Medium);
+
+        assert_eq!(Reasoning::BudgetTokens(1024).effort(), ReasoningEffort::Low);
+        assert_eq!(
+            Reasoning::BudgetTokens(4096).effort(),
+            ReasoningEffort::Medium
+        );
+        assert_eq!(
+            Reasoning::BudgetTokens(32000).effort(),
+            ReasoningEffort::High
+        );
+        assert_eq!(
+            serde_json::to_value(Reasoning::BudgetTokens(4096)).unwrap(),
+            serde_json::json!({"budget_tokens": 4096})
+        );
+    }
+
+    #[test]
+    /// Tests that model overrides switch to a provider of the matching vendor.
+    fn test_provider_for() {
+        let client = Client::from_provider(FakeOpenAI);
+        let same = client.provider_for(Some("gpt-4")).unwrap();
+        assert!(Arc::ptr_eq(&same, &client.provider));
+        let same = client.provider_for(None).unwrap();
+        assert!(Arc::ptr_eq(&same, &client.provider));
+
+        // The key is injected rather than read from the environment, which tests share.
+        let http = reqwest::Client::new();
+        let anthropic: Arc<dyn AIProvider> = provider::for_vendor(
+            Anthropic::NAME,
+            "claude-3-haiku-20240307",
+            Some("test_api_key".to_string()),
+            &http,
+        )
+        .unwrap()
+        .into();
+        assert_eq!(anthropic.model(), Some("claude-3-haiku-20240307"));
+        client
+            .vendors
+            .lock()
+            .unwrap()
+            .insert(Anthropic::NAME, anthropic.clone());
+
+        let other = client
+            .provider_for(Some("claude-3-haiku-20240307"))
+            .unwrap();
+        assert!(Arc::ptr_eq(&other, &anthropic));
+        assert_eq!(other.name(), "anthropic");
+    }
+
+    #[test]
+    /// Tests that custom providers receive every model override.
+    fn test_provider_for_custom() {
+        let client = Client::from_provider(Hanging);
+        let same = client
+            .provider_for(Some("claude-3-haiku-20240307"))
+            .unwrap();
+        assert!(Arc::ptr_eq(&same, &client.provider));
+    }
+
+    #[tokio::test]
+    /// Tests that transcription fails on invalid audio and on providers without support.
+    async fn test_transcribe() {
+        let client = Client::from_provider(Hanging);
+        let err = client
+            .transcribe(b"hello".to_vec())
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "unrecognized audio format");
+
+        let wav = b"RIFF\x24\x08\x00\x00WAVEfmt ".to_vec();
+        let err = client.transcribe(wav).send().await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "custom does not support transcription with whisper-1"
+        );
+    }
+
+    #[tokio::test]
+    /// Tests that embeddings are batched and normalized.
+    async fn test_embed() {
+        let client = Client::from_provider(FakeEmbedder);
+        let embeddings = client
+            .embed(["a", "b", "c", "d", "e"])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(embeddings.vectors.len(), 5);
+        assert_eq!(embeddings.vectors[4], vec![0.6, 0.8]);
+        assert_eq!(embeddings.dimensions, 2);
+        assert_eq!(embeddings.usage.unwrap().prompt_tokens, 5);
+
+        let client = Client::from_provider(Hanging);
+        let err = client.embed(["a"]).send().await.unwrap_err();
+        assert_eq!(err.to_string(), "custom does not support embeddings");
+    }
+
+    #[tokio::test]
+    /// Tests that a per-request timeout interrupts a hanging request.
+    async fn test_send_timeout() {
+        let client = Client::from_provider(Hanging);
+        let err = client
+            .message()
+            .text("Hello, world!")
+            .timeout(Duration::from_millis(10))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SendError>(),
+            Some(&SendError::Timeout(Duration::from_millis(10)))
+        );
+    }
+
+    #[tokio::test]
+    /// Tests that cancelling the token aborts the in-flight request.
+    async fn test_send_cancellation() {
+        let token = CancellationToken::new();
+        let client = Client::from_provider(Hanging);
+        let send = client
+            .message()
+            .text("Hello, world!")
+            .cancellation(token.clone())
+            .send();
+
+        token.cancel();
+        let err = send.await.unwrap_err();
+        assert_eq!(err.downcast_ref::<SendError>(), Some(&SendError::Cancelled));
+    }
+
+    #[test]
+    /// Tests dropping the oldest turns of the history until a message fits its context window.
+    fn test_fit_context_window() {
+        let long = "hello ".repeat(5_000);
+        let mut message = Message {
+            text: "How are you?".to_string(),
+            history: Some(vec![
+                Turn::user(&long),
+                Turn::assistant(&Response::new(&long)),
+                Turn::user("Hi"),
+                Turn::assistant(&Response::new("Hi!")),
+            ]),
+            ..Default::default()
+        };
+        let info = models::info("gpt-4").unwrap();
+        let reject = ContextOverflow::Reject;
+        assert!(fit_context_window(&mut message, "gpt-4", info, 0, reject).is_err());
+
+        fit_context_window(&mut message, "gpt-4", info, 0, ContextOverflow::Truncate).unwrap();
+        let history = message.history.as_ref().unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].text(), "Hi");
+
+        // The response must fit too.
+        message.text = "hello ".repeat(3_000);
+        assert!(fit_context_window(&mut message, "gpt-4", info, 0, reject).is_ok());
+        let err = fit_context_window(&mut message, "gpt-4", info, 6_000, reject).unwrap_err();
+        assert_eq!(err.output_tokens, 6_000);
+    }
+
+    #[test]
+    /// Tests reserving the tokens of the response, capped by the model, and the reasoning budget.
+    fn test_output_tokens() {
+        let openai = provider::OpenAI::new("test_api_key", "gpt-3.5-turbo");
+        let mut message = Message::default();
+        let info = models::info("gpt-3.5-turbo").unwrap();
+        assert_eq!(
+            output_tokens(&openai, &message, "gpt-3.5-turbo", info),
+            4_096
+        );
+
+        let info = models::info("o3-mini").unwrap();
+        message.options.reasoning = Some(Reasoning::BudgetTokens(2_000));
+        assert_eq!(output_tokens(&openai, &message, "o3-mini", info), 6_096);
+
+        // Without a provider limit, the model's maximum is reserved.
+        assert_eq!(
+            output_tokens(&FakeOpenAI, &message, "o3-mini", info),
+            102_000
+        );
+    }
+
+    #[tokio::test]
+    /// Tests rejecting a message exceeding the context window before sending it.
+    async fn test_send_context_overflow() {
+        let client = Client::from_provider(FakeOpenAI);
+        let err = client
+            .message()
+            .model("gpt-4")
+            .text("hello ".repeat(10_000))
+            .context_overflow(ContextOverflow::Reject)
+            .send()
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ContextWindowError>().unwrap();
+        assert_eq!(err.context_window, 8_192);
+        assert!(err.tokens > 8_192);
+    }
+
+    #[tokio::test]
+    /// Tests rejecting images sent to a model of the catalog without vision.
+    async fn test_send_unsupported_images() {
+        let client = Client::from_provider(FakeOpenAI);
+        let err = client
+            .message()
+            .model("gpt-3.5-turbo")
+            .text("What is this?")
+            .image_url("https://example.com/cat.png")
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "gpt-3.5-turbo does not accept images");
+        assert!(client.model_info().unwrap().capabilities.vision);
+    }
+
+    #[test]
+    /// Tests that response formats are only checked for OpenAI models, which honor them.
+    fn test_check_capabilities() {
+        let message = Message {
+            options: GenerationOptions::new().response_format(ResponseFormat::Json),
+            ..Default::default()
+        };
+        assert!(check_capabilities(&message, models::info("gpt-4o").unwrap()).is_ok());
+        let err = check_capabilities(&message, models::info("gpt-4").unwrap()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "gpt-4 does not support JSON response formats"
+        );
+        let claude = models::info("claude-3-5-sonnet-20240620").unwrap();
+        assert!(check_capabilities(&message, claude).is_ok());
+    }
+
+    #[cfg(feature = "preprocess")]
+    #[tokio::test]
+    /// Tests that preprocessing applies to the images added before enabling it, and that images
+    /// only preprocessing converts are rejected when it is off.
+    async fn test_send_preprocess_images() {
+        let image = ::image::DynamicImage::new_rgb8(4, 4);
+        let mut bmp = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut bmp, ::image::ImageFormat::Bmp).unwrap();
+        let bmp = bmp.into_inner();
+
+        let client = Client::from_provider(FakeOpenAI);
+        let err = client
+            .message()
+            .text("What is this?")
+            .image(bmp.clone())
+            .unwrap()
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<image::ImageError>(),
+            Some(&image::ImageError::Unsupported(image::ImageFormat::Bmp))
+        );
+
+        client
+            .message()
+            .text("What is this?")
+            .image(bmp)
+            .unwrap()
+            .preprocess_images(true)
+            .send()
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    /// Tests pricing responses, accounting them to their tag and enforcing its budget.
+    async fn test_send_budget() {
+        let tracker = CostTracker::new().budget("eval", budget::Budget::new().total(5.0));
+        let client = Client::from_provider(FakeOpenAI)
+            .with_cost_tracker(tracker.clone())
+            .with_tag("eval");
+
+        let response = client.message().text("Hi").send().await.unwrap();
+        assert_eq!(response.cost, Some(3.5));
+        client.message().text("Hi").send().await.unwrap();
+        let err = client.message().text("Hi").send().await.unwrap_err();
+        assert!(err.downcast_ref::<budget::BudgetExceededError>().is_some());
+        assert_eq!(tracker.spend("eval").requests, 2);
+
+        client
+            .message()
+            .text("Hi")
+            .tag("other")
+            .send()
+            .await
+            .unwrap();
+        // Tags without a budget share the default one, but their spend is still kept apart.
+        assert_eq!(tracker.spend("other").total, 3.5);
+        assert_eq!(tracker.pooled_spend().total, 3.5);
+    }
+
+    #[tokio::test]
+    /// Tests answering repeated messages from the cache for free, unless bypassed.
+    async fn test_send_cached() {
+        let tracker = CostTracker::new();
+        let cache = ResponseCache::new(crate::cache::MemoryCache::new(10));
+        let client = Client::from_provider(FakeOpenAI)
+            .with_cost_tracker(tracker.clone())
+            .with_cache(cache);
+
+        let response = client.message().text("Hi").send().await.unwrap();
+        assert!(!response.cached);
+        let response = client
+            .message()
+            .text("Hi")
+            .tag("other")
+            .send()
+            .await
+            .unwrap();
+        assert!(response.cached);
+        assert_eq!(response.cost, Some(0.0));
+        let response = client
+            .message()
+            .text("Hi")
+            .bypass_cache()
+            .send()
+            .await
+            .unwrap();
+        assert!(!response.cached);
+
+        assert_eq!(tracker.spend(budget::DEFAULT_TAG).requests, 2);
+        assert_eq!(tracker.spend("other").requests, 0);
+    }
+
+    #[tokio::test]
+    /// Tests answering cached messages once the budget is spent.
+    async fn test_send_cached_over_budget() {
+        let tracker = CostTracker::new().budget("eval", budget::Budget::new().total(5.0));
+        let cache = ResponseCache::new(crate::cache::MemoryCache::new(10));
+        let client = Client::from_provider(FakeOpenAI)
+            .with_cost_tracker(tracker.clone())
+            .with_tag("eval")
+            .with_cache(cache);
+
+        client.message().text("Hi").send().await.unwrap();
+        client.message().text("Hello").send().await.unwrap();
+        let err = client.message().text("Hey").send().await.unwrap_err();
+        assert!(err.downcast_ref::<budget::BudgetExceededError>().is_some());
+
+        let response = client.message().text("Hi").send().await.unwrap();
+        assert!(response.cached);
+        assert_eq!(tracker.spend("eval").requests, 2);
+    }
+
+    /// A provider fetching images by URL, answering with the URL of the first image.
+    struct UrlReader;
+
+    #[async_trait]
+    impl AIProvider for UrlReader {
+        async fn send_message(&self, message: Message) -> anyhow::Result<Response> {
+            let images = message.images.unwrap_or_default();
+            Ok(Response::new(images[0].url.clone().unwrap_or_default()))
+        }
+
+        fn supports_image_urls(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    /// Tests passing image URLs through, unless their MIME type is unknown.
+    async fn test_send_image_urls() {
+        let client = Client::from_provider(UrlReader);
+        let response = client
+            .message()
+            .text("What is this?")
+            .image_url("http://127.0.0.1/cat.png")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.text(), "http://127.0.0.1/cat.png");
+
+        // Inlining is attempted, and refused as the address is not public.
+        let err = client
+            .message()
+            .text("What is this?")
+            .image_url("http://127.0.0.1/cat")
+            .send()
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<image::ImageError>(),
+            Some(image::ImageError::ForbiddenUrl(_))
+        ));
+
+        // HEIC images are rejected before the provider fetches them.
+        let err = client
+            .message()
+            .text("What is this?")
+            .image_url("http://127.0.0.1/photo.HEIC")
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<image::ImageError>(),
+            Some(&image::ImageError::Heic)
+        );
     }
 }
@@ -0,0 +1,239 @@
+use std::path::Path;
+
+use base64::{engine::general_purpose, Engine as _};
+
+use crate::client::Document;
+
+/// MIME type of PDF documents.
+pub const PDF: &str = "application/pdf";
+/// MIME type of plain text documents.
+pub const TEXT: &str = "text/plain";
+
+/// Returns the MIME type of a document file, from its content or its extension.
+///
+/// # Arguments
+///
+/// * `path` - The path of the document.
+/// * `data` - The content of the document.
+///
+pub fn mime_type(path: &Path, data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"%PDF-") {
+        return Some(PDF);
+    }
+
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "pdf" => Some(PDF),
+        "txt" => Some(TEXT),
+        "md" => Some("text/markdown"),
+        "csv" => Some("text/csv"),
+        "html" | "htm" => Some("text/html"),
+        _ => None,
+    }
+}
+
+/// Returns `true` if documents of the given MIME type are plain text, e.g. `text/csv`.
+///
+/// # Arguments
+///
+/// * `mime_type` - The MIME type of the document.
+///
+pub fn is_text(mime_type: &str) -> bool {
+    mime_type.starts_with("text/")
+}
+
+/// Returns the text of a document, for providers that do not read documents natively.
+///
+/// Text documents are decoded as is, while the text of PDF documents is extracted locally when
+/// the `pdf` feature is enabled. Extracting PDF documents is CPU-bound, see `read_text` in async
+/// code.
+///
+/// # Arguments
+///
+/// * `document` - The document to read.
+///
+/// # Errors
+///
+/// Returns an error if the document is referenced by URL, cannot be decoded or its type is
+/// unsupported, or if it is a PDF document without text, e.g. a scanned fax.
+///
+pub fn text(document: &Document) -> anyhow::Result<String> {
+    if let Some(url) = &document.url {
+        anyhow::bail!("cannot read the text of a document referenced by URL: {url}");
+    }
+
+    let data = general_purpose::STANDARD.decode(&document.data)?;
+
+    if is_text(&document.mime_type) {
+        return Ok(String::from_utf8(data)?);
+    }
+
+    match document.mime_type.as_str() {
+        #[cfg(feature = "pdf")]
+        PDF => extract_text(&data),
+        #[cfg(not(feature = "pdf"))]
+        PDF => Err(anyhow::anyhow!(
+            "reading PDF documents requires the `pdf` feature"
+        )),
+        mime_type => Err(anyhow::anyhow!("unsupported document type: {mime_type}")),
+    }
+}
+
+/// Returns the text of a document like `text`, on a blocking thread so PDF extraction does not
+/// stall the async runtime.
+///
+/// # Arguments
+///
+/// * `document` - The document to read.
+///
+/// # Errors
+///
+/// Returns the errors of `text`.
+///
+pub async fn read_text(document: Document) -> anyhow::Result<String> {
+    tokio::task::spawn_blocking(move || text(&document)).await?
+}
+
+/// Extracts the text of a PDF document.
+///
+/// Scanned documents, like faxes, are images without a text layer: their text cannot be
+/// extracted, and they must be sent to a provider reading PDF documents natively.
+///
+/// # Arguments
+///
+/// * `data` - The content of the PDF document.
+///
+/// # Errors
+///
+/// Returns an error if the document cannot be parsed or has no text.
+///
+#[cfg(feature = "pdf")]
+pub fn extract_text(data: &[u8]) -> anyhow::Result<String> {
+    let text = pdf_extract::extract_text_from_mem(data)
+        .map_err(|err| anyhow::anyhow!("failed to extract the text of the PDF document: {err}"))?;
+    if text.trim().is_empty() {
+        anyhow::bail!(
+            "the PDF document has no text to extract, e.g. it is scanned: send it to a provider \
+             reading PDF documents natively"
+        );
+    }
+    Ok(text)
+}
+
+/// Counts the pages of a PDF document from its page objects, without parsing it.
+///
+/// Returns `None` when no page object is found, e.g. when they are compressed in object streams.
+///
+/// # Arguments
+///
+/// * `data` - The content of the PDF document.
+///
+pub(crate) fn page_count(data: &[u8]) -> Option<usize> {
+    let pages = data
+        .windows(5)
+        .enumerate()
+        .filter(|(_, window)| *window == b"/Type")
+        .filter(|(index, _)| {
+            let rest = &data[index + 5..];
+            let rest = &rest[rest.iter().take_while(|b| b.is_ascii_whitespace()).count()..];
+            // `/Pages` objects are the nodes of the page tree, not pages.
+            rest.starts_with(b"/Page") && !rest[5..].starts_with(b"s")
+        })
+        .count();
+    (pages > 0).then_some(pages)
+}
+
+#[cfg(test)]
+/// Unit tests for the document module.
+mod tests {
+    use super::*;
+
+    /// Builds a single page PDF document showing the given text.
+    fn pdf(text: &str) -> Vec<u8> {
+        let stream = format!("BT /F1 12 Tf 72 720 Td ({text}) Tj ET");
+        let objects = [
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] \
+             /Resources << /Font << /F1 5 0 R >> >> /Contents 4 0 R >>"
+                .to_string(),
+            format!(
+                "<< /Length {} >>\nstream\n{stream}\nendstream",
+                stream.len()
+            ),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+        ];
+
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (index, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend(format!("{} 0 obj\n{object}\nendobj\n", index + 1).bytes());
+        }
+
+        let xref = pdf.len();
+        pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).bytes());
+        for offset in offsets {
+            pdf.extend(format!("{offset:010} 00000 n \n").bytes());
+        }
+        pdf.extend(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+                objects.len() + 1
+            )
+            .bytes(),
+        );
+        pdf
+    }
+
+    #[test]
+    /// Tests counting the pages of a PDF document.
+    fn test_page_count() {
+        assert_eq!(page_count(&pdf("fax")), Some(1));
+        assert_eq!(page_count(b"%PDF-1.4 /Type/Page /Type /Page"), Some(2));
+        assert_eq!(page_count(b"%PDF-1.5 /Type /ObjStm"), None);
+    }
+
+    #[test]
+    /// Tests detecting the MIME type of documents.
+    fn test_mime_type() {
+        assert_eq!(mime_type(Path::new("fax"), &pdf("fax")), Some(PDF));
+        assert_eq!(mime_type(Path::new("notes.TXT"), b"notes"), Some(TEXT));
+        assert_eq!(mime_type(Path::new("archive.zip"), b"PK"), None);
+    }
+
+    #[test]
+    /// Tests reading the text of a text document.
+    fn test_text() {
+        let document = Document {
+            data: general_purpose::STANDARD.encode("a,b\n1,2"),
+            mime_type: "text/csv".to_string(),
+            name: None,
+            url: None,
+            cache: false,
+        };
+        assert_eq!(text(&document).unwrap(), "a,b\n1,2");
+
+        let document = Document {
+            url: Some("https://example.com/notes.csv".to_string()),
+            ..document
+        };
+        assert!(text(&document).is_err());
+
+        let document = Document {
+            mime_type: "application/zip".to_string(),
+            url: None,
+            ..document
+        };
+        assert!(text(&document).is_err());
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    /// Tests extracting the text of a PDF document.
+    fn test_extract_text() {
+        let text = extract_text(&pdf("Hello from a fax")).unwrap();
+        assert_eq!(text.trim(), "Hello from a fax");
+        assert!(extract_text(&pdf("")).is_err());
+    }
+}
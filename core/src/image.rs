@@ -0,0 +1,736 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect, Url,
+};
+
+use crate::client::Image;
+
+/// Size of a megabyte, in bytes.
+const MB: usize = 1024 * 1024;
+/// Quality used when re-encoding images as JPEG.
+#[cfg(feature = "preprocess")]
+const JPEG_QUALITY: u8 = 85;
+/// Maximum size of an image downloaded to be preprocessed, or when the provider has no limit.
+pub const MAX_DOWNLOAD_BYTES: usize = 50 * MB;
+/// Maximum number of redirects followed when downloading an image.
+const MAX_REDIRECTS: usize = 5;
+/// Number of attempts at shrinking an image that exceeds the byte limit.
+#[cfg(feature = "preprocess")]
+const SHRINK_ATTEMPTS: usize = 5;
+
+/// Represents an image format recognized from the first bytes of an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    Gif,
+    Webp,
+    Bmp,
+    Tiff,
+    /// HEIC or HEIF, e.g. iPhone photos. Recognized but never converted, as decoding it requires
+    /// the libheif system library: convert these images to JPEG before attaching them.
+    Heic,
+}
+
+impl ImageFormat {
+    /// Returns the MIME type of the format.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+            ImageFormat::Gif => "image/gif",
+            ImageFormat::Webp => "image/webp",
+            ImageFormat::Bmp => "image/bmp",
+            ImageFormat::Tiff => "image/tiff",
+            ImageFormat::Heic => "image/heic",
+        }
+    }
+
+    /// Returns the format matching a file extension, ignoring its case.
+    ///
+    /// # Arguments
+    ///
+    /// * `extension` - The file extension, without the leading dot.
+    ///
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "png" => Some(ImageFormat::Png),
+            "gif" => Some(ImageFormat::Gif),
+            "webp" => Some(ImageFormat::Webp),
+            "bmp" => Some(ImageFormat::Bmp),
+            "tif" | "tiff" => Some(ImageFormat::Tiff),
+            "heic" | "heif" => Some(ImageFormat::Heic),
+            _ => None,
+        }
+    }
+
+    /// Returns the format matching a MIME type.
+    ///
+    /// # Arguments
+    ///
+    /// * `mime_type` - The MIME type, e.g. `image/png`.
+    ///
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        [
+            ImageFormat::Jpeg,
+            ImageFormat::Png,
+            ImageFormat::Gif,
+            ImageFormat::Webp,
+            ImageFormat::Bmp,
+            ImageFormat::Tiff,
+            ImageFormat::Heic,
+        ]
+        .into_iter()
+        .find(|format| format.mime_type() == mime_type)
+    }
+
+    /// Returns `true` if the format is accepted by every provider, i.e. JPEG, PNG, GIF or WebP.
+    pub fn is_supported(&self) -> bool {
+        matches!(
+            self,
+            ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Gif | ImageFormat::Webp
+        )
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.mime_type())
+    }
+}
+
+/// Represents an error raised while attaching an image to a message.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ImageError {
+    #[error("unrecognized image format")]
+    Unrecognized,
+    #[error("unsupported image format: {0}")]
+    Unsupported(ImageFormat),
+    /// HEIC images, e.g. iPhone photos, cannot be decoded, so they are never sent.
+    #[error("HEIC images are not supported: convert them to JPEG before attaching them")]
+    Heic,
+    #[error("invalid image: {0}")]
+    Invalid(String),
+    #[error("too many images: {count} (maximum is {max})")]
+    TooMany { count: usize, max: usize },
+    #[error("image is too large: {size} bytes (maximum is {max})")]
+    TooLarge { size: usize, max: usize },
+    #[error("image URL is not allowed: {0}")]
+    ForbiddenUrl(String),
+}
+
+impl ImageError {
+    /// Returns the error rejecting an image in a format the providers don't accept.
+    pub(crate) fn unsupported(format: ImageFormat) -> Self {
+        match format {
+            ImageFormat::Heic => ImageError::Heic,
+            format => ImageError::Unsupported(format),
+        }
+    }
+}
+
+/// Represents the limits a provider puts on the images of a message.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ImageLimits {
+    /// Maximum width and height, in pixels, before the provider downsizes the image itself.
+    pub max_dimension: Option<u32>,
+    /// Maximum size of a single image, in bytes.
+    pub max_bytes: Option<usize>,
+    /// Maximum number of images in a single message.
+    pub max_images: Option<usize>,
+}
+
+impl ImageLimits {
+    /// Limits of the OpenAI API.
+    pub const OPENAI: Self = Self {
+        max_dimension: Some(2048),
+        max_bytes: Some(20 * MB),
+        max_images: Some(500),
+    };
+
+    /// Limits of the Anthropic API.
+    pub const ANTHROPIC: Self = Self {
+        max_dimension: Some(1568),
+        max_bytes: Some(5 * MB),
+        max_images: Some(100),
+    };
+
+    /// Limits of the Gemini API.
+    pub const GOOGLE: Self = Self {
+        max_dimension: Some(3072),
+        max_bytes: Some(20 * MB),
+        max_images: Some(3600),
+    };
+
+    /// Checks the number and size of the given images.
+    ///
+    /// # Arguments
+    ///
+    /// * `images` - The images of a message.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ImageError::TooMany` or `ImageError::TooLarge` if a limit is exceeded.
+    ///
+    pub fn check(&self, images: &[Image]) -> Result<(), ImageError> {
+        if let Some(max) = self.max_images {
+            if images.len() > max {
+                return Err(ImageError::TooMany {
+                    count: images.len(),
+                    max,
+                });
+            }
+        }
+
+        if let Some(max) = self.max_bytes {
+            for image in images {
+                // Decoded size of the base64 data, ignoring the padding.
+                let size = image.data.trim_end_matches('=').len() * 3 / 4;
+                if size > max {
+                    return Err(ImageError::TooLarge { size, max });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Represents the size of a generated image, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ImageSize {
+    /// A square image of 1024x1024 pixels.
+    pub const SQUARE: Self = Self::new(1024, 1024);
+
+    /// Creates a new `ImageSize` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the image, in pixels.
+    /// * `height` - The height of the image, in pixels.
+    ///
+    pub const fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+
+    /// Returns the aspect ratio closest to this size among the ones accepted by Imagen, e.g.
+    /// `16:9` for 1792x1024.
+    pub fn aspect_ratio(&self) -> &'static str {
+        const RATIOS: &[(&str, f64)] = &[
+            ("1:1", 1.0),
+            ("3:4", 0.75),
+            ("4:3", 4.0 / 3.0),
+            ("9:16", 9.0 / 16.0),
+            ("16:9", 16.0 / 9.0),
+        ];
+
+        let ratio = self.width as f64 / self.height.max(1) as f64;
+        RATIOS
+            .iter()
+            .min_by(|(_, a), (_, b)| (a - ratio).abs().total_cmp(&(b - ratio).abs()))
+            .map(|(name, _)| *name)
+            .unwrap_or("1:1")
+    }
+}
+
+impl Default for ImageSize {
+    fn default() -> Self {
+        Self::SQUARE
+    }
+}
+
+impl fmt::Display for ImageSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// Detects the format of an image from its magic bytes.
+///
+/// # Arguments
+///
+/// * `data` - The image data.
+///
+/// # Examples
+///
+/// ```
+/// use aipim::image::{sniff, ImageFormat};
+///
+/// let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+/// assert_eq!(sniff(png), Some(ImageFormat::Png));
+/// ```
+pub fn sniff(data: &[u8]) -> Option<ImageFormat> {
+    match data {
+        [0xFF, 0xD8, 0xFF, ..] => Some(ImageFormat::Jpeg),
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(ImageFormat::Png),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(ImageFormat::Gif),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(ImageFormat::Webp),
+        [b'B', b'M', ..] if has_dib_header(data) => Some(ImageFormat::Bmp),
+        [b'I', b'I', 0x2A, 0x00, ..] | [b'M', b'M', 0x00, 0x2A, ..] => Some(ImageFormat::Tiff),
+        [_, _, _, _, b'f', b't', b'y', b'p', brand @ ..] if is_heif_brand(brand) => {
+            Some(ImageFormat::Heic)
+        }
+        _ => None,
+    }
+}
+
+/// Detects the format of an image, rejecting the ones providers don't accept.
+///
+/// # Arguments
+///
+/// * `data` - The image data.
+///
+/// # Errors
+///
+/// Returns `ImageError::Unrecognized` if the format cannot be detected, or
+/// `ImageError::Unsupported` if it is not accepted by the providers, or `ImageError::Heic` for
+/// HEIC images.
+///
+pub fn detect(data: &[u8]) -> Result<ImageFormat, ImageError> {
+    let format = sniff(data).ok_or(ImageError::Unrecognized)?;
+    if !format.is_supported() {
+        return Err(ImageError::unsupported(format));
+    }
+    Ok(format)
+}
+
+/// Prepares an image for a provider: applies and strips the EXIF metadata, converts formats the
+/// providers don't accept (BMP, TIFF) and downsizes it to fit the given limits.
+///
+/// Images are re-encoded as PNG when they have an alpha channel and as JPEG otherwise. GIFs that
+/// already fit are kept as-is to preserve their animation.
+///
+/// # Arguments
+///
+/// * `data` - The image data.
+/// * `limits` - The limits of the provider the image is sent to.
+///
+/// # Errors
+///
+/// Returns an `ImageError` if the image cannot be decoded, is in a format that cannot be
+/// converted, or still exceeds the byte limit after being shrunk. HEIC images are not decoded
+/// and are rejected with `ImageError::Heic`.
+///
+#[cfg(feature = "preprocess")]
+pub fn preprocess(data: &[u8], limits: &ImageLimits) -> Result<(Vec<u8>, ImageFormat), ImageError> {
+    use std::io::Cursor;
+
+    use ::image::{imageops::FilterType, DynamicImage, GenericImageView, ImageDecoder};
+
+    let format = sniff(data).ok_or(ImageError::Unrecognized)?;
+    if format == ImageFormat::Heic {
+        return Err(ImageError::Heic);
+    }
+
+    let invalid = |err: ::image::ImageError| ImageError::Invalid(err.to_string());
+    let mut decoder = ::image::ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|err| ImageError::Invalid(err.to_string()))?
+        .into_decoder()
+        .map_err(invalid)?;
+    let orientation = decoder.orientation().map_err(invalid)?;
+    let mut image = DynamicImage::from_decoder(decoder).map_err(invalid)?;
+
+    let (width, height) = image.dimensions();
+    let max_dimension = limits.max_dimension.unwrap_or(u32::MAX);
+    let fits = width <= max_dimension && height <= max_dimension;
+    if format == ImageFormat::Gif && fits && limits.max_bytes.is_none_or(|max| data.len() <= max) {
+        return Ok((data.to_vec(), format));
+    }
+
+    image.apply_orientation(orientation);
+    if !fits {
+        image = image.resize(max_dimension, max_dimension, FilterType::Triangle);
+    }
+
+    for _ in 0..SHRINK_ATTEMPTS {
+        let (encoded, format) = encode(&image).map_err(invalid)?;
+        match limits.max_bytes {
+            Some(max) if encoded.len() > max => {
+                let (width, height) = image.dimensions();
+                image = image.resize(width * 3 / 4, height * 3 / 4, FilterType::Triangle);
+            }
+            _ => return Ok((encoded, format)),
+        }
+    }
+
+    let (encoded, _) = encode(&image).map_err(invalid)?;
+    Err(ImageError::TooLarge {
+        size: encoded.len(),
+        max: limits.max_bytes.unwrap_or_default(),
+    })
+}
+
+/// Preprocesses a base64 encoded image in place, see `preprocess`.
+///
+/// # Arguments
+///
+/// * `image` - The image to preprocess.
+/// * `limits` - The limits of the provider the image is sent to.
+///
+/// # Errors
+///
+/// Returns an `ImageError` if the image cannot be decoded or preprocessed.
+///
+#[cfg(feature = "preprocess")]
+pub fn preprocess_image(image: &mut Image, limits: &ImageLimits) -> Result<(), ImageError> {
+    use base64::{engine::general_purpose, Engine as _};
+
+    let data = general_purpose::STANDARD
+        .decode(&image.data)
+        .map_err(|err| ImageError::Invalid(err.to_string()))?;
+    let (data, format) = preprocess(&data, limits)?;
+    image.data = general_purpose::STANDARD.encode(data);
+    image.mime_type = format.mime_type().to_string();
+    Ok(())
+}
+
+#[cfg(feature = "preprocess")]
+fn encode(image: &::image::DynamicImage) -> ::image::ImageResult<(Vec<u8>, ImageFormat)> {
+    use std::io::Cursor;
+
+    use ::image::codecs::jpeg::JpegEncoder;
+
+    let mut buffer = Cursor::new(Vec::new());
+    if image.color().has_alpha() {
+        image.write_to(&mut buffer, ::image::ImageFormat::Png)?;
+        return Ok((buffer.into_inner(), ImageFormat::Png));
+    }
+
+    let encoder = JpegEncoder::new_with_quality(&mut buffer, JPEG_QUALITY);
+    image.to_rgb8().write_with_encoder(encoder)?;
+    Ok((buffer.into_inner(), ImageFormat::Jpeg))
+}
+
+/// Detects the format of an image, accepting the ones `preprocess` can convert.
+///
+/// # Arguments
+///
+/// * `data` - The image data.
+///
+/// # Errors
+///
+/// Returns `ImageError::Unrecognized` if the format cannot be detected, or
+/// `ImageError::Heic` if it cannot be converted.
+///
+pub fn detect_convertible(data: &[u8]) -> Result<ImageFormat, ImageError> {
+    match sniff(data).ok_or(ImageError::Unrecognized)? {
+        ImageFormat::Heic => Err(ImageError::Heic),
+        format => Ok(format),
+    }
+}
+
+/// Downloads an image attached by URL.
+///
+/// Only `http` and `https` URLs are fetched, and only from public addresses: hosts resolving to
+/// loopback, private or link-local addresses (e.g. cloud metadata endpoints) are refused, also
+/// when reached through a redirect. The body is read up to `max_bytes`.
+///
+/// # Arguments
+///
+/// * `url` - The URL of the image.
+/// * `max_bytes` - The maximum size of the image.
+///
+/// # Errors
+///
+/// Returns `ImageError::ForbiddenUrl` if the URL is refused, `ImageError::TooLarge` if the image
+/// exceeds `max_bytes`, or the error of the request.
+///
+pub(crate) async fn download(url: &str, max_bytes: usize) -> anyhow::Result<Vec<u8>> {
+    let parsed = Url::parse(url).map_err(|_| ImageError::ForbiddenUrl(url.to_string()))?;
+    check_url(&parsed)?;
+
+    let mut response = http_client().get(parsed).send().await?.error_for_status()?;
+    if let Some(size) = response.content_length() {
+        if size > max_bytes as u64 {
+            let size = usize::try_from(size).unwrap_or(usize::MAX);
+            return Err(ImageError::TooLarge {
+                size,
+                max: max_bytes,
+            }
+            .into());
+        }
+    }
+
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if data.len() + chunk.len() > max_bytes {
+            return Err(ImageError::TooLarge {
+                size: data.len() + chunk.len(),
+                max: max_bytes,
+            }
+            .into());
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+/// Returns the HTTP client downloading images, which only connects to public addresses.
+fn http_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        let policy = redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error(format!("more than {MAX_REDIRECTS} redirects"))
+            } else if let Err(err) = check_url(attempt.url()) {
+                attempt.error(err)
+            } else {
+                attempt.follow()
+            }
+        });
+        reqwest::Client::builder()
+            .dns_resolver(Arc::new(PublicResolver))
+            .redirect(policy)
+            .connect_timeout(Duration::from_secs(10))
+            .timeout(Duration::from_secs(60))
+            .build()
+            .expect("the image download client is valid")
+    })
+}
+
+/// Checks that a URL uses HTTP and does not target a non-public IP address. Host names are
+/// checked when they are resolved, by `PublicResolver`.
+fn check_url(url: &Url) -> Result<(), ImageError> {
+    let forbidden = || ImageError::ForbiddenUrl(url.to_string());
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(forbidden());
+    }
+    let host = url.host_str().ok_or_else(forbidden)?;
+    match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(ip) if !is_public(ip) => Err(forbidden()),
+        _ => Ok(()),
+    }
+}
+
+/// Returns `true` if the address is reachable on the public internet.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                // Shared address space (carrier-grade NAT), 100.64.0.0/10.
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+/// A DNS resolver dropping the non-public addresses of hosts, so image URLs cannot reach internal
+/// services, including through DNS rebinding.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(ImageError::ForbiddenUrl(host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Returns `true` if a BMP file header is followed by a DIB header of a known size, since the `BM`
+/// signature alone is common in other data.
+fn has_dib_header(data: &[u8]) -> bool {
+    const SIZES: &[u32] = &[12, 40, 108, 124];
+    data.get(14..18)
+        .map(|size| u32::from_le_bytes([size[0], size[1], size[2], size[3]]))
+        .is_some_and(|size| SIZES.contains(&size))
+}
+
+fn is_heif_brand(brand: &[u8]) -> bool {
+    const BRANDS: &[&[u8; 4]] = &[
+        b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1",
+    ];
+    brand.len() >= 4 && BRANDS.iter().any(|known| &brand[..4] == *known)
+}
+
+#[cfg(test)]
+/// Unit tests for the image module.
+mod tests {
+    use base64::{engine::general_purpose, Engine as _};
+
+    use super::*;
+
+    /// The file header of a BMP image, followed by the size of a `BITMAPINFOHEADER`.
+    const BMP_HEADER: &[u8] = b"BM\x36\0\0\0\0\0\0\0\x36\0\0\0\x28\0\0\0";
+
+    #[test]
+    /// Tests sniffing every recognized format.
+    fn test_sniff() {
+        let cases: &[(&[u8], ImageFormat)] = &[
+            (b"\xFF\xD8\xFF\xE0\0\x10JFIF", ImageFormat::Jpeg),
+            (b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", ImageFormat::Png),
+            (b"GIF89a\x01\0\x01\0", ImageFormat::Gif),
+            (b"RIFF\x24\0\0\0WEBPVP8 ", ImageFormat::Webp),
+            (BMP_HEADER, ImageFormat::Bmp),
+            (b"II*\0\x08\0\0\0", ImageFormat::Tiff),
+            (b"MM\0*\0\0\0\x08", ImageFormat::Tiff),
+            (b"\0\0\0\x18ftypheic\0\0\0\0", ImageFormat::Heic),
+        ];
+        for (data, format) in cases {
+            assert_eq!(sniff(data), Some(*format));
+        }
+    }
+
+    #[test]
+    /// Tests mapping image sizes to the closest aspect ratio.
+    fn test_aspect_ratio() {
+        assert_eq!(ImageSize::SQUARE.aspect_ratio(), "1:1");
+        assert_eq!(ImageSize::new(1792, 1024).aspect_ratio(), "16:9");
+        assert_eq!(ImageSize::new(1024, 1536).aspect_ratio(), "3:4");
+        assert_eq!(ImageSize::new(1536, 1024).to_string(), "1536x1024");
+    }
+
+    #[test]
+    /// Tests that unknown data is not recognized.
+    fn test_sniff_unknown() {
+        assert_eq!(sniff(b""), None);
+        assert_eq!(sniff(b"%PDF-1.7"), None);
+        assert_eq!(sniff(b"\0\0\0\x18ftypmp42"), None);
+        assert_eq!(sniff(b"BM\x36\0\0\0"), None);
+        assert_eq!(sniff(b"BMW owners manual, 2019 edition"), None);
+    }
+
+    #[test]
+    /// Tests that unsupported formats are rejected with a typed error.
+    fn test_detect() {
+        assert_eq!(detect(b"GIF87a\x01\0"), Ok(ImageFormat::Gif));
+        assert_eq!(
+            detect(BMP_HEADER),
+            Err(ImageError::Unsupported(ImageFormat::Bmp))
+        );
+        assert_eq!(detect(b"hello"), Err(ImageError::Unrecognized));
+    }
+
+    #[test]
+    /// Tests checking the number and size of images against the limits.
+    fn test_check_limits() {
+        let image = Image {
+            data: general_purpose::STANDARD.encode([0u8; 300]),
+            mime_type: "image/png".to_string(),
+            url: None,
+        };
+        let limits = ImageLimits {
+            max_dimension: None,
+            max_bytes: Some(200),
+            max_images: Some(1),
+        };
+
+        assert_eq!(
+            limits.check(&[image.clone(), image.clone()]),
+            Err(ImageError::TooMany { count: 2, max: 1 })
+        );
+        assert_eq!(
+            limits.check(&[image]),
+            Err(ImageError::TooLarge {
+                size: 300,
+                max: 200
+            })
+        );
+        assert_eq!(ImageLimits::default().check(&[]), Ok(()));
+    }
+
+    #[cfg(feature = "preprocess")]
+    fn encode_test_image(format: ::image::ImageFormat, width: u32, height: u32) -> Vec<u8> {
+        let image = ::image::DynamicImage::new_rgb8(width, height);
+        let mut buffer = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut buffer, format).unwrap();
+        buffer.into_inner()
+    }
+
+    #[cfg(feature = "preprocess")]
+    #[test]
+    /// Tests that BMP images are converted to JPEG and downsized to the maximum dimension.
+    fn test_preprocess_converts_and_resizes() {
+        let data = encode_test_image(::image::ImageFormat::Bmp, 400, 200);
+        let limits = ImageLimits {
+            max_dimension: Some(100),
+            ..Default::default()
+        };
+
+        let (data, format) = preprocess(&data, &limits).unwrap();
+        assert_eq!(format, ImageFormat::Jpeg);
+        let image = ::image::load_from_memory(&data).unwrap();
+        assert_eq!((image.width(), image.height()), (100, 50));
+    }
+
+    #[cfg(feature = "preprocess")]
+    #[test]
+    /// Tests that HEIC images are rejected with an error telling to convert them.
+    fn test_preprocess_rejects_heic() {
+        let data = b"\0\0\0\x18ftypheic\0\0\0\0";
+        assert_eq!(
+            preprocess(data, &ImageLimits::default()),
+            Err(ImageError::Heic)
+        );
+        assert_eq!(detect(data), Err(ImageError::Heic));
+        assert_eq!(detect_convertible(data), Err(ImageError::Heic));
+    }
+
+    #[test]
+    /// Tests refusing image URLs that are not HTTP or target non-public addresses.
+    fn test_check_url() {
+        let check = |url: &str| check_url(&Url::parse(url).unwrap());
+        assert!(check("https://example.com/cat.png").is_ok());
+        assert!(check("http://93.184.216.34/cat.png").is_ok());
+        assert!(check("file:///etc/passwd").is_err());
+        assert!(check("ftp://example.com/cat.png").is_err());
+        assert!(check("http://127.0.0.1/cat.png").is_err());
+        assert!(check("http://10.0.0.1/cat.png").is_err());
+        assert!(check("http://169.254.169.254/latest/meta-data/").is_err());
+        assert!(check("http://100.64.0.1/cat.png").is_err());
+        assert!(check("http://[::1]/cat.png").is_err());
+        assert!(check("http://[fd00::1]/cat.png").is_err());
+        assert!(check("http://[::ffff:192.168.0.1]/cat.png").is_err());
+    }
+
+    #[tokio::test]
+    /// Tests that host names resolving to non-public addresses are refused.
+    async fn test_download_refuses_localhost() {
+        let err = download("http://localhost:9/cat.png", MB)
+            .await
+            .unwrap_err();
+        assert!(format!("{err:?}").contains("not allowed"), "{err:?}");
+        let err = download("http://127.0.0.1:9/cat.png", MB)
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<ImageError>().is_some());
+    }
+}
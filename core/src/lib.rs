@@ -1,2 +1,10 @@
+pub mod audio;
+pub mod budget;
+pub mod cache;
 pub mod client;
-mod provider;
+pub mod document;
+pub mod image;
+pub mod limiter;
+pub mod models;
+pub mod provider;
+pub mod tokens;
@@ -0,0 +1,502 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose, Engine as _};
+use log::debug;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{
+    client::{Audio, Document, Message},
+    document,
+};
+
+/// Rough number of characters per token, used to estimate the size of a prompt.
+const CHARS_PER_TOKEN: usize = 4;
+/// Rough number of tokens billed for an image.
+pub(crate) const TOKENS_PER_IMAGE: usize = 1000;
+/// Upper range of the tokens billed per page of a PDF document, read as text and an image.
+const TOKENS_PER_PAGE: usize = 3000;
+/// Tokens reserved for a document referenced by URL, which is not downloaded to be estimated.
+const TOKENS_PER_DOCUMENT_URL: usize = 10 * TOKENS_PER_PAGE;
+/// Upper range of the tokens billed per second of audio.
+const TOKENS_PER_AUDIO_SECOND: usize = 32;
+/// Bytes per second of audio compressed at 64 kbit/s, low enough that the duration of most clips
+/// is overestimated rather than underestimated.
+const AUDIO_BYTES_PER_SECOND: usize = 8000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Represents the limits applied to a provider or model.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aipim::limiter::RateLimit;
+///
+/// let limit = RateLimit::new().requests_per_minute(500).tokens_per_minute(30_000);
+/// ```
+pub struct RateLimit {
+    requests_per_minute: Option<u32>,
+    tokens_per_minute: Option<u32>,
+}
+
+impl RateLimit {
+    /// Creates a new `RateLimit` instance without any limit.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of requests per minute.
+    ///
+    /// # Arguments
+    ///
+    /// * `requests` - The number of requests allowed per minute.
+    ///
+    pub fn requests_per_minute(self, requests: u32) -> Self {
+        Self {
+            requests_per_minute: Some(requests),
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of estimated tokens per minute.
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - The number of tokens allowed per minute.
+    ///
+    pub fn tokens_per_minute(self, tokens: u32) -> Self {
+        Self {
+            tokens_per_minute: Some(tokens),
+            ..self
+        }
+    }
+}
+
+/// The `RateLimiter` struct throttles the messages sent by a `Client`.
+///
+/// Requests and estimated tokens are tracked with token buckets, which refill continuously up to
+/// their per-minute limit. Limits are looked up by model name first, then by provider name (e.g.
+/// `openai`), then fall back to the default limit. Each limit has its own bucket, so the models
+/// of a provider limited as a whole share one, while the default limit applies to every provider
+/// and model separately. An optional semaphore caps the number of requests in flight.
+///
+/// The limiter is cheaply cloneable and clones share the same buckets, so the same limiter can
+/// be given to several clients.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aipim::client::Client;
+/// use aipim::limiter::{RateLimit, RateLimiter};
+///
+/// let limiter = RateLimiter::new()
+///     .limit("gpt-4o", RateLimit::new().requests_per_minute(500).tokens_per_minute(30_000))
+///     .default_limit(RateLimit::new().requests_per_minute(60))
+///     .max_concurrency(8);
+/// let client = Client::new("gpt-4o").unwrap().with_rate_limiter(limiter);
+/// ```
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    limits: HashMap<String, RateLimit>,
+    default: Option<RateLimit>,
+    concurrency: Option<Arc<Semaphore>>,
+    buckets: Arc<Mutex<HashMap<String, Buckets>>>,
+}
+
+/// A permit returned by `RateLimiter::acquire`, releasing its concurrency slot when dropped.
+pub struct Permit {
+    _concurrency: Option<OwnedSemaphorePermit>,
+}
+
+impl RateLimiter {
+    /// Creates a new `RateLimiter` instance without any limit.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the limit for a model or provider.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The name of a model (e.g. `gpt-4o`) or provider (e.g. `openai`).
+    /// * `limit` - The limit to apply.
+    ///
+    pub fn limit(mut self, key: impl Into<String>, limit: RateLimit) -> Self {
+        self.limits.insert(key.into(), limit);
+        self
+    }
+
+    /// Sets the limit used for models and providers without a specific limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The limit to apply.
+    ///
+    pub fn default_limit(self, limit: RateLimit) -> Self {
+        Self {
+            default: Some(limit),
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of requests in flight at any time.
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - The number of concurrent requests allowed.
+    ///
+    pub fn max_concurrency(self, max: usize) -> Self {
+        Self {
+            concurrency: Some(Arc::new(Semaphore::new(max))),
+            ..self
+        }
+    }
+
+    /// Waits until a request can be sent to the given provider and model.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The name of the provider.
+    /// * `model` - The name of the model, if known.
+    /// * `tokens` - The estimated number of tokens of the request.
+    ///
+    pub async fn acquire(&self, provider: &str, model: Option<&str>, tokens: usize) -> Permit {
+        let concurrency = match &self.concurrency {
+            Some(semaphore) => Some(
+                semaphore
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("rate limiter semaphore is never closed"),
+            ),
+            None => None,
+        };
+
+        if let Some((key, limit)) = self.limit_for(provider, model) {
+            loop {
+                let wait = {
+                    let mut buckets = self.buckets.lock().unwrap();
+                    let buckets = buckets
+                        .entry(key.clone())
+                        .or_insert_with(|| Buckets::new(limit));
+                    buckets.try_take(tokens, Instant::now())
+                };
+
+                match wait {
+                    None => break,
+                    Some(wait) => {
+                        debug!("Rate limit reached for {key}, waiting {wait:?}");
+                        tokio::time::sleep(wait).await;
+                    }
+                }
+            }
+        }
+
+        Permit {
+            _concurrency: concurrency,
+        }
+    }
+
+    /// Returns the limit applied to a provider and model, with the key of its bucket.
+    fn limit_for(&self, provider: &str, model: Option<&str>) -> Option<(String, RateLimit)> {
+        let configured = model
+            .into_iter()
+            .chain([provider])
+            .find_map(|key| Some((key.to_string(), *self.limits.get(key)?)));
+        configured.or_else(|| {
+            let key = match model {
+                Some(model) => format!("{provider}/{model}"),
+                None => provider.to_string(),
+            };
+            Some((key, self.default?))
+        })
+    }
+}
+
+/// Estimates the number of tokens of a message.
+///
+/// Documents and audio clips are estimated conservatively from their size: PDF documents by page
+/// when their pages can be counted, by byte otherwise, and audio clips from a low bitrate.
+///
+/// # Arguments
+///
+/// * `message` - The message to estimate.
+///
+pub fn estimate_tokens(message: &Message) -> usize {
+    let images = message.images.as_ref().map_or(0, Vec::len);
+    let history = message
+        .history
+        .iter()
+        .flatten()
+        .map(|turn| estimate_text_tokens(&turn.text()))
+        .sum::<usize>();
+    let system = message.system.as_deref().map_or(0, estimate_text_tokens);
+    let documents = message
+        .documents
+        .iter()
+        .flatten()
+        .map(estimate_document_tokens)
+        .sum::<usize>();
+    let audio = message
+        .audio
+        .iter()
+        .flatten()
+        .map(estimate_audio_tokens)
+        .sum::<usize>();
+    estimate_text_tokens(&message.text)
+        + system
+        + history
+        + images * TOKENS_PER_IMAGE
+        + documents
+        + audio
+}
+
+/// Estimates the number of tokens of a text.
+///
+/// # Arguments
+///
+/// * `text` - The text to estimate.
+///
+pub fn estimate_text_tokens(text: &str) -> usize {
+    text.len().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Estimates the number of tokens of a document, see `estimate_tokens`.
+fn estimate_document_tokens(document: &Document) -> usize {
+    if document.url.is_some() {
+        return TOKENS_PER_DOCUMENT_URL;
+    }
+
+    if document.mime_type == document::PDF {
+        let pages = general_purpose::STANDARD
+            .decode(&document.data)
+            .ok()
+            .and_then(|data| document::page_count(&data));
+        if let Some(pages) = pages {
+            return pages * TOKENS_PER_PAGE;
+        }
+    }
+    decoded_len(&document.data).div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Estimates the number of tokens of an audio clip, see `estimate_tokens`.
+fn estimate_audio_tokens(audio: &Audio) -> usize {
+    decoded_len(&audio.data).div_ceil(AUDIO_BYTES_PER_SECOND) * TOKENS_PER_AUDIO_SECOND
+}
+
+/// Returns the number of bytes encoded by base64 data, without decoding it.
+fn decoded_len(data: &str) -> usize {
+    data.len() / 4 * 3
+}
+
+/// The request and token buckets of a single limit.
+struct Buckets {
+    requests: Option<Bucket>,
+    tokens: Option<Bucket>,
+}
+
+impl Buckets {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            requests: limit.requests_per_minute.map(Bucket::per_minute),
+            tokens: limit.tokens_per_minute.map(Bucket::per_minute),
+        }
+    }
+
+    /// Takes one request and `tokens` tokens, or returns how long to wait before retrying.
+    fn try_take(&mut self, tokens: usize, now: Instant) -> Option<Duration> {
+        let requests_wait = self
+            .requests
+            .as_mut()
+            .and_then(|bucket| bucket.wait_for(1.0, now));
+        let tokens_wait = self
+            .tokens
+            .as_mut()
+            .and_then(|bucket| bucket.wait_for(tokens as f64, now));
+
+        match requests_wait.max(tokens_wait) {
+            Some(wait) => Some(wait),
+            None => {
+                if let Some(bucket) = self.requests.as_mut() {
+                    bucket.take(1.0);
+                }
+                if let Some(bucket) = self.tokens.as_mut() {
+                    bucket.take(tokens as f64);
+                }
+                None
+            }
+        }
+    }
+}
+
+/// A token bucket refilling continuously up to its capacity.
+struct Bucket {
+    capacity: f64,
+    available: f64,
+    refill_per_sec: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn per_minute(limit: u32) -> Self {
+        let capacity = limit.max(1) as f64;
+        Self {
+            capacity,
+            available: capacity,
+            refill_per_sec: capacity / 60.0,
+            updated_at: Instant::now(),
+        }
+    }
+
+    /// Refills the bucket and returns how long to wait until `amount` is available.
+    ///
+    /// Amounts larger than the capacity only wait for a full bucket, otherwise they would never
+    /// be satisfied.
+    fn wait_for(&mut self, amount: f64, now: Instant) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.available = (self.available + elapsed * self.refill_per_sec).min(self.capacity);
+        self.updated_at = now;
+
+        let missing = amount.min(self.capacity) - self.available;
+        if missing <= 0.0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(missing / self.refill_per_sec))
+        }
+    }
+
+    fn take(&mut self, amount: f64) {
+        self.available = (self.available - amount).max(0.0);
+    }
+}
+
+#[cfg(test)]
+/// Unit tests for the limiter module.
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Tests that the request bucket empties and refills over time.
+    fn test_request_bucket() {
+        let now = Instant::now();
+        let mut buckets = Buckets::new(RateLimit::new().requests_per_minute(2));
+        assert!(buckets.try_take(0, now).is_none());
+        assert!(buckets.try_take(0, now).is_none());
+
+        let wait = buckets.try_take(0, now).unwrap();
+        assert_eq!(wait.as_secs(), 30);
+        assert!(buckets.try_take(0, now + wait).is_none());
+    }
+
+    #[test]
+    /// Tests that the token bucket limits requests by their estimated tokens.
+    fn test_token_bucket() {
+        let now = Instant::now();
+        let mut buckets = Buckets::new(RateLimit::new().tokens_per_minute(600));
+        assert!(buckets.try_take(500, now).is_none());
+        assert_eq!(buckets.try_take(200, now).unwrap().as_secs(), 10);
+
+        // Oversized requests only wait for a full bucket.
+        assert_eq!(buckets.try_take(10_000, now).unwrap().as_secs(), 50);
+    }
+
+    #[test]
+    /// Tests that limits are looked up by model, then provider, then default.
+    fn test_limit_for() {
+        let model = RateLimit::new().requests_per_minute(1);
+        let provider = RateLimit::new().requests_per_minute(2);
+        let default = RateLimit::new().requests_per_minute(3);
+        let limiter = RateLimiter::new()
+            .limit("gpt-4o", model)
+            .limit("openai", provider)
+            .default_limit(default);
+
+        let limit_for = |provider, model| limiter.limit_for(provider, model);
+        assert_eq!(
+            limit_for("openai", Some("gpt-4o")),
+            Some(("gpt-4o".to_string(), model))
+        );
+        assert_eq!(
+            limit_for("openai", Some("gpt-4")),
+            Some(("openai".to_string(), provider))
+        );
+        assert_eq!(
+            limit_for("google", Some("gemini-1.5-pro")),
+            Some(("google/gemini-1.5-pro".to_string(), default))
+        );
+        assert_eq!(RateLimiter::new().limit_for("openai", None), None);
+    }
+
+    #[tokio::test]
+    /// Tests that the models of a provider limited as a whole share its bucket.
+    async fn test_provider_bucket() {
+        let limiter = RateLimiter::new().limit("openai", RateLimit::new().requests_per_minute(1));
+        limiter.acquire("openai", Some("gpt-4o"), 0).await;
+
+        let pending = tokio::time::timeout(
+            Duration::from_millis(20),
+            limiter.acquire("openai", Some("gpt-4"), 0),
+        )
+        .await;
+        assert!(pending.is_err());
+        limiter.acquire("google", Some("gemini-1.5-pro"), 0).await;
+    }
+
+    #[test]
+    /// Tests estimating the tokens of a message.
+    fn test_estimate_tokens() {
+        let message = Message {
+            text: "a".repeat(10),
+            images: None,
+            ..Default::default()
+        };
+        assert_eq!(estimate_tokens(&message), 3);
+
+        let encode = |data: &[u8]| general_purpose::STANDARD.encode(data);
+        let document = |data: &[u8], mime_type: &str| Document {
+            data: encode(data),
+            mime_type: mime_type.to_string(),
+            url: None,
+            name: None,
+            cache: false,
+        };
+        let message = Message {
+            documents: Some(vec![
+                document(
+                    b"%PDF-1.4 /Type /Pages /Type /Page /Type /Page",
+                    document::PDF,
+                ),
+                document(&[b'a'; 12], document::TEXT),
+            ]),
+            audio: Some(vec![Audio {
+                data: encode(&[0; 12_000]),
+                mime_type: "audio/mpeg".to_string(),
+            }]),
+            ..Default::default()
+        };
+        assert_eq!(
+            estimate_tokens(&message),
+            2 * TOKENS_PER_PAGE + 3 + 2 * TOKENS_PER_AUDIO_SECOND
+        );
+    }
+
+    #[tokio::test]
+    /// Tests that the concurrency limit holds requests until a permit is released.
+    async fn test_max_concurrency() {
+        let limiter = RateLimiter::new().max_concurrency(1);
+        let permit = limiter.acquire("openai", None, 0).await;
+
+        let pending = tokio::time::timeout(
+            Duration::from_millis(20),
+            limiter.acquire("openai", None, 0),
+        )
+        .await;
+        assert!(pending.is_err());
+
+        drop(permit);
+        limiter.acquire("openai", None, 0).await;
+    }
+}
@@ -0,0 +1,390 @@
+use serde::Serialize;
+
+use crate::{
+    client::Usage,
+    provider::{Anthropic, Google, OpenAI},
+};
+
+const OPENAI: &str = OpenAI::NAME;
+const ANTHROPIC: &str = Anthropic::NAME;
+const GOOGLE: &str = Google::NAME;
+
+/// The `ModelInfo` struct describes a model of the catalog: its provider, limits, capabilities
+/// and pricing.
+///
+/// # Examples
+///
+/// ```
+/// use aipim::models;
+///
+/// let info = models::info("gpt-4o-2024-05-13").unwrap();
+/// assert_eq!(info.provider, "openai");
+/// assert_eq!(info.context_window, 128_000);
+/// assert!(info.capabilities.vision);
+/// ```
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct ModelInfo {
+    /// The name of the model, shared by its dated versions.
+    pub name: &'static str,
+    /// The name of the provider serving the model, e.g. `openai`.
+    pub provider: &'static str,
+    /// The maximum number of tokens of a request, prompt and response included.
+    pub context_window: usize,
+    /// The maximum number of tokens of a response.
+    pub max_output_tokens: usize,
+    pub capabilities: Capabilities,
+    /// The price of the model, in US dollars per million tokens.
+    pub pricing: Pricing,
+    /// The date the model is retired by its provider, formatted as `YYYY-MM-DD`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deprecation_date: Option<&'static str>,
+}
+
+/// The `Capabilities` struct lists the features supported by a model.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Whether the model accepts images.
+    pub vision: bool,
+    /// Whether the model can call tools.
+    pub tools: bool,
+    /// Whether the model can be constrained to answer in JSON, see `ResponseFormat`.
+    pub json: bool,
+    /// Whether the model can stream its responses.
+    pub streaming: bool,
+    /// Whether the model reasons before answering, accepting `client::Reasoning` settings.
+    pub reasoning: bool,
+}
+
+/// The `Pricing` struct holds the prices of a model, in US dollars per million tokens.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct Pricing {
+    /// The price of input tokens.
+    pub input: f64,
+    /// The price of output tokens, reasoning tokens included.
+    pub output: f64,
+    /// The price of input tokens read from the prompt cache, if it is discounted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cached_input: Option<f64>,
+    /// The price of input tokens written to the prompt cache, if it is charged extra.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_write: Option<f64>,
+}
+
+impl Pricing {
+    /// Returns the cost of a response, in US dollars.
+    ///
+    /// Cached input tokens are charged at their discounted price and cache writes at their
+    /// extra price when the model has them, otherwise at the input price.
+    ///
+    /// # Arguments
+    ///
+    /// * `usage` - The tokens billed for the response.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aipim::{client::Usage, models};
+    ///
+    /// let pricing = models::info("gpt-4o").unwrap().pricing;
+    /// let usage = Usage {
+    ///     input_tokens: 1_000_000,
+    ///     output_tokens: 100_000,
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(pricing.cost(&usage), 3.5);
+    /// ```
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        let cache_read = usage.cache_read_input_tokens.unwrap_or(0);
+        let cache_write = usage.cache_creation_input_tokens.unwrap_or(0);
+        let uncached = usage
+            .input_tokens
+            .saturating_sub(cache_read)
+            .saturating_sub(cache_write);
+
+        let dollars = uncached as f64 * self.input
+            + cache_read as f64 * self.cached_input.unwrap_or(self.input)
+            + cache_write as f64 * self.cache_write.unwrap_or(self.input)
+            + usage.output_tokens as f64 * self.output;
+        dollars / 1_000_000.0
+    }
+}
+
+impl ModelInfo {
+    const fn new(
+        name: &'static str,
+        provider: &'static str,
+        context_window: usize,
+        max_output_tokens: usize,
+        input: f64,
+        output: f64,
+    ) -> Self {
+        Self {
+            name,
+            provider,
+            context_window,
+            max_output_tokens,
+            capabilities: Capabilities {
+                vision: true,
+                tools: true,
+                json: true,
+                streaming: true,
+                reasoning: false,
+            },
+            pricing: Pricing {
+                input,
+                output,
+                cached_input: None,
+                cache_write: None,
+            },
+            deprecation_date: None,
+        }
+    }
+
+    const fn cached_input(mut self, price: f64) -> Self {
+        self.pricing.cached_input = Some(price);
+        self
+    }
+
+    /// Sets the prices of Anthropic prompt caching, which charges writes 1.25 times the input
+    /// price and reads a tenth of it.
+    const fn anthropic_caching(mut self) -> Self {
+        self.pricing.cached_input = Some(self.pricing.input / 10.0);
+        self.pricing.cache_write = Some(self.pricing.input * 1.25);
+        self
+    }
+
+    const fn without_vision(mut self) -> Self {
+        self.capabilities.vision = false;
+        self
+    }
+
+    const fn without_tools(mut self) -> Self {
+        self.capabilities.tools = false;
+        self
+    }
+
+    const fn without_json(mut self) -> Self {
+        self.capabilities.json = false;
+        self
+    }
+
+    const fn with_reasoning(mut self) -> Self {
+        self.capabilities.reasoning = true;
+        self
+    }
+
+    const fn deprecated(mut self, date: &'static str) -> Self {
+        self.deprecation_date = Some(date);
+        self
+    }
+}
+
+/// The catalog of the models served by the built-in providers.
+const MODELS: &[ModelInfo] = &[
+    // OpenAI
+    ModelInfo::new("gpt-4.1", OPENAI, 1_047_576, 32_768, 2.0, 8.0).cached_input(0.5),
+    ModelInfo::new("gpt-4.1-mini", OPENAI, 1_047_576, 32_768, 0.4, 1.6).cached_input(0.1),
+    ModelInfo::new("gpt-4.1-nano", OPENAI, 1_047_576, 32_768, 0.1, 0.4).cached_input(0.025),
+    ModelInfo::new("gpt-4o", OPENAI, 128_000, 16_384, 2.5, 10.0).cached_input(1.25),
+    ModelInfo::new("gpt-4o-mini", OPENAI, 128_000, 16_384, 0.15, 0.6).cached_input(0.075),
+    ModelInfo::new("gpt-4-turbo", OPENAI, 128_000, 4_096, 10.0, 30.0),
+    ModelInfo::new("gpt-4", OPENAI, 8_192, 8_192, 30.0, 60.0)
+        .without_vision()
+        .without_json(),
+    ModelInfo::new("gpt-3.5-turbo", OPENAI, 16_385, 4_096, 0.5, 1.5).without_vision(),
+    ModelInfo::new("o1", OPENAI, 200_000, 100_000, 15.0, 60.0)
+        .cached_input(7.5)
+        .with_reasoning(),
+    ModelInfo::new("o1-mini", OPENAI, 128_000, 65_536, 1.1, 4.4)
+        .cached_input(0.55)
+        .with_reasoning()
+        .without_vision()
+        .without_tools()
+        .without_json(),
+    ModelInfo::new("o3", OPENAI, 200_000, 100_000, 2.0, 8.0)
+        .cached_input(0.5)
+        .with_reasoning(),
+    ModelInfo::new("o3-mini", OPENAI, 200_000, 100_000, 1.1, 4.4)
+        .cached_input(0.55)
+        .without_vision()
+        .with_reasoning(),
+    ModelInfo::new("o4-mini", OPENAI, 200_000, 100_000, 1.1, 4.4)
+        .cached_input(0.275)
+        .with_reasoning(),
+    // Anthropic, which has no JSON mode
+    ModelInfo::new("claude-opus-4", ANTHROPIC, 200_000, 32_000, 15.0, 75.0)
+        .anthropic_caching()
+        .without_json()
+        .with_reasoning(),
+    ModelInfo::new("claude-sonnet-4", ANTHROPIC, 200_000, 64_000, 3.0, 15.0)
+        .anthropic_caching()
+        .without_json()
+        .with_reasoning(),
+    ModelInfo::new("claude-3-7-sonnet", ANTHROPIC, 200_000, 64_000, 3.0, 15.0)
+        .anthropic_caching()
+        .without_json()
+        .with_reasoning(),
+    ModelInfo::new("claude-3-5-sonnet", ANTHROPIC, 200_000, 8_192, 3.0, 15.0)
+        .anthropic_caching()
+        .without_json()
+        .deprecated("2025-10-22"),
+    ModelInfo::new("claude-3-5-haiku", ANTHROPIC, 200_000, 8_192, 0.8, 4.0)
+        .anthropic_caching()
+        .without_json(),
+    ModelInfo::new("claude-3-opus", ANTHROPIC, 200_000, 4_096, 15.0, 75.0)
+        .anthropic_caching()
+        .without_json()
+        .deprecated("2026-01-05"),
+    ModelInfo::new("claude-3-sonnet", ANTHROPIC, 200_000, 4_096, 3.0, 15.0)
+        .without_json()
+        .deprecated("2025-07-21"),
+    ModelInfo::new("claude-3-haiku", ANTHROPIC, 200_000, 4_096, 0.25, 1.25)
+        .anthropic_caching()
+        .without_json(),
+    // Google, with the prices of prompts up to 200k tokens
+    ModelInfo::new("gemini-2.5-pro", GOOGLE, 1_048_576, 65_536, 1.25, 10.0)
+        .cached_input(0.31)
+        .with_reasoning(),
+    ModelInfo::new("gemini-2.5-flash", GOOGLE, 1_048_576, 65_536, 0.3, 2.5)
+        .cached_input(0.075)
+        .with_reasoning(),
+    ModelInfo::new("gemini-2.0-flash", GOOGLE, 1_048_576, 8_192, 0.1, 0.4).cached_input(0.025),
+    ModelInfo::new(
+        "gemini-2.0-flash-lite",
+        GOOGLE,
+        1_048_576,
+        8_192,
+        0.075,
+        0.3,
+    ),
+    ModelInfo::new("gemini-1.5-pro", GOOGLE, 2_097_152, 8_192, 1.25, 5.0)
+        .cached_input(0.3125)
+        .deprecated("2025-09-24"),
+    ModelInfo::new("gemini-1.5-flash", GOOGLE, 1_048_576, 8_192, 0.075, 0.3)
+        .cached_input(0.01875)
+        .deprecated("2025-09-24"),
+];
+
+/// Returns every model of the catalog.
+pub fn all() -> &'static [ModelInfo] {
+    MODELS
+}
+
+/// Returns the models of the catalog served by the given provider.
+///
+/// # Arguments
+///
+/// * `provider` - The name of the provider, e.g. `anthropic`.
+///
+pub fn for_provider(provider: &str) -> impl Iterator<Item = &'static ModelInfo> + '_ {
+    MODELS.iter().filter(move |info| info.provider == provider)
+}
+
+/// Returns `true` if a model accepts reasoning settings, see `client::Reasoning`.
+///
+/// Models missing from the catalog are assumed to, leaving it to their provider to reject them.
+///
+/// # Arguments
+///
+/// * `model` - A string slice that holds the name of the model.
+///
+pub fn supports_reasoning(model: &str) -> bool {
+    info(model).is_none_or(|info| info.capabilities.reasoning)
+}
+
+/// Returns the catalog entry of a model, or `None` if the model is unknown.
+///
+/// Dated or suffixed versions (e.g. `claude-3-5-sonnet-20240620`) match the longest known name
+/// they start with.
+///
+/// # Arguments
+///
+/// * `model` - A string slice that holds the name of the model.
+///
+pub fn info(model: &str) -> Option<&'static ModelInfo> {
+    MODELS
+        .iter()
+        .filter(|info| match model.strip_prefix(info.name) {
+            Some(rest) => rest.is_empty() || rest.starts_with('-'),
+            None => false,
+        })
+        .max_by_key(|info| info.name.len())
+}
+
+#[cfg(test)]
+/// Unit tests for the models module.
+mod tests {
+    use super::*;
+    use crate::provider;
+
+    #[test]
+    /// Tests looking up models by name and by dated version.
+    fn test_info() {
+        assert_eq!(info("gpt-4o").unwrap().name, "gpt-4o");
+        assert_eq!(info("gpt-4o-mini-2024-07-18").unwrap().name, "gpt-4o-mini");
+        assert_eq!(info("gpt-4-0613").unwrap().name, "gpt-4");
+        assert_eq!(
+            info("claude-3-5-sonnet-20240620")
+                .unwrap()
+                .max_output_tokens,
+            8_192
+        );
+        assert_eq!(
+            info("gemini-2.0-flash-001").unwrap().name,
+            "gemini-2.0-flash"
+        );
+        assert!(info("gpt-4omni").is_none());
+        assert!(info("llama3.1").is_none());
+    }
+
+    #[test]
+    /// Tests that every model of the catalog belongs to the provider serving it.
+    fn test_catalog_vendors() {
+        for info in all() {
+            assert_eq!(
+                provider::vendor(info.name),
+                Some(info.provider),
+                "{}",
+                info.name
+            );
+        }
+        assert!(for_provider(Google::NAME).all(|info| info.name.starts_with("gemini")));
+    }
+
+    #[test]
+    /// Tests which models accept reasoning settings.
+    fn test_supports_reasoning() {
+        assert!(supports_reasoning("o3-mini"));
+        assert!(supports_reasoning("claude-3-7-sonnet-20250219"));
+        assert!(supports_reasoning("gemini-2.5-flash"));
+        assert!(!supports_reasoning("gpt-4o"));
+        assert!(!supports_reasoning("claude-3-5-haiku-20241022"));
+        assert!(!supports_reasoning("gemini-2.0-flash"));
+        assert!(supports_reasoning("claude-unreleased"));
+    }
+
+    #[test]
+    /// Tests the prices of Anthropic prompt caching.
+    fn test_anthropic_caching() {
+        let pricing = info("claude-3-5-haiku-20241022").unwrap().pricing;
+        assert_eq!(pricing.cached_input, Some(0.08));
+        assert_eq!(pricing.cache_write, Some(1.0));
+    }
+
+    #[test]
+    /// Tests pricing cache reads and writes apart from the other input tokens.
+    fn test_cost() {
+        let usage = Usage {
+            input_tokens: 3_000_000,
+            output_tokens: 1_000_000,
+            cache_creation_input_tokens: Some(1_000_000),
+            cache_read_input_tokens: Some(1_000_000),
+            ..Default::default()
+        };
+        let cost = info("claude-3-haiku").unwrap().pricing.cost(&usage);
+        assert!((cost - (0.25 + 0.3125 + 0.025 + 1.25)).abs() < 1e-9);
+
+        let cost = info("gpt-4-turbo").unwrap().pricing.cost(&usage);
+        assert!((cost - 60.0).abs() < 1e-9);
+    }
+}
@@ -4,17 +4,20 @@ use log::{debug, trace};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
-use super::AIProvider;
-use crate::client;
+use super::{read_json, AIProvider, ApiError};
+use crate::{
+    client::{self, Timeouts},
+    document,
+    image::ImageLimits,
+    models,
+};
 
 const MAX_TOKENS: u32 = 1024;
+/// The smallest budget of thinking tokens the API accepts.
+const MIN_THINKING_BUDGET: u32 = 1024;
 const ANTRHOPIC_VERSION: &str = "2023-06-01";
-const MODELS: &[&str] = &[
-    "claude-3-5-sonnet-20240620",
-    "claude-3-opus-20240229",
-    "claude-3-sonnet-20240229",
-    "claude-3-haiku-20240307",
-];
+/// The model used by `Anthropic::default`, see `models` for the catalog.
+const DEFAULT_MODEL: &str = "claude-3-5-sonnet-20240620";
 
 const BASE_URL: &str = "https://api.anthropic.com/v1/";
 
@@ -26,6 +29,11 @@ pub struct Anthropic {
 }
 
 impl Anthropic {
+    /// The name of the provider, as returned by `AIProvider::name`.
+    pub const NAME: &'static str = "anthropic";
+    /// The environment variable holding the default API key.
+    pub const API_KEY_ENV: &'static str = "ANTHROPIC_API_KEY";
+
     /// Creates a new `Anthropic` instance.
     ///
     /// # Arguments
@@ -35,7 +43,7 @@ impl Anthropic {
     ///
     pub fn new(api_key: impl Into<String>, model: impl Into<String>) -> Self {
         Self {
-            client: Client::new(),
+            client: Timeouts::default().http_client(),
             api_key: api_key.into(),
             model: model.into(),
         }
@@ -53,6 +61,16 @@ impl Anthropic {
             ..self
         }
     }
+
+    /// Sets the HTTP client used by the `Anthropic` instance, e.g. one built with custom timeouts.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` instance.
+    ///
+    pub fn with_http_client(self, client: Client) -> Self {
+        Self { client, ..self }
+    }
 }
 
 impl Default for Anthropic {
@@ -65,7 +83,7 @@ impl Default for Anthropic {
     fn default() -> Self {
         Self::new(
             std::env::var("ANTHROPIC_API_KEY").expect("ANTHROPIC_API_KEY is not set"),
-            MODELS[0],
+            DEFAULT_MODEL,
         )
     }
 }
@@ -83,32 +101,10 @@ impl AIProvider for Anthropic {
     /// Returns an error if the request fails or the response contains an error.
     ///
     async fn send_message(&self, message: client::Message) -> anyhow::Result<client::Response> {
-        let mut content = vec![Content::Text(Text {
-            typ: "text".to_string(),
-            text: message.text,
-        })];
-
-        for image in message.images.unwrap_or_default() {
-            content.push(Content::Image(Image {
-                typ: "image".to_string(),
-                source: ImageData {
-                    typ: "base64".to_string(),
-                    media_type: image.mime_type,
-                    data: image.data,
-                },
-            }));
-        }
-
-        let messages = vec![ChatMessage {
-            role: "user".to_string(),
-            content,
-        }];
-
-        let request = Request {
-            model: self.model.clone(),
-            max_tokens: MAX_TOKENS as usize,
-            messages,
-        };
+        message.options.check_single_candidate(Self::NAME)?;
+        message.options.check_no_logprobs(Self::NAME)?;
+        let model = message.model.clone().unwrap_or_else(|| self.model.clone());
+        let request = build_request(message, model)?;
 
         trace!(
             "JSON Request: {}",
@@ -126,7 +122,7 @@ impl AIProvider for Anthropic {
             .send()
             .await?;
 
-        let response: serde_json::Value = response.json().await?;
+        let (status, response) = read_json(response).await?;
         trace!(
             "JSON Response: {}",
             serde_json::to_string_pretty(&response).unwrap()
@@ -135,22 +131,278 @@ impl AIProvider for Anthropic {
         let response = serde_json::from_value::<Response>(response)?;
         debug!("Anthropic Response: {:#?}", response);
 
-        if response.is_error() {
-            return Err(anyhow::anyhow!(response.error().error.message.clone()));
+        match response {
+            Response::Message(message) => {
+                let usage = client::Usage::from(&message.usage);
+                let id = message.id.clone();
+                Ok(
+                    client::Response::from_candidates(vec![message.into_candidate()])
+                        .with_id(id)
+                        .with_usage(usage),
+                )
+            }
+            Response::Error(error) => Err(ApiError::new(status, error.error.message).into()),
+        }
+    }
+
+    /// Counts the input tokens of a message with the `messages/count_tokens` endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - A `client::Message` instance containing the message to be counted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response contains an error.
+    ///
+    async fn count_tokens(&self, message: client::Message) -> anyhow::Result<usize> {
+        let model = message.model.clone().unwrap_or_else(|| self.model.clone());
+        let request = CountTokensRequest::from(build_request(message, model)?);
+        let response = self
+            .client
+            .post(format!("{}messages/count_tokens", BASE_URL))
+            .header("Content-Type", "application/json")
+            .header("anthropic-version", ANTRHOPIC_VERSION)
+            .header("x-api-key", &self.api_key)
+            .json(&request)
+            .send()
+            .await?;
+
+        let (status, response) = read_json(response).await?;
+        match serde_json::from_value::<CountTokensResponse>(response)? {
+            CountTokensResponse::Count { input_tokens } => Ok(input_tokens),
+            CountTokensResponse::Error(error) => {
+                Err(ApiError::new(status, error.error.message).into())
+            }
         }
+    }
 
-        Ok(client::Response::new(response.text()))
+    /// Lists the models of the `models` endpoint, following its pages.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a request fails or a response contains an error.
+    ///
+    async fn list_models(&self) -> anyhow::Result<Vec<client::AvailableModel>> {
+        let mut models = Vec::new();
+        let mut after_id = None;
+        loop {
+            let mut request = self
+                .client
+                .get(format!("{}models", BASE_URL))
+                .query(&[("limit", "1000")])
+                .header("anthropic-version", ANTRHOPIC_VERSION)
+                .header("x-api-key", &self.api_key);
+            if let Some(after_id) = &after_id {
+                request = request.query(&[("after_id", after_id)]);
+            }
+
+            let (status, response) = read_json(request.send().await?).await?;
+            let page = match serde_json::from_value::<ModelsResponse>(response)? {
+                ModelsResponse::Page(page) => page,
+                ModelsResponse::Error(error) => {
+                    return Err(ApiError::new(status, error.error.message).into())
+                }
+            };
+
+            models.extend(page.data.into_iter().map(|model| {
+                client::AvailableModel::new(model.id, Self::NAME)
+                    .with_display_name(model.display_name)
+            }));
+            match page.last_id {
+                Some(last_id) if page.has_more => after_id = Some(last_id),
+                _ => return Ok(models),
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn model(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+
+    fn supports_image_urls(&self) -> bool {
+        true
+    }
+
+    fn image_limits(&self) -> ImageLimits {
+        ImageLimits::ANTHROPIC
+    }
+
+    fn max_tokens(&self, _model: &str) -> Option<usize> {
+        Some(MAX_TOKENS as usize)
     }
 }
 
-unsafe impl Send for Anthropic {}
-unsafe impl Sync for Anthropic {}
+/// Builds the request sending a message to the given model.
+fn build_request(message: client::Message, model: String) -> anyhow::Result<Request> {
+    let mut content = vec![Content::Text(Text {
+        typ: "text".to_string(),
+        text: message.text,
+        cache_control: CacheControl::when(message.cache_text),
+    })];
+
+    for image in message.images.unwrap_or_default() {
+        content.push(Content::Image(Image {
+            typ: "image".to_string(),
+            source: ImageData::from(image),
+        }));
+    }
+
+    if message
+        .audio
+        .as_ref()
+        .is_some_and(|audio| !audio.is_empty())
+    {
+        anyhow::bail!("Anthropic models do not accept audio");
+    }
+
+    // Documents are placed before the prompt, as recommended for long documents.
+    for (index, doc) in message
+        .documents
+        .unwrap_or_default()
+        .into_iter()
+        .enumerate()
+    {
+        content.insert(
+            index,
+            Content::Document(Document {
+                typ: "document".to_string(),
+                cache_control: CacheControl::when(doc.cache),
+                source: DocumentSource::try_from(doc)?,
+            }),
+        );
+    }
+
+    let mut messages = message
+        .history
+        .unwrap_or_default()
+        .into_iter()
+        .map(ChatMessage::from)
+        .collect::<Vec<_>>();
+    messages.push(ChatMessage {
+        role: "user".to_string(),
+        content,
+    });
+
+    // The budget of thinking tokens counts towards `max_tokens`, so it has to be added to it.
+    // Models without extended thinking reject it, so it is dropped for them.
+    let reasoning = message
+        .options
+        .reasoning
+        .filter(|_| models::supports_reasoning(&model));
+    let thinking = reasoning.map(|reasoning| Thinking {
+        typ: "enabled".to_string(),
+        budget_tokens: reasoning.budget_tokens().max(MIN_THINKING_BUDGET),
+    });
+    let max_tokens = MAX_TOKENS.saturating_add(thinking.as_ref().map_or(0, |t| t.budget_tokens));
+
+    let system = message.system.map(|system| {
+        vec![Text {
+            typ: "text".to_string(),
+            text: system,
+            cache_control: CacheControl::when(message.cache_system),
+        }]
+    });
+
+    Ok(Request {
+        model,
+        max_tokens: max_tokens as usize,
+        system,
+        messages,
+        thinking,
+    })
+}
 
 #[derive(Serialize, Debug)]
 struct Request {
     model: String,
     max_tokens: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<Vec<Text>>,
     messages: Vec<ChatMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<Thinking>,
+}
+
+#[derive(Serialize, Debug)]
+/// Represents a request to the `messages/count_tokens` endpoint, which takes no `max_tokens`.
+struct CountTokensRequest {
+    model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<Vec<Text>>,
+    messages: Vec<ChatMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<Thinking>,
+}
+
+impl From<Request> for CountTokensRequest {
+    fn from(request: Request) -> Self {
+        Self {
+            model: request.model,
+            system: request.system,
+            messages: request.messages,
+            thinking: request.thinking,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+/// Represents a response from the `models` endpoint.
+enum ModelsResponse {
+    Page(ModelsPage),
+    Error(Error),
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents a page of models, most recent first.
+struct ModelsPage {
+    data: Vec<Model>,
+    has_more: bool,
+    last_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents a model listed by the `models` endpoint.
+struct Model {
+    id: String,
+    display_name: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+/// Represents a response from the `messages/count_tokens` endpoint.
+enum CountTokensResponse {
+    Count { input_tokens: usize },
+    Error(Error),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+/// Marks a content block as a prompt cache breakpoint.
+struct CacheControl {
+    #[serde(rename = "type")]
+    typ: String,
+}
+
+impl CacheControl {
+    /// Returns an ephemeral cache breakpoint if `cache` is set.
+    fn when(cache: bool) -> Option<Self> {
+        cache.then(|| Self {
+            typ: "ephemeral".to_string(),
+        })
+    }
+}
+
+#[derive(Serialize, Debug)]
+/// Enables extended thinking with a budget of tokens.
+struct Thinking {
+    #[serde(rename = "type")]
+    typ: String,
+    budget_tokens: u32,
 }
 
 #[derive(Serialize, Debug)]
@@ -159,21 +411,57 @@ struct ChatMessage {
     content: Vec<Content>,
 }
 
+impl From<client::Turn> for ChatMessage {
+    /// Replays a previous turn, sending signed thinking blocks back unchanged as the API
+    /// requires. Thinking blocks without a signature come from other providers and are dropped.
+    fn from(turn: client::Turn) -> Self {
+        let role = match turn.role {
+            client::Role::User => "user",
+            client::Role::Assistant => "assistant",
+        };
+        let content = turn
+            .content
+            .into_iter()
+            .filter_map(|block| match block {
+                client::ContentBlock::Text { text } => Some(Content::Text(Text {
+                    typ: "text".to_string(),
+                    text,
+                    cache_control: None,
+                })),
+                client::ContentBlock::Thinking {
+                    text,
+                    signature: Some(signature),
+                } => Some(Content::Thinking(ThinkingBlock {
+                    typ: "thinking".to_string(),
+                    thinking: text,
+                    signature,
+                })),
+                _ => None,
+            })
+            .collect();
+
+        Self {
+            role: role.to_string(),
+            content,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(untagged)]
 enum Content {
     Text(Text),
     Image(Image),
+    Document(Document),
+    Thinking(ThinkingBlock),
 }
 
-impl Content {
-    /// Returns the text content of the `Content` instance.
-    fn as_text(&self) -> String {
-        match self {
-            Content::Text(c) => c.text.clone(),
-            Content::Image(_) => "[image]".to_string(),
-        }
-    }
+#[derive(Serialize, Deserialize, Debug)]
+struct ThinkingBlock {
+    #[serde(rename = "type")]
+    typ: String,
+    thinking: String,
+    signature: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -181,6 +469,8 @@ struct Text {
     #[serde(rename = "type")]
     typ: String,
     text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_control: Option<CacheControl>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -194,8 +484,88 @@ struct Image {
 struct ImageData {
     #[serde(rename = "type")]
     typ: String,
-    media_type: String,
-    data: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    media_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
+
+impl From<client::Image> for ImageData {
+    fn from(image: client::Image) -> Self {
+        match image.url {
+            Some(url) => Self {
+                typ: "url".to_string(),
+                media_type: None,
+                data: None,
+                url: Some(url),
+            },
+            None => Self {
+                typ: "base64".to_string(),
+                media_type: Some(image.mime_type),
+                data: Some(image.data),
+                url: None,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Document {
+    #[serde(rename = "type")]
+    typ: String,
+    source: DocumentSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_control: Option<CacheControl>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct DocumentSource {
+    #[serde(rename = "type")]
+    typ: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    media_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
+
+impl TryFrom<client::Document> for DocumentSource {
+    type Error = anyhow::Error;
+
+    /// Sends PDF documents as is or by URL, and text documents as plain text.
+    fn try_from(doc: client::Document) -> anyhow::Result<Self> {
+        if let Some(url) = doc.url {
+            return Ok(Self {
+                typ: "url".to_string(),
+                media_type: None,
+                data: None,
+                url: Some(url),
+            });
+        }
+
+        if document::is_text(&doc.mime_type) {
+            return Ok(Self {
+                typ: "text".to_string(),
+                media_type: Some(document::TEXT.to_string()),
+                data: Some(document::text(&doc)?),
+                url: None,
+            });
+        }
+
+        if doc.mime_type != document::PDF {
+            anyhow::bail!("unsupported document type: {}", doc.mime_type);
+        }
+
+        Ok(Self {
+            typ: "base64".to_string(),
+            media_type: Some(doc.mime_type),
+            data: Some(doc.data),
+            url: None,
+        })
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -222,14 +592,6 @@ impl Response {
             _ => panic!("Response is not an error"),
         }
     }
-
-    /// Returns the text content of the response.
-    pub fn text(&self) -> String {
-        match self {
-            Response::Message(message) => message.content[0].as_text(),
-            _ => panic!("Response is not a message"),
-        }
-    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -242,13 +604,89 @@ struct Message {
     stop_reason: String,
     stop_sequence: Option<String>,
     usage: Usage,
-    content: Vec<Content>,
+    content: Vec<ResponseContent>,
+}
+
+impl Message {
+    /// Converts the message to a candidate, keeping its content blocks in order.
+    fn into_candidate(self) -> client::Candidate {
+        client::Candidate {
+            content: self
+                .content
+                .into_iter()
+                .filter_map(ResponseContent::into_block)
+                .collect(),
+            finish_reason: Some(self.stop_reason),
+            safety_ratings: Vec::new(),
+            logprobs: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// Represents a content block of a response.
+enum ResponseContent {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    Thinking {
+        thinking: String,
+        signature: Option<String>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+impl ResponseContent {
+    /// Converts the block to a content block, skipping unknown blocks.
+    fn into_block(self) -> Option<client::ContentBlock> {
+        match self {
+            ResponseContent::Text { text } => Some(client::ContentBlock::Text { text }),
+            ResponseContent::ToolUse { id, name, input } => Some(client::ContentBlock::ToolCall {
+                id: Some(id),
+                name,
+                arguments: input,
+            }),
+            ResponseContent::Thinking {
+                thinking,
+                signature,
+            } => Some(client::ContentBlock::Thinking {
+                text: thinking,
+                signature,
+            }),
+            ResponseContent::Other => None,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
 struct Usage {
     input_tokens: u32,
     output_tokens: u32,
+    cache_creation_input_tokens: Option<u32>,
+    cache_read_input_tokens: Option<u32>,
+}
+
+impl From<&Usage> for client::Usage {
+    /// Anthropic counts thinking tokens as output tokens without reporting them separately, and
+    /// reports cached tokens apart from the input tokens, so they are added up.
+    fn from(usage: &Usage) -> Self {
+        let cache_creation = usage.cache_creation_input_tokens.unwrap_or_default();
+        let cache_read = usage.cache_read_input_tokens.unwrap_or_default();
+        Self {
+            input_tokens: (usage.input_tokens + cache_creation + cache_read) as usize,
+            output_tokens: usage.output_tokens as usize,
+            reasoning_tokens: None,
+            cache_creation_input_tokens: usage.cache_creation_input_tokens.map(|t| t as usize),
+            cache_read_input_tokens: usage.cache_read_input_tokens.map(|t| t as usize),
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -298,6 +736,254 @@ mod tests {
         assert!(!response.is_error());
     }
 
+    #[test]
+    fn test_content_blocks() {
+        /// Tests reading every content block of a response in order.
+        let response = serde_json::from_str::<Response>(
+            r#"
+                {
+                  "content": [
+                    { "type": "thinking", "thinking": "Needs the weather.", "signature": "EqQB" },
+                    { "type": "text", "text": "Let me check " },
+                    { "type": "text", "text": "the weather." },
+                    { "type": "tool_use", "id": "toolu_01", "name": "weather", "input": { "city": "Lisbon" } }
+                  ],
+                  "id": "msg_013Zva2CMHLNnXjNJJKqJ2EF",
+                  "model": "claude-3-5-sonnet-20240620",
+                  "role": "assistant",
+                  "stop_reason": "tool_use",
+                  "stop_sequence": null,
+                  "type": "message",
+                  "usage": { "input_tokens": 10, "output_tokens": 25 }
+                }
+            "#,
+        )
+        .unwrap();
+        let Response::Message(message) = response else {
+            panic!("Response is not a message");
+        };
+
+        let candidate = message.into_candidate();
+        assert_eq!(candidate.text(), "Let me check the weather.");
+        assert_eq!(candidate.finish_reason.as_deref(), Some("tool_use"));
+        assert_eq!(
+            candidate.content[0],
+            client::ContentBlock::Thinking {
+                text: "Needs the weather.".to_string(),
+                signature: Some("EqQB".to_string()),
+            }
+        );
+        assert_eq!(
+            candidate.content[3],
+            client::ContentBlock::ToolCall {
+                id: Some("toolu_01".to_string()),
+                name: "weather".to_string(),
+                arguments: serde_json::json!({"city": "Lisbon"}),
+            }
+        );
+    }
+
+    #[test]
+    fn test_replay_thinking() {
+        /// Tests replaying an assistant turn with signed and unsigned thinking blocks.
+        let turn = client::Turn {
+            role: client::Role::Assistant,
+            content: vec![
+                client::ContentBlock::Thinking {
+                    text: "Seven is prime.".to_string(),
+                    signature: Some("EqQB".to_string()),
+                },
+                client::ContentBlock::Thinking {
+                    text: "Unsigned.".to_string(),
+                    signature: None,
+                },
+                client::ContentBlock::Text {
+                    text: "7".to_string(),
+                },
+            ],
+        };
+        assert_eq!(
+            serde_json::to_value(ChatMessage::from(turn)).unwrap(),
+            serde_json::json!({
+                "role": "assistant",
+                "content": [
+                    {"type": "thinking", "thinking": "Seven is prime.", "signature": "EqQB"},
+                    {"type": "text", "text": "7"}
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_cache_usage() {
+        /// Tests adding cached tokens to the input tokens.
+        let usage = serde_json::from_str::<Usage>(
+            r#"{
+              "input_tokens": 12,
+              "output_tokens": 30,
+              "cache_creation_input_tokens": 0,
+              "cache_read_input_tokens": 2048
+            }"#,
+        )
+        .unwrap();
+        let usage = client::Usage::from(&usage);
+        assert_eq!(usage.input_tokens, 2060);
+        assert_eq!(usage.cache_creation_input_tokens, Some(0));
+        assert_eq!(usage.cache_read_input_tokens, Some(2048));
+    }
+
+    #[test]
+    fn test_image_source() {
+        /// Tests serializing base64 and URL image sources.
+        let base64 = ImageData::from(client::Image {
+            data: "data".to_string(),
+            mime_type: "image/png".to_string(),
+            url: None,
+        });
+        assert_eq!(
+            serde_json::to_value(base64).unwrap(),
+            serde_json::json!({"type": "base64", "media_type": "image/png", "data": "data"})
+        );
+
+        let url = ImageData::from(client::Image {
+            data: String::new(),
+            mime_type: String::new(),
+            url: Some("https://example.com/image.png".to_string()),
+        });
+        assert_eq!(
+            serde_json::to_value(url).unwrap(),
+            serde_json::json!({"type": "url", "url": "https://example.com/image.png"})
+        );
+    }
+
+    #[test]
+    fn test_document_source() {
+        /// Tests converting PDF and text documents to document sources.
+        let pdf = DocumentSource::try_from(client::Document {
+            data: "JVBERi0=".to_string(),
+            mime_type: "application/pdf".to_string(),
+            name: None,
+            url: None,
+            cache: false,
+        })
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(pdf).unwrap(),
+            serde_json::json!({"type": "base64", "media_type": "application/pdf", "data": "JVBERi0="})
+        );
+
+        let text = DocumentSource::try_from(client::Document {
+            data: "IyBOb3Rlcw==".to_string(),
+            mime_type: "text/markdown".to_string(),
+            name: Some("notes.md".to_string()),
+            url: None,
+            cache: false,
+        })
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(text).unwrap(),
+            serde_json::json!({"type": "text", "media_type": "text/plain", "data": "# Notes"})
+        );
+
+        let url = DocumentSource::try_from(client::Document {
+            data: String::new(),
+            mime_type: "application/pdf".to_string(),
+            name: None,
+            url: Some("https://example.com/fax.pdf".to_string()),
+            cache: false,
+        })
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(url).unwrap(),
+            serde_json::json!({"type": "url", "url": "https://example.com/fax.pdf"})
+        );
+
+        assert!(DocumentSource::try_from(client::Document {
+            data: String::new(),
+            mime_type: "application/zip".to_string(),
+            name: None,
+            url: None,
+            cache: false,
+        })
+        .is_err());
+    }
+
+    #[test]
+    /// Tests reading a page of the models endpoint.
+    fn test_parse_models() {
+        let res = r#"
+        {
+          "data": [
+            {
+              "type": "model",
+              "id": "claude-3-5-sonnet-20241022",
+              "display_name": "Claude 3.5 Sonnet (New)",
+              "created_at": "2024-10-22T00:00:00Z"
+            }
+          ],
+          "has_more": true,
+          "first_id": "claude-3-5-sonnet-20241022",
+          "last_id": "claude-3-5-sonnet-20241022"
+        }
+        "#;
+        let ModelsResponse::Page(page) = serde_json::from_str(res).unwrap() else {
+            panic!("response is not a page of models");
+        };
+        assert!(page.has_more);
+        assert_eq!(page.last_id.as_deref(), Some("claude-3-5-sonnet-20241022"));
+        assert_eq!(page.data[0].display_name, "Claude 3.5 Sonnet (New)");
+    }
+
+    #[test]
+    /// Tests that token counting requests drop `max_tokens`, and parsing their response.
+    fn test_count_tokens() {
+        let message = client::Message {
+            system: Some("You are terse.".to_string()),
+            text: "Hello, world!".to_string(),
+            ..Default::default()
+        };
+        let request = build_request(message, "claude-3-5-sonnet-20240620".to_string()).unwrap();
+        let request = serde_json::to_value(CountTokensRequest::from(request)).unwrap();
+        assert!(request.get("max_tokens").is_none());
+        assert_eq!(request["system"][0]["text"], "You are terse.");
+
+        let response = r#"{"input_tokens": 14}"#;
+        assert!(matches!(
+            serde_json::from_str::<CountTokensResponse>(response).unwrap(),
+            CountTokensResponse::Count { input_tokens: 14 }
+        ));
+    }
+
+    #[test]
+    /// Tests raising thinking budgets to the minimum the API accepts.
+    fn test_thinking_budget() {
+        let message = client::Message {
+            text: "Hello, world!".to_string(),
+            options: client::GenerationOptions::new()
+                .reasoning(client::Reasoning::BudgetTokens(100)),
+            ..Default::default()
+        };
+        let request = build_request(message, "claude-3-7-sonnet-20250219".to_string()).unwrap();
+        let request = serde_json::to_value(request).unwrap();
+        assert_eq!(request["thinking"]["budget_tokens"], 1024);
+        assert_eq!(request["max_tokens"], 2048);
+    }
+
+    #[test]
+    /// Tests dropping the reasoning settings of models without extended thinking.
+    fn test_thinking_unsupported() {
+        let message = client::Message {
+            text: "Hello, world!".to_string(),
+            options: client::GenerationOptions::new()
+                .reasoning(client::Reasoning::BudgetTokens(2048)),
+            ..Default::default()
+        };
+        let request = build_request(message, "claude-3-5-sonnet-20240620".to_string()).unwrap();
+        let request = serde_json::to_value(request).unwrap();
+        assert!(request.get("thinking").is_none());
+        assert_eq!(request["max_tokens"], 1024);
+    }
+
     #[test]
     fn test_parse_error() {
         /// Tests parsing an error response.
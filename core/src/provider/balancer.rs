@@ -0,0 +1,735 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures_util::future;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::client;
+
+use super::{AIProvider, ApiError, BoxFuture, EmbeddingProvider, ImageGenerationProvider};
+
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+const MAX_COOLDOWN: Duration = Duration::from_secs(300);
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+/// Represents the strategy used by a `Balancer` to pick the member serving a request.
+pub enum Strategy {
+    /// Cycles through the members in order.
+    #[default]
+    RoundRobin,
+    /// Cycles through the members proportionally to their weights.
+    Weighted,
+    /// Picks the member with the fewest requests currently in flight.
+    LeastInFlight,
+}
+
+/// Represents a provider that distributes requests across a pool of providers, e.g. several API
+/// keys of the same vendor with separate quotas.
+///
+/// Members that fail with a rate limit (429) or server (5xx) error are ejected from the pool for
+/// a cooldown period, which doubles on every consecutive failure, and the request is retried on
+/// the next healthy member. When every member is ejected, the pool fails open and keeps using
+/// all of them.
+///
+/// The balancer is cheaply cloneable and clones share the same pool, so one clone can be handed
+/// to a `Client` while another is kept around to report the pool health.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aipim::client::Client;
+/// use aipim::provider::{Balancer, OpenAI, Strategy};
+///
+/// let balancer = Balancer::new(Strategy::Weighted)
+///     .member("primary", OpenAI::new("sk-1", "gpt-4o"), 3)
+///     .member("secondary", OpenAI::new("sk-2", "gpt-4o"), 1);
+/// let client = Client::from_provider(balancer.clone());
+/// ```
+#[derive(Clone)]
+pub struct Balancer {
+    inner: Arc<Pool>,
+}
+
+struct Pool {
+    strategy: Strategy,
+    cooldown: Duration,
+    members: Vec<Member>,
+    next: AtomicUsize,
+    current_weights: Mutex<Vec<i64>>,
+}
+
+struct Member {
+    label: String,
+    provider: Box<dyn AIProvider>,
+    weight: u32,
+    in_flight: AtomicUsize,
+    failures: AtomicUsize,
+    ejected_until: Mutex<Option<Instant>>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+/// Represents the health of a single member of a `Balancer` pool.
+pub struct MemberHealth {
+    pub label: String,
+    pub weight: u32,
+    pub healthy: bool,
+    pub in_flight: usize,
+    pub consecutive_failures: usize,
+    /// Remaining ejection time, in seconds.
+    pub ejected_for: Option<f64>,
+}
+
+impl Balancer {
+    /// Creates a new, empty `Balancer` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `strategy` - The strategy used to pick a member for each request.
+    ///
+    pub fn new(strategy: Strategy) -> Self {
+        Self {
+            inner: Arc::new(Pool {
+                strategy,
+                cooldown: DEFAULT_COOLDOWN,
+                members: Vec::new(),
+                next: AtomicUsize::new(0),
+                current_weights: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Adds a member to the pool.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - A name identifying the member in the pool health.
+    /// * `provider` - The provider serving the requests routed to this member.
+    /// * `weight` - The relative share of requests for the `Weighted` strategy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the balancer has already been cloned.
+    ///
+    pub fn member(
+        self,
+        label: impl Into<String>,
+        provider: impl AIProvider + 'static,
+        weight: u32,
+    ) -> Self {
+        self.boxed_member(label, Box::new(provider), weight)
+    }
+
+    /// Adds an already boxed member to the pool.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - A name identifying the member in the pool health.
+    /// * `provider` - The provider serving the requests routed to this member.
+    /// * `weight` - The relative share of requests for the `Weighted` strategy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the balancer has already been cloned.
+    ///
+    pub fn boxed_member(
+        mut self,
+        label: impl Into<String>,
+        provider: Box<dyn AIProvider>,
+        weight: u32,
+    ) -> Self {
+        let pool = self.pool_mut();
+        pool.members.push(Member {
+            label: label.into(),
+            provider,
+            weight: weight.max(1),
+            in_flight: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+            ejected_until: Mutex::new(None),
+        });
+        pool.current_weights.get_mut().unwrap().push(0);
+        self
+    }
+
+    /// Sets the base cooldown of ejected members.
+    ///
+    /// # Arguments
+    ///
+    /// * `cooldown` - How long a member is ejected after its first consecutive failure.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the balancer has already been cloned.
+    ///
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.pool_mut().cooldown = cooldown;
+        self
+    }
+
+    /// Returns the health of every member of the pool.
+    pub fn health(&self) -> Vec<MemberHealth> {
+        let now = Instant::now();
+        self.inner
+            .members
+            .iter()
+            .map(|member| {
+                let ejected_for = member
+                    .ejected_until()
+                    .filter(|until| *until > now)
+                    .map(|until| (until - now).as_secs_f64());
+                MemberHealth {
+                    label: member.label.clone(),
+                    weight: member.weight,
+                    healthy: ejected_for.is_none(),
+                    in_flight: member.in_flight.load(Ordering::SeqCst),
+                    consecutive_failures: member.failures.load(Ordering::SeqCst),
+                    ejected_for,
+                }
+            })
+            .collect()
+    }
+
+    fn pool_mut(&mut self) -> &mut Pool {
+        Arc::get_mut(&mut self.inner).expect("balancer members must be added before cloning")
+    }
+}
+
+impl Pool {
+    /// Picks the next member to use, skipping the ones in `tried`.
+    fn pick(&self, tried: &[usize]) -> Option<usize> {
+        let now = Instant::now();
+        let untried = (0..self.members.len())
+            .filter(|index| !tried.contains(index))
+            .collect::<Vec<_>>();
+        let healthy = untried
+            .iter()
+            .copied()
+            .filter(|index| self.members[*index].is_healthy(now))
+            .collect::<Vec<_>>();
+        let candidates = if healthy.is_empty() { untried } else { healthy };
+
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let picked = match self.strategy {
+            Strategy::RoundRobin => {
+                candidates[self.next.fetch_add(1, Ordering::SeqCst) % candidates.len()]
+            }
+            Strategy::Weighted => {
+                // Smooth weighted round-robin: every candidate gains its weight, the richest one
+                // is picked and pays back the total.
+                let mut current = self.current_weights.lock().unwrap();
+                let total = candidates
+                    .iter()
+                    .map(|index| self.members[*index].weight as i64)
+                    .sum::<i64>();
+                for index in &candidates {
+                    current[*index] += self.members[*index].weight as i64;
+                }
+                let picked = candidates
+                    .iter()
+                    .copied()
+                    .max_by_key(|index| (current[*index], std::cmp::Reverse(*index)))
+                    .unwrap();
+                current[picked] -= total;
+                picked
+            }
+            Strategy::LeastInFlight => candidates
+                .iter()
+                .copied()
+                .min_by_key(|index| self.members[*index].in_flight.load(Ordering::SeqCst))
+                .unwrap(),
+        };
+
+        Some(picked)
+    }
+}
+
+impl Member {
+    fn ejected_until(&self) -> Option<Instant> {
+        *self.ejected_until.lock().unwrap()
+    }
+
+    fn is_healthy(&self, now: Instant) -> bool {
+        self.ejected_until().is_none_or(|until| until <= now)
+    }
+
+    fn record_success(&self) {
+        self.failures.store(0, Ordering::SeqCst);
+        *self.ejected_until.lock().unwrap() = None;
+    }
+
+    fn eject(&self, cooldown: Duration) {
+        let failures = self.failures.fetch_add(1, Ordering::SeqCst) + 1;
+        let factor = 1u32 << (failures - 1).min(16);
+        let cooldown = cooldown.saturating_mul(factor).min(MAX_COOLDOWN);
+        warn!(
+            "Ejecting pool member {} for {:?} after {} consecutive failures",
+            self.label, cooldown, failures
+        );
+        *self.ejected_until.lock().unwrap() = Some(Instant::now() + cooldown);
+    }
+}
+
+/// Counts a request in flight on a member until it is dropped, so cancelled requests are
+/// uncounted too.
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl<'a> InFlight<'a> {
+    fn start(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Self(counter)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Balancer {
+    /// Runs a call on the members of the pool, failing over to the next healthy member when the
+    /// call fails with a transient error.
+    async fn route<T, F>(&self, call: F) -> anyhow::Result<T>
+    where
+        F: for<'a> Fn(&'a dyn AIProvider) -> BoxFuture<'a, anyhow::Result<T>>,
+    {
+        self.route_capable("messages", |_| true, call).await
+    }
+
+    /// Runs a call like `route`, only on the members for which `capable` returns `true`, e.g.
+    /// those supporting embeddings.
+    async fn route_capable<T, C, F>(&self, feature: &str, capable: C, call: F) -> anyhow::Result<T>
+    where
+        C: Fn(&dyn AIProvider) -> bool,
+        F: for<'a> Fn(&'a dyn AIProvider) -> BoxFuture<'a, anyhow::Result<T>>,
+    {
+        let pool = &self.inner;
+        // Incapable members are skipped as if they were already tried, without being ejected.
+        let mut tried = (0..pool.members.len())
+            .filter(|index| !capable(pool.members[*index].provider.as_ref()))
+            .collect::<Vec<_>>();
+        if !pool.members.is_empty() && tried.len() == pool.members.len() {
+            anyhow::bail!("no member of the balancer pool supports {feature}");
+        }
+        let mut last_error = None;
+
+        while let Some(index) = pool.pick(&tried) {
+            tried.push(index);
+            let member = &pool.members[index];
+            debug!("Routing request to pool member {}", member.label);
+
+            let in_flight = InFlight::start(&member.in_flight);
+            let result = call(member.provider.as_ref()).await;
+            drop(in_flight);
+
+            match result {
+                Ok(response) => {
+                    member.record_success();
+                    return Ok(response);
+                }
+                Err(err) => {
+                    let transient = err
+                        .downcast_ref::<ApiError>()
+                        .is_some_and(ApiError::is_transient);
+                    if !transient {
+                        return Err(err);
+                    }
+                    member.eject(pool.cooldown);
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("balancer pool has no members")))
+    }
+}
+
+#[async_trait]
+impl AIProvider for Balancer {
+    /// Sends a message through one of the members of the pool.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - A `client::Message` instance containing the message to be sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pool is empty, if a member fails with a non-transient error, or
+    /// the last error when every member failed.
+    ///
+    async fn send_message(&self, message: client::Message) -> anyhow::Result<client::Response> {
+        // Members are priced with their own model, which the client cannot see.
+        self.route(|provider| {
+            let message = message.clone();
+            Box::pin(async move {
+                let model = message.model.clone();
+                let response = provider.send_message(message).await?;
+                Ok(response.priced(model.as_deref().or(provider.model())))
+            })
+        })
+        .await
+    }
+
+    /// Transcribes audio through one of the members of the pool.
+    async fn transcribe(
+        &self,
+        request: client::TranscriptionRequest,
+    ) -> anyhow::Result<client::Transcription> {
+        self.route(|provider| provider.transcribe(request.clone()))
+            .await
+    }
+
+    /// Counts the tokens of a message through one of the members of the pool.
+    async fn count_tokens(&self, message: client::Message) -> anyhow::Result<usize> {
+        self.route(|provider| provider.count_tokens(message.clone()))
+            .await
+    }
+
+    /// Lists the models of every member of the pool concurrently, skipping the members that fail.
+    ///
+    /// # Errors
+    ///
+    /// Returns the last error when every member failed, or if the pool is empty.
+    ///
+    async fn list_models(&self) -> anyhow::Result<Vec<client::AvailableModel>> {
+        let members = &self.inner.members;
+        let listed =
+            future::join_all(members.iter().map(|member| member.provider.list_models())).await;
+        let mut models = Vec::new();
+        let mut last_error = None;
+        for (member, listed) in members.iter().zip(listed) {
+            match listed {
+                Ok(listed) => models.extend(listed),
+                Err(err) => {
+                    warn!(
+                        "Failed to list the models of pool member {}: {err}",
+                        member.label
+                    );
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        match last_error {
+            Some(err) if models.is_empty() => Err(err),
+            _ if self.inner.members.is_empty() => {
+                Err(anyhow::anyhow!("balancer pool has no members"))
+            }
+            _ => Ok(client::dedup_models(models)),
+        }
+    }
+
+    fn name(&self) -> &str {
+        "balancer"
+    }
+
+    fn as_embedding_provider(&self) -> Option<&dyn EmbeddingProvider> {
+        let supported = self
+            .inner
+            .members
+            .iter()
+            .any(|member| member.provider.as_embedding_provider().is_some());
+        supported.then_some(self as &dyn EmbeddingProvider)
+    }
+
+    fn as_image_generator(&self) -> Option<&dyn ImageGenerationProvider> {
+        let supported = self
+            .inner
+            .members
+            .iter()
+            .any(|member| member.provider.as_image_generator().is_some());
+        supported.then_some(self as &dyn ImageGenerationProvider)
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for Balancer {
+    /// Computes embeddings through one of the members of the pool.
+    async fn embed(&self, request: client::EmbeddingRequest) -> anyhow::Result<client::Embeddings> {
+        self.route_capable(
+            "embeddings",
+            |provider| provider.as_embedding_provider().is_some(),
+            |provider| match provider.as_embedding_provider() {
+                Some(embedder) => embedder.embed(request.clone()),
+                None => unreachable!("incapable members are skipped"),
+            },
+        )
+        .await
+    }
+
+    /// Returns the smallest batch size of the members supporting embeddings.
+    fn max_batch_size(&self) -> usize {
+        self.inner
+            .members
+            .iter()
+            .filter_map(|member| member.provider.as_embedding_provider())
+            .map(|embedder| embedder.max_batch_size())
+            .min()
+            .unwrap_or(1)
+    }
+}
+
+#[async_trait]
+impl ImageGenerationProvider for Balancer {
+    /// Generates images through one of the members of the pool.
+    async fn generate_image(
+        &self,
+        request: client::ImageGenerationRequest,
+    ) -> anyhow::Result<Vec<client::GeneratedImage>> {
+        self.route_capable(
+            "image generation",
+            |provider| provider.as_image_generator().is_some(),
+            |provider| match provider.as_image_generator() {
+                Some(generator) => generator.generate_image(request.clone()),
+                None => unreachable!("incapable members are skipped"),
+            },
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+/// Unit tests for the balancer module.
+mod tests {
+    use super::*;
+
+    /// A provider that answers with its own name, or fails with the given status.
+    struct Fake {
+        name: &'static str,
+        status: Option<u16>,
+    }
+
+    #[async_trait]
+    impl AIProvider for Fake {
+        async fn send_message(
+            &self,
+            _message: client::Message,
+        ) -> anyhow::Result<client::Response> {
+            match self.status {
+                Some(status) => Err(ApiError {
+                    status,
+                    message: "failed".to_string(),
+                }
+                .into()),
+                None => Ok(client::Response::new(self.name)),
+            }
+        }
+
+        async fn list_models(&self) -> anyhow::Result<Vec<client::AvailableModel>> {
+            match self.status {
+                Some(_) => Err(anyhow::anyhow!("failed")),
+                None => Ok(vec![
+                    client::AvailableModel::new(self.name, "custom"),
+                    client::AvailableModel::new("gpt-4o", "openai"),
+                ]),
+            }
+        }
+    }
+
+    fn ok(name: &'static str) -> Fake {
+        Fake { name, status: None }
+    }
+
+    fn failing(name: &'static str, status: u16) -> Fake {
+        Fake {
+            name,
+            status: Some(status),
+        }
+    }
+
+    fn message() -> client::Message {
+        client::Message {
+            text: "Hello, world!".to_string(),
+            images: None,
+            ..Default::default()
+        }
+    }
+
+    async fn send(balancer: &Balancer) -> anyhow::Result<String> {
+        Ok(balancer.send_message(message()).await?.text())
+    }
+
+    #[tokio::test]
+    /// Tests that round-robin cycles through the members in order.
+    async fn test_round_robin() {
+        let balancer = Balancer::new(Strategy::RoundRobin)
+            .member("a", ok("a"), 1)
+            .member("b", ok("b"), 1);
+        assert_eq!(send(&balancer).await.unwrap(), "a");
+        assert_eq!(send(&balancer).await.unwrap(), "b");
+        assert_eq!(send(&balancer).await.unwrap(), "a");
+    }
+
+    #[tokio::test]
+    /// Tests that the weighted strategy honors the member weights.
+    async fn test_weighted() {
+        let balancer = Balancer::new(Strategy::Weighted)
+            .member("a", ok("a"), 3)
+            .member("b", ok("b"), 1);
+        let mut picked = Vec::new();
+        for _ in 0..8 {
+            picked.push(send(&balancer).await.unwrap());
+        }
+        assert_eq!(picked.iter().filter(|name| *name == "a").count(), 6);
+        assert_eq!(picked.iter().filter(|name| *name == "b").count(), 2);
+    }
+
+    #[tokio::test]
+    /// Tests that transient failures eject the member and fail over to the next one.
+    async fn test_ejects_on_transient_error() {
+        let balancer = Balancer::new(Strategy::RoundRobin)
+            .member("a", failing("a", 429), 1)
+            .member("b", ok("b"), 1);
+        assert_eq!(send(&balancer).await.unwrap(), "b");
+        assert_eq!(send(&balancer).await.unwrap(), "b");
+
+        let health = balancer.health();
+        assert!(!health[0].healthy);
+        assert_eq!(health[0].consecutive_failures, 1);
+        assert!(health[1].healthy);
+    }
+
+    #[tokio::test]
+    /// Tests that non-transient failures are returned without ejecting the member.
+    async fn test_does_not_eject_on_client_error() {
+        let balancer = Balancer::new(Strategy::RoundRobin)
+            .member("a", failing("a", 400), 1)
+            .member("b", ok("b"), 1);
+        assert!(send(&balancer).await.is_err());
+        assert!(balancer.health()[0].healthy);
+    }
+
+    #[tokio::test]
+    /// Tests that the last error is returned when every member fails.
+    async fn test_all_members_failing() {
+        let balancer = Balancer::new(Strategy::LeastInFlight)
+            .member("a", failing("a", 500), 1)
+            .member("b", failing("b", 503), 1);
+        let err = send(&balancer).await.unwrap_err();
+        assert!(err.downcast_ref::<ApiError>().unwrap().is_transient());
+        assert!(balancer.health().iter().all(|member| !member.healthy));
+    }
+
+    /// A provider that never answers.
+    struct Hanging;
+
+    #[async_trait]
+    impl AIProvider for Hanging {
+        async fn send_message(
+            &self,
+            _message: client::Message,
+        ) -> anyhow::Result<client::Response> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    /// Tests that cancelled requests are no longer counted in flight.
+    async fn test_cancelled_request_in_flight() {
+        let balancer = Balancer::new(Strategy::LeastInFlight).member("a", Hanging, 1);
+        let mut send = Box::pin(balancer.send_message(message()));
+        let timeout = tokio::time::timeout(Duration::from_millis(10), &mut send).await;
+        assert!(timeout.is_err());
+        assert_eq!(balancer.health()[0].in_flight, 1);
+
+        drop(send);
+        assert_eq!(balancer.health()[0].in_flight, 0);
+    }
+
+    #[tokio::test]
+    /// Tests that an empty pool returns an error.
+    async fn test_empty_pool() {
+        let balancer = Balancer::new(Strategy::RoundRobin);
+        assert!(send(&balancer).await.is_err());
+    }
+
+    #[tokio::test]
+    /// Tests listing the models of every member once, skipping the failing members.
+    async fn test_list_models() {
+        let balancer = Balancer::new(Strategy::RoundRobin)
+            .member("a", ok("a"), 1)
+            .member("b", failing("b", 500), 1)
+            .member("c", ok("c"), 1);
+        let models = balancer.list_models().await.unwrap();
+        let ids = models
+            .iter()
+            .map(|model| model.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["a", "c", "gpt-4o"]);
+        assert!(models[2].info.is_some());
+
+        let balancer = Balancer::new(Strategy::RoundRobin).member("b", failing("b", 500), 1);
+        assert!(balancer.list_models().await.is_err());
+    }
+
+    /// A provider embedding every text as `[1]`.
+    struct Embedder;
+
+    #[async_trait]
+    impl AIProvider for Embedder {
+        async fn send_message(
+            &self,
+            _message: client::Message,
+        ) -> anyhow::Result<client::Response> {
+            Ok(client::Response::new("embedder"))
+        }
+
+        fn as_embedding_provider(&self) -> Option<&dyn EmbeddingProvider> {
+            Some(self)
+        }
+    }
+
+    #[async_trait]
+    impl EmbeddingProvider for Embedder {
+        async fn embed(
+            &self,
+            request: client::EmbeddingRequest,
+        ) -> anyhow::Result<client::Embeddings> {
+            let vectors = request.texts.iter().map(|_| vec![1.0]).collect();
+            Ok(client::Embeddings::new(vectors, None))
+        }
+    }
+
+    #[tokio::test]
+    /// Tests that embeddings are only routed to the members supporting them.
+    async fn test_embed_skips_incapable_members() {
+        let balancer = Balancer::new(Strategy::RoundRobin)
+            .member("a", ok("a"), 1)
+            .member("b", Embedder, 1);
+        let embedder = balancer.as_embedding_provider().unwrap();
+        for _ in 0..4 {
+            let request = client::EmbeddingRequest {
+                texts: vec!["Hello".to_string()],
+                model: None,
+                dimensions: None,
+            };
+            assert_eq!(embedder.embed(request).await.unwrap().vectors.len(), 1);
+        }
+        assert!(balancer.health().iter().all(|member| member.healthy));
+
+        let balancer = Balancer::new(Strategy::RoundRobin).member("a", ok("a"), 1);
+        assert!(balancer.as_embedding_provider().is_none());
+        let err = balancer
+            .embed(client::EmbeddingRequest {
+                texts: vec!["Hello".to_string()],
+                model: None,
+                dimensions: None,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no member of the balancer pool supports embeddings"
+        );
+    }
+}
@@ -1,33 +1,46 @@
 #![allow(unused)]
+use std::time::Duration;
+
 use async_trait::async_trait;
 use log::{debug, trace};
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
 
-use crate::client;
+use crate::{
+    client::{self, Timeouts},
+    image::ImageLimits,
+    models,
+};
 
-use super::AIProvider;
+use super::{
+    read_json, AIProvider, ApiError, ContentBlockedError, EmbeddingProvider,
+    ImageGenerationProvider,
+};
 
 const MAX_TOKENS: u32 = 32768; // Increased token limit for Gemini 2.0 models
 const BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta/";
-const MODELS: &[&str] = &[
-    // Gemini 1.0 models
-    "gemini-1.5-pro",
-    // Gemini 2.0 models
-    "gemini-2.0-flash",
-    "gemini-2.0-flash-lite",
-    // Gemini 2.5 models
-    "gemini-2.5-pro-exp-03-25",
-];
+const UPLOAD_URL: &str = "https://generativelanguage.googleapis.com/upload/v1beta/";
+/// The model used by `Google::default`, see `models` for the catalog.
+const DEFAULT_MODEL: &str = "gemini-2.0-flash-lite";
+const EMBEDDING_MODEL: &str = "text-embedding-004";
+const IMAGE_MODEL: &str = "imagen-3.0-generate-002";
+/// Maximum number of requests accepted by `batchEmbedContents`.
+const MAX_EMBEDDING_REQUESTS: usize = 100;
 
 /// Represents a Google Gemini client for interacting with the Gemini API.
 pub struct Google {
     client: Client,
     api_key: String,
     model: String,
+    safety_settings: Vec<SafetySetting>,
 }
 
 impl Google {
+    /// The name of the provider, as returned by `AIProvider::name`.
+    pub const NAME: &'static str = "google";
+    /// The environment variable holding the default API key.
+    pub const API_KEY_ENV: &'static str = "GEMINI_API_KEY";
+
     /// Creates a new `Google` instance.
     ///
     /// # Arguments
@@ -37,9 +50,10 @@ impl Google {
     ///
     pub fn new(api_key: impl Into<String>, model: impl Into<String>) -> Self {
         Self {
-            client: Client::new(),
+            client: Timeouts::default().http_client(),
             api_key: api_key.into(),
             model: model.into(),
+            safety_settings: Vec::new(),
         }
     }
 
@@ -56,6 +70,239 @@ impl Google {
         }
     }
 
+    /// Sets the HTTP client used by the `Google` instance, e.g. one built with custom timeouts.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` instance.
+    ///
+    pub fn with_http_client(self, client: Client) -> Self {
+        Self { client, ..self }
+    }
+
+    /// Sets the safety settings applied to every message, unless a message sets its own
+    /// threshold for the same category with `GenerationOptions::safety_setting`.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The thresholds per harm category.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::provider::{Google, HarmBlockThreshold, HarmCategory, SafetySetting};
+    ///
+    /// let google = Google::default().with_safety_settings([SafetySetting::new(
+    ///     HarmCategory::DangerousContent,
+    ///     HarmBlockThreshold::BlockOnlyHigh,
+    /// )]);
+    /// ```
+    pub fn with_safety_settings(self, settings: impl IntoIterator<Item = SafetySetting>) -> Self {
+        Self {
+            safety_settings: settings.into_iter().collect(),
+            ..self
+        }
+    }
+
+    /// Uploads a file with the File API, e.g. a large document sent with many messages, and
+    /// returns its reference. Files are deleted by Gemini after 48 hours.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The content of the file.
+    /// * `mime_type` - The MIME type of the file, e.g. `application/pdf`.
+    /// * `display_name` - An optional name shown when listing files.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response contains an error.
+    ///
+    /// # Examples
+    ///
+    /// ```ingore
+    /// use aipim::client::Client;
+    /// use aipim::provider::Google;
+    ///
+    /// let google = Google::default();
+    /// let file = google
+    ///     .upload_file(std::fs::read("fax.pdf")?, "application/pdf", Some("fax.pdf"))
+    ///     .await?;
+    /// let client = Client::from_provider(google);
+    /// let response = client
+    ///     .message()
+    ///     .document_url(&file.uri, &file.mime_type)
+    ///     .text("Who sent this fax?")
+    ///     .send()
+    ///     .await?;
+    /// ```
+    pub async fn upload_file(
+        &self,
+        data: Vec<u8>,
+        mime_type: &str,
+        display_name: Option<&str>,
+    ) -> anyhow::Result<UploadedFile> {
+        // Resumable uploads start with the metadata, then send the content to the returned URL.
+        let response = self
+            .client
+            .post(format!("{}files?key={}", UPLOAD_URL, self.api_key))
+            .header("X-Goog-Upload-Protocol", "resumable")
+            .header("X-Goog-Upload-Command", "start")
+            .header("X-Goog-Upload-Header-Content-Length", data.len())
+            .header("X-Goog-Upload-Header-Content-Type", mime_type)
+            .json(&UploadRequest {
+                file: UploadMetadata {
+                    display_name: display_name.map(str::to_string),
+                },
+            })
+            .send()
+            .await?;
+
+        let upload_url = response
+            .headers()
+            .get("x-goog-upload-url")
+            .and_then(|url| url.to_str().ok())
+            .map(str::to_string);
+        let Some(upload_url) = upload_url else {
+            let (status, response) = read_json(response).await?;
+            let response = serde_json::from_value::<FileResponse>(response)?;
+            return response.into_file(status);
+        };
+
+        let response = self
+            .client
+            .post(upload_url)
+            .header("X-Goog-Upload-Offset", 0)
+            .header("X-Goog-Upload-Command", "upload, finalize")
+            .body(data)
+            .send()
+            .await?;
+
+        let (status, response) = read_json(response).await?;
+        serde_json::from_value::<FileResponse>(response)?.into_file(status)
+    }
+
+    /// Returns an uploaded file, e.g. to wait until its `state` is `ACTIVE`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The resource name of the file, e.g. `files/abc-123`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response contains an error.
+    ///
+    pub async fn get_file(&self, name: &str) -> anyhow::Result<UploadedFile> {
+        let url = format!("{}{}?key={}", BASE_URL, name, self.api_key);
+        let response = self.client.get(&url).send().await?;
+        let (status, response) = read_json(response).await?;
+        serde_json::from_value::<FileResponse>(response)?.into_file(status)
+    }
+
+    /// Deletes an uploaded file.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The resource name of the file, e.g. `files/abc-123`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response contains an error.
+    ///
+    pub async fn delete_file(&self, name: &str) -> anyhow::Result<()> {
+        self.delete(name).await
+    }
+
+    /// Caches the content of a message, i.e. its system prompt, text, documents, images and
+    /// audio, for the given time. Messages then send only their new content and set
+    /// `GenerationOptions::cached_content` to the returned name.
+    ///
+    /// The content is cached for the model of the message, or the model of the provider.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The content to cache.
+    /// * `ttl` - How long the content is kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response contains an error, e.g. if the
+    /// content is below the minimum number of tokens cached by the model.
+    ///
+    /// # Examples
+    ///
+    /// ```ingore
+    /// use std::time::Duration;
+    ///
+    /// use aipim::client::{Client, GenerationOptions, Message};
+    /// use aipim::provider::Google;
+    ///
+    /// let google = Google::default().with_model("gemini-1.5-flash-002");
+    /// let file = google
+    ///     .upload_file(std::fs::read("fax.pdf")?, "application/pdf", None)
+    ///     .await?;
+    /// let message = Message {
+    ///     system: Some("You read faxes.".to_string()),
+    ///     documents: Some(vec![file.to_document()]),
+    ///     ..Default::default()
+    /// };
+    /// let cache = google
+    ///     .create_cached_content(message, Duration::from_secs(3600))
+    ///     .await?;
+    /// let client = Client::from_provider(google);
+    /// let response = client
+    ///     .message()
+    ///     .options(GenerationOptions::new().cached_content(&cache.name))
+    ///     .text("Who sent this fax?")
+    ///     .send()
+    ///     .await?;
+    /// google.delete_cached_content(&cache.name).await?;
+    /// ```
+    pub async fn create_cached_content(
+        &self,
+        message: client::Message,
+        ttl: Duration,
+    ) -> anyhow::Result<CachedContent> {
+        let model = message.model.clone().unwrap_or_else(|| self.model.clone());
+        let request = cached_content_request(message, &model, ttl);
+        trace!(
+            "JSON Request: {}",
+            serde_json::to_string_pretty(&request).unwrap()
+        );
+
+        let url = format!("{}cachedContents?key={}", BASE_URL, self.api_key);
+        let response = self.client.post(&url).json(&request).send().await?;
+        let (status, response) = read_json(response).await?;
+        match serde_json::from_value::<CachedContentResponse>(response)? {
+            CachedContentResponse::CachedContent(cached_content) => Ok(cached_content),
+            CachedContentResponse::Error { error } => Err(error.into_api_error(status)),
+        }
+    }
+
+    /// Deletes cached content before it expires.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The resource name of the cached content, e.g. `cachedContents/abc-123`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response contains an error.
+    ///
+    pub async fn delete_cached_content(&self, name: &str) -> anyhow::Result<()> {
+        self.delete(name).await
+    }
+
+    /// Deletes a resource of the Gemini API, which returns an empty object on success.
+    async fn delete(&self, name: &str) -> anyhow::Result<()> {
+        let url = format!("{}{}?key={}", BASE_URL, name, self.api_key);
+        let response = self.client.delete(&url).send().await?;
+        let (status, response) = read_json(response).await?;
+        match serde_json::from_value::<Response>(response)? {
+            Response::Error { error } => Err(error.into_api_error(status)),
+            Response::Success(_) => Ok(()),
+        }
+    }
+
     /// Validates if a model name is supported by the Gemini API.
     ///
     /// # Arguments
@@ -64,10 +311,10 @@ impl Google {
     ///
     /// # Returns
     ///
-    /// Returns `true` if the model is supported, `false` otherwise.
+    /// Returns `true` if the model is a Gemini model of the catalog, `false` otherwise.
     ///
     pub fn is_valid_model(model_name: &str) -> bool {
-        MODELS.contains(&model_name)
+        models::info(model_name).is_some_and(|info| info.provider == Self::NAME)
     }
 }
 
@@ -75,7 +322,7 @@ impl Default for Google {
     fn default() -> Self {
         Self::new(
             std::env::var("GEMINI_API_KEY").expect("GEMINI_API_KEY is not set"),
-            MODELS[2], // Default to gemini-2.0-pro
+            DEFAULT_MODEL,
         )
     }
 }
@@ -90,10 +337,22 @@ impl AIProvider for Google {
     ///
     /// # Errors
     ///
-    /// Returns an error if the request fails or the response contains an error.
+    /// Returns an error if the message sets a system prompt along with cached content, if the
+    /// request fails or if the response contains an error.
     ///
     async fn send_message(&self, message: client::Message) -> anyhow::Result<client::Response> {
-        let request = build_request(message, self.model.as_str());
+        check_cached_content(&message)?;
+        let model = message.model.clone().unwrap_or_else(|| self.model.clone());
+        let mut request = build_request(message, &model);
+        for setting in &self.safety_settings {
+            if !request
+                .safety_settings
+                .iter()
+                .any(|s| s.category == setting.category)
+            {
+                request.safety_settings.push(*setting);
+            }
+        }
         log::info!(
             "JSON Request: {}",
             serde_json::to_string_pretty(&request).unwrap()
@@ -101,12 +360,12 @@ impl AIProvider for Google {
 
         let url = format!(
             "{}models/{}:generateContent?key={}",
-            BASE_URL, self.model, self.api_key
+            BASE_URL, model, self.api_key
         );
         log::info!("url: {}", url);
         let response = self.client.post(&url).json(&request).send().await?;
 
-        let response: serde_json::Value = response.json().await?;
+        let (status, response) = read_json(response).await?;
         log::info!(
             "JSON Response: {}",
             serde_json::to_string_pretty(&response).unwrap()
@@ -116,21 +375,312 @@ impl AIProvider for Google {
         debug!("Google Response: {:#?}", response);
 
         match response {
-            Response::Success(success) => {
-                let content = &success.candidates[0].content;
-                let text = content.parts[0].as_text().ok_or_else(|| {
-                    anyhow::anyhow!("unsupported response content type: {:?}", content)
-                })?;
+            Response::Success(success) => success.into_response(),
+            Response::Error { error } => Err(error.into_api_error(status)),
+        }
+    }
+
+    /// Counts the input tokens of a message with the `countTokens` endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - A `client::Message` instance containing the message to be counted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message sets a system prompt along with cached content, if the
+    /// request fails or if the response contains an error.
+    ///
+    async fn count_tokens(&self, message: client::Message) -> anyhow::Result<usize> {
+        check_cached_content(&message)?;
+        let model = message.model.clone().unwrap_or_else(|| self.model.clone());
+        let request = CountTokensRequest {
+            generate_content_request: GenerateContentRequest {
+                model: format!("models/{model}"),
+                request: build_request(message, &model),
+            },
+        };
+
+        let url = format!(
+            "{}models/{}:countTokens?key={}",
+            BASE_URL, model, self.api_key
+        );
+        let response = self.client.post(&url).json(&request).send().await?;
+        let (status, response) = read_json(response).await?;
+        match serde_json::from_value::<CountTokensResponse>(response)? {
+            CountTokensResponse::Error { error } => Err(error.into_api_error(status)),
+            CountTokensResponse::Count { total_tokens } => Ok(total_tokens),
+        }
+    }
+
+    /// Lists the models of the `models` endpoint, following its pages.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a request fails or a response contains an error.
+    ///
+    async fn list_models(&self) -> anyhow::Result<Vec<client::AvailableModel>> {
+        let mut models = Vec::new();
+        let mut page_token = None;
+        loop {
+            let mut request = self
+                .client
+                .get(format!("{}models", BASE_URL))
+                .query(&[("key", self.api_key.as_str()), ("pageSize", "1000")]);
+            if let Some(page_token) = &page_token {
+                request = request.query(&[("pageToken", page_token)]);
+            }
+
+            let (status, response) = read_json(request.send().await?).await?;
+            let page = match serde_json::from_value::<ModelsResponse>(response)? {
+                ModelsResponse::Error { error } => return Err(error.into_api_error(status)),
+                ModelsResponse::Page(page) => page,
+            };
+
+            models.extend(page.models.into_iter().map(Model::into_available));
+            match page.next_page_token {
+                Some(token) if !token.is_empty() => page_token = Some(token),
+                _ => return Ok(models),
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn model(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+
+    fn supports_image_urls(&self) -> bool {
+        true
+    }
+
+    fn image_limits(&self) -> ImageLimits {
+        ImageLimits::GOOGLE
+    }
+
+    fn max_tokens(&self, model: &str) -> Option<usize> {
+        Some(max_tokens(model) as usize)
+    }
+
+    fn as_embedding_provider(&self) -> Option<&dyn EmbeddingProvider> {
+        Some(self)
+    }
+
+    fn as_image_generator(&self) -> Option<&dyn ImageGenerationProvider> {
+        Some(self)
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for Google {
+    /// Computes embeddings with the Gemini API, using `embedContent` for a single text and
+    /// `batchEmbedContents` otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A `client::EmbeddingRequest` instance containing the texts.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response contains an error.
+    ///
+    async fn embed(&self, request: client::EmbeddingRequest) -> anyhow::Result<client::Embeddings> {
+        let model = request.model.unwrap_or_else(|| EMBEDDING_MODEL.to_string());
+        let mut requests = request
+            .texts
+            .into_iter()
+            .map(|text| EmbedContentRequest {
+                model: format!("models/{model}"),
+                content: Content {
+                    parts: vec![Part::Text(TextPart { text })],
+                    role: "user".to_string(),
+                },
+                output_dimensionality: request.dimensions,
+            })
+            .collect::<Vec<_>>();
+
+        let response = if requests.len() == 1 {
+            let url = format!(
+                "{}models/{}:embedContent?key={}",
+                BASE_URL, model, self.api_key
+            );
+            let request = requests.remove(0);
+            self.client.post(&url).json(&request).send().await?
+        } else {
+            let url = format!(
+                "{}models/{}:batchEmbedContents?key={}",
+                BASE_URL, model, self.api_key
+            );
+            let request = BatchEmbedContentsRequest { requests };
+            self.client.post(&url).json(&request).send().await?
+        };
+
+        let (status, response) = read_json(response).await?;
+        match serde_json::from_value::<EmbeddingResponse>(response)? {
+            EmbeddingResponse::Single { embedding } => {
+                Ok(client::Embeddings::new(vec![embedding.values], None))
+            }
+            EmbeddingResponse::Batch { embeddings } => {
+                let vectors = embeddings.into_iter().map(|embedding| embedding.values);
+                Ok(client::Embeddings::new(vectors.collect(), None))
+            }
+            EmbeddingResponse::Error { error } => Err(error.into_api_error(status)),
+        }
+    }
+
+    fn max_batch_size(&self) -> usize {
+        MAX_EMBEDDING_REQUESTS
+    }
+}
 
-                Ok(client::Response::new(text.to_string()))
+#[async_trait]
+impl ImageGenerationProvider for Google {
+    /// Generates images with Imagen models through `predict`, or with Gemini models able to
+    /// output images (e.g. `gemini-2.0-flash-preview-image-generation`) through
+    /// `generateContent`, one request per image.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A `client::ImageGenerationRequest` instance containing the prompt.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response contains an error.
+    ///
+    async fn generate_image(
+        &self,
+        request: client::ImageGenerationRequest,
+    ) -> anyhow::Result<Vec<client::GeneratedImage>> {
+        let model = request
+            .model
+            .clone()
+            .unwrap_or_else(|| IMAGE_MODEL.to_string());
+        if model.starts_with("imagen") {
+            return self.predict_images(request, &model).await;
+        }
+
+        let mut images = Vec::new();
+        for _ in 0..request.n {
+            let message = client::Message {
+                text: request.prompt.clone(),
+                ..Default::default()
+            };
+            let mut body = build_request(message, &model);
+            body.generation_config.response_mime_type = None;
+            body.generation_config.response_modalities =
+                Some(vec!["TEXT".to_string(), "IMAGE".to_string()]);
+            body.system_instruction = None;
+
+            let url = format!(
+                "{}models/{}:generateContent?key={}",
+                BASE_URL, model, self.api_key
+            );
+            let response = self.client.post(&url).json(&body).send().await?;
+            let (status, response) = read_json(response).await?;
+
+            match serde_json::from_value::<Response>(response)? {
+                Response::Success(success) => {
+                    let parts = success
+                        .candidates
+                        .into_iter()
+                        .flat_map(|candidate| candidate.content.parts);
+                    for part in parts {
+                        if let Part::InlineData(InlineData { inline_data }) = part {
+                            images.push(client::GeneratedImage::from_base64(
+                                &inline_data.data,
+                                Some(inline_data.mime_type),
+                            )?);
+                        }
+                    }
+                }
+                Response::Error { error } => return Err(error.into_api_error(status)),
             }
-            Response::Error { error } => Err(anyhow::anyhow!(
-                "{}: {} ({})",
-                error.status,
-                error.message,
-                error.code
-            )),
         }
+
+        Ok(images)
+    }
+}
+
+impl Google {
+    /// Generates images with the `predict` endpoint of an Imagen model.
+    async fn predict_images(
+        &self,
+        request: client::ImageGenerationRequest,
+        model: &str,
+    ) -> anyhow::Result<Vec<client::GeneratedImage>> {
+        let body = PredictRequest {
+            instances: vec![PredictInstance {
+                prompt: request.prompt,
+            }],
+            parameters: PredictParameters {
+                sample_count: request.n,
+                aspect_ratio: request.size.aspect_ratio().to_string(),
+            },
+        };
+
+        let url = format!("{}models/{}:predict?key={}", BASE_URL, model, self.api_key);
+        let response = self.client.post(&url).json(&body).send().await?;
+        let (status, response) = read_json(response).await?;
+
+        match serde_json::from_value::<PredictResponse>(response)? {
+            PredictResponse::Predictions { predictions } => predictions
+                .into_iter()
+                .map(|prediction| {
+                    client::GeneratedImage::from_base64(
+                        &prediction.bytes_base64_encoded,
+                        prediction.mime_type,
+                    )
+                })
+                .collect(),
+            PredictResponse::Error { error } => Err(error.into_api_error(status)),
+        }
+    }
+}
+
+/// Builds the request creating cached content from a message, dropping its empty text and the
+/// default system instruction.
+fn cached_content_request(
+    message: client::Message,
+    model: &str,
+    ttl: Duration,
+) -> CachedContentRequest {
+    let system = message.system.is_some();
+    let mut request = build_request(message, model);
+    for content in &mut request.contents {
+        content
+            .parts
+            .retain(|part| !matches!(part, Part::Text(part) if part.text.is_empty()));
+    }
+
+    CachedContentRequest {
+        model: format!("models/{model}"),
+        contents: request.contents,
+        system_instruction: request.system_instruction.filter(|_| system),
+        ttl: format!("{}s", ttl.as_secs()),
+    }
+}
+
+/// Returns the maximum number of tokens requested from a model, higher for Gemini 2.x models.
+fn max_tokens(model: &str) -> u32 {
+    if model.starts_with("gemini-2") {
+        MAX_TOKENS
+    } else {
+        8192
+    }
+}
+
+/// Rejects messages setting a system prompt along with cached content, which Gemini refuses: the
+/// system prompt has to be cached with the content instead.
+fn check_cached_content(message: &client::Message) -> anyhow::Result<()> {
+    match (&message.options.cached_content, &message.system) {
+        (Some(name), Some(_)) => Err(anyhow::anyhow!(
+            "messages using cached content {name} cannot set a system prompt: cache it with \
+             the content instead"
+        )),
+        _ => Ok(()),
     }
 }
 
@@ -142,56 +692,116 @@ fn build_request(message: client::Message, model: &str) -> Request {
 
     if let Some(images) = message.images {
         for image in images {
-            content.parts.insert(
-                0,
-                Part::InlineData(InlineData {
+            let part = match image.url {
+                Some(file_uri) => Part::FileData(FileData {
+                    file_data: FileUri {
+                        mime_type: image.mime_type,
+                        file_uri,
+                    },
+                }),
+                None => Part::InlineData(InlineData {
                     inline_data: Blob {
                         mime_type: image.mime_type,
                         data: image.data,
                     },
                 }),
-            );
+            };
+            content.parts.insert(0, part);
         }
     }
 
-    // Adjust generation config based on model version
-    let max_tokens = if model.starts_with("gemini-2") {
-        32768 // Higher token limit for Gemini 2.x models
-    } else {
-        8192 // Original token limit for older models
+    for audio in message.audio.unwrap_or_default() {
+        content.parts.insert(
+            0,
+            Part::InlineData(InlineData {
+                inline_data: Blob {
+                    mime_type: audio.mime_type,
+                    data: audio.data,
+                },
+            }),
+        );
+    }
+
+    for document in message.documents.unwrap_or_default() {
+        let part = match document.url {
+            Some(file_uri) => Part::FileData(FileData {
+                file_data: FileUri {
+                    mime_type: document.mime_type,
+                    file_uri,
+                },
+            }),
+            None => Part::InlineData(InlineData {
+                inline_data: Blob {
+                    mime_type: document.mime_type,
+                    data: document.data,
+                },
+            }),
+        };
+        content.parts.insert(0, part);
+    }
+
+    let mut contents = message
+        .history
+        .unwrap_or_default()
+        .into_iter()
+        .map(Content::from)
+        .filter(|content| !content.parts.is_empty())
+        .collect::<Vec<_>>();
+    contents.push(content);
+
+    // Models without thinking reject its configuration, so it is dropped for them.
+    let reasoning = message
+        .options
+        .reasoning
+        .filter(|_| models::supports_reasoning(model));
+
+    // Requests using cached content cannot set a system instruction, so the default one is
+    // only sent without it.
+    let system_instruction = match message.system {
+        Some(system) => Some(SystemInstruction {
+            parts: vec![Part::Text(TextPart { text: system })],
+        }),
+        // System instructions are supported in Gemini 2.x models
+        None if model.starts_with("gemini-2") && message.options.cached_content.is_none() => {
+            Some(SystemInstruction {
+                parts: vec![Part::Text(TextPart {
+                    text: "You are a helpful AI assistant.".to_string(),
+                })],
+            })
+        }
+        None => None,
     };
 
     Request {
-        contents: vec![content],
-        safety_settings: vec![],
+        contents,
+        cached_content: message.options.cached_content,
+        safety_settings: message.options.safety_settings,
         generation_config: GenerationConfig {
             temperature: 0.9,
             top_p: 1.0,
             top_k: 1,
-            max_output_tokens: max_tokens,
+            max_output_tokens: max_tokens(model),
             response_mime_type: Some("text/plain".to_string()), // Explicitly request text response
+            response_modalities: None,
+            candidate_count: message.options.n,
+            thinking_config: reasoning.map(|reasoning| ThinkingConfig {
+                thinking_budget: reasoning.budget_tokens(),
+                include_thoughts: true,
+            }),
+            response_logprobs: message.options.logprobs.map(|_| true),
+            logprobs: message.options.logprobs.filter(|top_k| *top_k > 0),
         },
-        system_instruction: if model.starts_with("gemini-2") {
-            // System instructions are supported in Gemini 2.x models
-            Some(SystemInstruction {
-                parts: vec![Part::Text(TextPart {
-                    text: "You are a helpful AI assistant.".to_string(),
-                })],
-            })
-        } else {
-            None
-        },
+        system_instruction,
     }
 }
 
-unsafe impl Send for Google {}
-unsafe impl Sync for Google {}
-
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 /// Represents a request to the Gemini API.
 struct Request {
     contents: Vec<Content>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cached_content: Option<String>,
     safety_settings: Vec<SafetySetting>,
     generation_config: GenerationConfig,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -205,20 +815,54 @@ struct SystemInstruction {
     parts: Vec<Part>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 /// Represents the content of a message.
 struct Content {
+    #[serde(default)]
     parts: Vec<Part>,
     role: String,
 }
 
+impl From<client::Turn> for Content {
+    /// Replays a previous turn with its text and images. Thoughts are not sent back, as the
+    /// model does not need them to continue the conversation.
+    fn from(turn: client::Turn) -> Self {
+        let role = match turn.role {
+            client::Role::User => "user",
+            client::Role::Assistant => "model",
+        };
+        let parts = turn
+            .content
+            .into_iter()
+            .filter_map(|block| match block {
+                client::ContentBlock::Text { text } => Some(Part::Text(TextPart { text })),
+                client::ContentBlock::Image { data, mime_type } => {
+                    Some(Part::InlineData(InlineData {
+                        inline_data: Blob { mime_type, data },
+                    }))
+                }
+                _ => None,
+            })
+            .collect();
+
+        Self {
+            parts,
+            role: role.to_string(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(untagged)]
 /// Represents different parts of a message.
 enum Part {
+    // Thoughts are text parts flagged with `thought`, so they must be tried first.
+    Thought(ThoughtPart),
     Text(TextPart),
     InlineData(InlineData),
+    FileData(FileData),
+    FunctionCall(FunctionCallPart),
 }
 
 impl Part {
@@ -228,6 +872,29 @@ impl Part {
             _ => None,
         }
     }
+
+    /// Converts a part of a response to a content block. File references are never returned by
+    /// the model and are skipped.
+    fn into_block(self) -> Option<client::ContentBlock> {
+        match self {
+            Part::Thought(part) if part.thought => Some(client::ContentBlock::Thinking {
+                text: part.text,
+                signature: None,
+            }),
+            Part::Thought(part) => Some(client::ContentBlock::Text { text: part.text }),
+            Part::Text(part) => Some(client::ContentBlock::Text { text: part.text }),
+            Part::InlineData(part) => Some(client::ContentBlock::Image {
+                data: part.inline_data.data,
+                mime_type: part.inline_data.mime_type,
+            }),
+            Part::FunctionCall(part) => Some(client::ContentBlock::ToolCall {
+                id: part.function_call.id,
+                name: part.function_call.name,
+                arguments: part.function_call.args,
+            }),
+            Part::FileData(_) => None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -239,60 +906,348 @@ struct TextPart {
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-/// Represents inline data (e.g., images) in a message.
-struct InlineData {
-    inline_data: Blob,
+/// Represents a summary of the reasoning of a thinking model.
+struct ThoughtPart {
+    text: String,
+    thought: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct Blob {
-    mime_type: String,
-    data: String,
+/// Represents a call to a function requested by the model.
+struct FunctionCallPart {
+    function_call: FunctionCall,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-/// Represents safety settings for content generation.
-struct SafetySetting {
-    category: String,
-    threshold: String,
+struct FunctionCall {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    name: String,
+    #[serde(default)]
+    args: serde_json::Value,
 }
 
-#[derive(Serialize, Debug)]
-/// Represents configuration for content generation.
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct GenerationConfig {
-    temperature: f32,
-    top_p: f32,
-    top_k: u32,
-    max_output_tokens: u32,
+/// Represents inline data (e.g., images, documents or audio) in a message.
+struct InlineData {
+    inline_data: Blob,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Represents a reference to a file (e.g. an image URL) in a message.
+struct FileData {
+    file_data: FileUri,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct FileUri {
+    mime_type: String,
+    file_uri: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Blob {
+    mime_type: String,
+    data: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// Represents the blocking threshold of a harm category for Gemini models.
+pub struct SafetySetting {
+    pub category: HarmCategory,
+    pub threshold: HarmBlockThreshold,
+}
+
+impl SafetySetting {
+    /// Creates a new `SafetySetting` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `category` - The harm category.
+    /// * `threshold` - The probability of harm from which content is blocked.
+    ///
+    pub fn new(category: HarmCategory, threshold: HarmBlockThreshold) -> Self {
+        Self {
+            category,
+            threshold,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+/// Represents the harm categories filtered by Gemini models.
+pub enum HarmCategory {
+    #[serde(rename = "HARM_CATEGORY_HARASSMENT")]
+    Harassment,
+    #[serde(rename = "HARM_CATEGORY_HATE_SPEECH")]
+    HateSpeech,
+    #[serde(rename = "HARM_CATEGORY_SEXUALLY_EXPLICIT")]
+    SexuallyExplicit,
+    #[serde(rename = "HARM_CATEGORY_DANGEROUS_CONTENT")]
+    DangerousContent,
+    #[serde(rename = "HARM_CATEGORY_CIVIC_INTEGRITY")]
+    CivicIntegrity,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+/// Represents the probability of harm from which Gemini models block content.
+pub enum HarmBlockThreshold {
+    BlockLowAndAbove,
+    BlockMediumAndAbove,
+    BlockOnlyHigh,
+    BlockNone,
+    /// Turns the safety filter off.
+    Off,
+}
+
+#[derive(Serialize, Debug)]
+/// Represents configuration for content generation.
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    temperature: f32,
+    top_p: f32,
+    top_k: u32,
+    max_output_tokens: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
     response_mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_modalities: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    candidate_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking_config: Option<ThinkingConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_logprobs: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logprobs: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Represents the thinking budget of Gemini 2.5 models, returning their thoughts as parts.
+struct ThinkingConfig {
+    thinking_budget: u32,
+    include_thoughts: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Represents a request to the `countTokens` endpoint, counting a whole `generateContent` request.
+struct CountTokensRequest {
+    generate_content_request: GenerateContentRequest,
+}
+
+#[derive(Serialize, Debug)]
+/// Represents a `generateContent` request naming its model, as expected by `countTokens`.
+struct GenerateContentRequest {
+    model: String,
+    #[serde(flatten)]
+    request: Request,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+/// Represents a response from the `models` endpoint.
+enum ModelsResponse {
+    Error { error: ErrorResponse },
+    Page(ModelsPage),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Represents a page of models.
+struct ModelsPage {
+    #[serde(default)]
+    models: Vec<Model>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Represents a model listed by the `models` endpoint, named e.g. `models/gemini-2.0-flash`.
+struct Model {
+    name: String,
+    display_name: Option<String>,
+}
+
+impl Model {
+    fn into_available(self) -> client::AvailableModel {
+        let id = self.name.strip_prefix("models/").unwrap_or(&self.name);
+        let model = client::AvailableModel::new(id, Google::NAME);
+        match self.display_name {
+            Some(display_name) => model.with_display_name(display_name),
+            None => model,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+/// Represents a response from the `countTokens` endpoint.
+enum CountTokensResponse {
+    Error {
+        error: ErrorResponse,
+    },
+    Count {
+        #[serde(rename = "totalTokens")]
+        total_tokens: usize,
+    },
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Represents a request to the `embedContent` endpoint of the Gemini API.
+struct EmbedContentRequest {
+    model: String,
+    content: Content,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_dimensionality: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+/// Represents a request to the `batchEmbedContents` endpoint of the Gemini API.
+struct BatchEmbedContentsRequest {
+    requests: Vec<EmbedContentRequest>,
+}
+
+#[derive(Serialize, Debug)]
+/// Represents a request to the `predict` endpoint of an Imagen model.
+struct PredictRequest {
+    instances: Vec<PredictInstance>,
+    parameters: PredictParameters,
+}
+
+#[derive(Serialize, Debug)]
+struct PredictInstance {
+    prompt: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PredictParameters {
+    sample_count: usize,
+    aspect_ratio: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+/// Represents a response from the `predict` endpoint of an Imagen model.
+enum PredictResponse {
+    Predictions { predictions: Vec<Prediction> },
+    Error { error: ErrorResponse },
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Represents a single image generated by an Imagen model.
+struct Prediction {
+    bytes_base64_encoded: String,
+    mime_type: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+/// Represents a response from the embedding endpoints of the Gemini API.
+enum EmbeddingResponse {
+    Single { embedding: ContentEmbedding },
+    Batch { embeddings: Vec<ContentEmbedding> },
+    Error { error: ErrorResponse },
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents the vector of a single text.
+struct ContentEmbedding {
+    values: Vec<f32>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(untagged)]
-/// Represents a response from the Gemini API.
+/// Represents a response from the Gemini API. Errors are tried first, as every field of a
+/// successful response is optional.
 enum Response {
-    Success(SuccessResponse),
     Error { error: ErrorResponse },
+    Success(SuccessResponse),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-/// Represents a successful response from the Gemini API.
+/// Represents a successful response from the Gemini API, without candidates when the prompt was
+/// blocked.
 struct SuccessResponse {
+    #[serde(default)]
     candidates: Vec<Candidate>,
     model_version: Option<String>,
     usage_metadata: Option<UsageMetadata>,
     prompt_feedback: Option<PromptFeedback>,
 }
 
+impl SuccessResponse {
+    /// Converts the response, failing with a `ContentBlockedError` when it has no candidates,
+    /// i.e. when the prompt was blocked.
+    fn into_response(self) -> anyhow::Result<client::Response> {
+        let prompt_safety_ratings = self
+            .prompt_feedback
+            .as_ref()
+            .map(PromptFeedback::ratings)
+            .unwrap_or_default();
+        if self.candidates.is_empty() {
+            let reason = self
+                .prompt_feedback
+                .and_then(|feedback| feedback.block_reason)
+                .unwrap_or_else(|| "no candidates returned".to_string());
+            return Err(ContentBlockedError {
+                reason,
+                safety_ratings: prompt_safety_ratings,
+            }
+            .into());
+        }
+
+        let usage = self.usage_metadata.as_ref().map(client::Usage::from);
+        let mut response = client::Response::from_candidates(self.candidates());
+        response.prompt_safety_ratings = prompt_safety_ratings;
+        Ok(match usage {
+            Some(usage) => response.with_usage(usage),
+            None => response,
+        })
+    }
+
+    /// Returns the content blocks and finish reason of every candidate.
+    fn candidates(self) -> Vec<client::Candidate> {
+        self.candidates
+            .into_iter()
+            .map(|candidate| client::Candidate {
+                content: candidate
+                    .content
+                    .parts
+                    .into_iter()
+                    .filter_map(Part::into_block)
+                    .collect(),
+                finish_reason: Some(candidate.finish_reason),
+                safety_ratings: candidate
+                    .safety_ratings
+                    .into_iter()
+                    .flatten()
+                    .map(client::SafetyRating::from)
+                    .collect(),
+                logprobs: candidate.logprobs_result.map(LogprobsResult::into_tokens),
+            })
+            .collect()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-/// Represents a candidate response from the Gemini API.
+/// Represents a candidate response from the Gemini API, without content when it was blocked.
 struct Candidate {
+    #[serde(default)]
     content: Content,
     finish_reason: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -300,30 +1255,132 @@ struct Candidate {
     #[serde(skip_serializing_if = "Option::is_none")]
     safety_ratings: Option<Vec<SafetyRating>>,
     avg_logprobs: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logprobs_result: Option<LogprobsResult>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Represents the log probabilities of a candidate, returned when `responseLogprobs` is set.
+struct LogprobsResult {
+    #[serde(default)]
+    top_candidates: Vec<TopCandidates>,
+    #[serde(default)]
+    chosen_candidates: Vec<LogprobCandidate>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+/// Represents the most likely tokens at a position of a candidate.
+struct TopCandidates {
+    #[serde(default)]
+    candidates: Vec<LogprobCandidate>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Represents a token with its log probability.
+struct LogprobCandidate {
+    #[serde(default)]
+    token: String,
+    #[serde(default)]
+    log_probability: f32,
+}
+
+impl LogprobsResult {
+    /// Pairs every chosen token with the top candidates at the same position.
+    fn into_tokens(self) -> Vec<client::TokenLogprob> {
+        let mut top_candidates = self.top_candidates.into_iter();
+        self.chosen_candidates
+            .into_iter()
+            .map(|chosen| client::TokenLogprob {
+                token: chosen.token,
+                logprob: chosen.log_probability,
+                top_logprobs: top_candidates
+                    .next()
+                    .map(|top| {
+                        top.candidates
+                            .into_iter()
+                            .map(|candidate| client::TopLogprob {
+                                token: candidate.token,
+                                logprob: candidate.log_probability,
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 /// Represents usage metadata for the response.
 struct UsageMetadata {
+    #[serde(default)]
     candidates_token_count: u32,
     prompt_token_count: u32,
     total_token_count: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thoughts_token_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cached_content_token_count: Option<u32>,
+}
+
+impl From<&UsageMetadata> for client::Usage {
+    /// Gemini reports thinking tokens apart from the candidate tokens, so they are added up.
+    fn from(usage: &UsageMetadata) -> Self {
+        let reasoning_tokens = usage.thoughts_token_count.unwrap_or_default();
+        Self {
+            input_tokens: usage.prompt_token_count as usize,
+            output_tokens: (usage.candidates_token_count + reasoning_tokens) as usize,
+            reasoning_tokens: usage.thoughts_token_count.map(|tokens| tokens as usize),
+            cache_creation_input_tokens: None,
+            cache_read_input_tokens: usage
+                .cached_content_token_count
+                .map(|tokens| tokens as usize),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-/// Represents feedback on the prompt.
+/// Represents feedback on the prompt, with the reason it was blocked if it was.
 struct PromptFeedback {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    block_reason: Option<String>,
+    #[serde(default)]
     safety_ratings: Vec<SafetyRating>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl PromptFeedback {
+    /// Returns the safety ratings of the prompt.
+    fn ratings(&self) -> Vec<client::SafetyRating> {
+        self.safety_ratings
+            .iter()
+            .cloned()
+            .map(client::SafetyRating::from)
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 /// Represents a safety rating for content.
 struct SafetyRating {
     category: String,
     probability: String,
+    #[serde(default)]
+    blocked: bool,
+}
+
+impl From<SafetyRating> for client::SafetyRating {
+    fn from(rating: SafetyRating) -> Self {
+        Self {
+            category: rating.category,
+            probability: rating.probability,
+            blocked: rating.blocked,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -334,11 +1391,567 @@ struct ErrorResponse {
     status: String,
 }
 
+impl ErrorResponse {
+    /// Converts the error to an `ApiError` keeping the HTTP status.
+    fn into_api_error(self, status: StatusCode) -> anyhow::Error {
+        ApiError::new(
+            status,
+            format!("{}: {} ({})", self.status, self.message, self.code),
+        )
+        .into()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Represents a file uploaded with the File API, kept by Gemini for 48 hours.
+///
+/// Its `uri` references the file in messages, see `MessageBuilder::document_url`.
+pub struct UploadedFile {
+    /// The resource name of the file, e.g. `files/abc-123`.
+    pub name: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    pub mime_type: String,
+    pub uri: String,
+    /// The processing state, `PROCESSING` until the file can be used, then `ACTIVE`.
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub expiration_time: Option<String>,
+}
+
+impl UploadedFile {
+    /// Returns a document referencing the file, to be added to a message.
+    pub fn to_document(&self) -> client::Document {
+        client::Document {
+            data: String::new(),
+            mime_type: self.mime_type.clone(),
+            name: self.display_name.clone(),
+            url: Some(self.uri.clone()),
+            cache: false,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+/// Represents the metadata sent when starting an upload.
+struct UploadRequest {
+    file: UploadMetadata,
+}
+
+#[derive(Serialize, Debug)]
+struct UploadMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    display_name: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+/// Represents a response from the File API.
+enum FileResponse {
+    Error { error: ErrorResponse },
+    Uploaded { file: UploadedFile },
+    File(UploadedFile),
+}
+
+impl FileResponse {
+    fn into_file(self, status: StatusCode) -> anyhow::Result<UploadedFile> {
+        match self {
+            FileResponse::Uploaded { file } | FileResponse::File(file) => Ok(file),
+            FileResponse::Error { error } => Err(error.into_api_error(status)),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Represents content cached by Gemini, e.g. long documents shared by many messages.
+///
+/// Messages use it by setting `GenerationOptions::cached_content` to its `name`, and must be
+/// sent to the same model without a system prompt, which is cached with the content.
+pub struct CachedContent {
+    /// The resource name of the cached content, e.g. `cachedContents/abc-123`.
+    pub name: String,
+    /// The model using the cached content, e.g. `models/gemini-1.5-flash-002`.
+    pub model: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub expire_time: Option<String>,
+    /// The number of tokens cached.
+    #[serde(default)]
+    pub usage_metadata: Option<CachedContentUsage>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedContentUsage {
+    pub total_token_count: u32,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Represents a request creating cached content.
+struct CachedContentRequest {
+    model: String,
+    contents: Vec<Content>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<SystemInstruction>,
+    ttl: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+/// Represents a response from the `cachedContents` endpoint.
+enum CachedContentResponse {
+    Error { error: ErrorResponse },
+    CachedContent(CachedContent),
+}
+
 #[cfg(test)]
 /// Unit tests for the Google module.
 mod tests {
     use super::*;
 
+    #[test]
+    /// Tests parsing responses from the embedding endpoints of the Gemini API.
+    fn test_parse_embeddings() {
+        let single = r#"{"embedding": {"values": [0.1, 0.2, 0.3]}}"#;
+        let single = serde_json::from_str::<EmbeddingResponse>(single).unwrap();
+        assert!(
+            matches!(single, EmbeddingResponse::Single { embedding } if embedding.values.len() == 3)
+        );
+
+        let batch = r#"{"embeddings": [{"values": [0.1]}, {"values": [0.2]}]}"#;
+        let batch = serde_json::from_str::<EmbeddingResponse>(batch).unwrap();
+        assert!(matches!(batch, EmbeddingResponse::Batch { embeddings } if embeddings.len() == 2));
+    }
+
+    #[test]
+    /// Tests reading every part of a candidate as content blocks.
+    fn test_candidate_blocks() {
+        let res = r#"
+        {
+          "candidates": [
+            {
+              "content": {
+                "parts": [
+                  { "text": "The user wants the weather.", "thought": true },
+                  { "text": "Let me check " },
+                  { "text": "the weather." },
+                  { "functionCall": { "name": "weather", "args": { "city": "Lisbon" } } }
+                ],
+                "role": "model"
+              },
+              "finishReason": "STOP"
+            }
+          ]
+        }
+        "#;
+        let Response::Success(success) = serde_json::from_str::<Response>(res).unwrap() else {
+            panic!("Expected successful response");
+        };
+        let response = client::Response::from_candidates(success.candidates());
+        assert_eq!(response.text(), "Let me check the weather.");
+        assert_eq!(
+            response.content[0],
+            client::ContentBlock::Thinking {
+                text: "The user wants the weather.".to_string(),
+                signature: None,
+            }
+        );
+        assert_eq!(
+            response.content[3],
+            client::ContentBlock::ToolCall {
+                id: None,
+                name: "weather".to_string(),
+                arguments: serde_json::json!({"city": "Lisbon"}),
+            }
+        );
+    }
+
+    #[test]
+    /// Tests requesting several candidates.
+    fn test_build_request_with_candidates() {
+        let message = client::Message {
+            text: "Hello, world!".to_string(),
+            options: client::GenerationOptions::new().n(3),
+            ..Default::default()
+        };
+        let request = build_request(message, "gemini-2.0-flash");
+        assert_eq!(request.generation_config.candidate_count, Some(3));
+    }
+
+    #[test]
+    /// Tests requesting thoughts and replaying previous turns.
+    fn test_build_request_with_reasoning() {
+        let first = client::Response::from_content(vec![
+            client::ContentBlock::Thinking {
+                text: "Seven is prime.".to_string(),
+                signature: None,
+            },
+            client::ContentBlock::Text {
+                text: "7".to_string(),
+            },
+        ]);
+        let message = client::Message {
+            text: "Double it.".to_string(),
+            options: client::GenerationOptions::new()
+                .reasoning(client::Reasoning::BudgetTokens(1024)),
+            history: Some(vec![
+                client::Turn::user("Pick a prime."),
+                client::Turn::assistant(&first),
+            ]),
+            ..Default::default()
+        };
+        let request = build_request(message, "gemini-2.5-flash");
+        assert_eq!(
+            serde_json::to_value(&request.generation_config.thinking_config).unwrap(),
+            serde_json::json!({"thinkingBudget": 1024, "includeThoughts": true})
+        );
+        assert_eq!(request.contents.len(), 3);
+        assert_eq!(request.contents[1].role, "model");
+        assert_eq!(request.contents[1].parts.len(), 1);
+        assert_eq!(request.contents[1].parts[0].as_text(), Some("7"));
+    }
+
+    #[test]
+    /// Tests dropping the reasoning settings of models without thinking.
+    fn test_build_request_without_thinking() {
+        let message = client::Message {
+            text: "Hi".to_string(),
+            options: client::GenerationOptions::new()
+                .reasoning(client::Reasoning::BudgetTokens(1024)),
+            ..Default::default()
+        };
+        let request = build_request(message, "gemini-2.0-flash");
+        assert!(request.generation_config.thinking_config.is_none());
+    }
+
+    #[test]
+    /// Tests failing with a typed error when the prompt is blocked.
+    fn test_blocked_prompt() {
+        let res = r#"
+        {
+          "promptFeedback": {
+            "blockReason": "SAFETY",
+            "safetyRatings": [
+              { "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "probability": "HIGH", "blocked": true }
+            ]
+          }
+        }
+        "#;
+        let Response::Success(success) = serde_json::from_str::<Response>(res).unwrap() else {
+            panic!("Expected successful response");
+        };
+        let err = success.into_response().unwrap_err();
+        let err = err.downcast_ref::<ContentBlockedError>().unwrap();
+        assert_eq!(err.reason, "SAFETY");
+        assert_eq!(err.safety_ratings.len(), 1);
+        assert!(err.safety_ratings[0].blocked);
+    }
+
+    #[test]
+    /// Tests reading the safety ratings of a candidate blocked without content.
+    fn test_blocked_candidate() {
+        let res = r#"
+        {
+          "candidates": [
+            {
+              "finishReason": "SAFETY",
+              "index": 0,
+              "safetyRatings": [
+                { "category": "HARM_CATEGORY_HARASSMENT", "probability": "MEDIUM", "blocked": true }
+              ]
+            }
+          ],
+          "promptFeedback": {
+            "safetyRatings": [{ "category": "HARM_CATEGORY_HARASSMENT", "probability": "LOW" }]
+          }
+        }
+        "#;
+        let Response::Success(success) = serde_json::from_str::<Response>(res).unwrap() else {
+            panic!("Expected successful response");
+        };
+        let response = success.into_response().unwrap();
+        assert_eq!(response.text(), "");
+        assert_eq!(response.prompt_safety_ratings[0].probability, "LOW");
+        let candidate = &response.candidates()[0];
+        assert_eq!(candidate.finish_reason.as_deref(), Some("SAFETY"));
+        assert_eq!(
+            candidate.safety_ratings,
+            vec![client::SafetyRating {
+                category: "HARM_CATEGORY_HARASSMENT".to_string(),
+                probability: "MEDIUM".to_string(),
+                blocked: true,
+            }]
+        );
+    }
+
+    #[test]
+    /// Tests requesting log probabilities and pairing chosen tokens with their alternatives.
+    fn test_logprobs() {
+        let message = client::Message {
+            text: "Is it spam?".to_string(),
+            options: client::GenerationOptions::new().logprobs(2),
+            ..Default::default()
+        };
+        let request = build_request(message, "gemini-2.0-flash");
+        let config = serde_json::to_value(&request.generation_config).unwrap();
+        assert_eq!(config["responseLogprobs"], true);
+        assert_eq!(config["logprobs"], 2);
+
+        let res = r#"
+        {
+          "candidates": [
+            {
+              "content": { "parts": [{ "text": "No" }], "role": "model" },
+              "finishReason": "STOP",
+              "avgLogprobs": -0.05,
+              "logprobsResult": {
+                "topCandidates": [
+                  {
+                    "candidates": [
+                      { "token": "No", "logProbability": -0.05 },
+                      { "token": "Yes", "logProbability": -3.1 }
+                    ]
+                  }
+                ],
+                "chosenCandidates": [{ "token": "No", "logProbability": -0.05 }]
+              }
+            }
+          ]
+        }
+        "#;
+        let Response::Success(success) = serde_json::from_str::<Response>(res).unwrap() else {
+            panic!("Expected successful response");
+        };
+        let response = success.into_response().unwrap();
+        let logprobs = response.logprobs().unwrap();
+        assert_eq!(logprobs.len(), 1);
+        assert_eq!(logprobs[0].token, "No");
+        assert_eq!(logprobs[0].logprob, -0.05);
+        assert_eq!(logprobs[0].top_logprobs[1].token, "Yes");
+    }
+
+    #[test]
+    /// Tests sending the safety settings of a message.
+    fn test_build_request_with_safety_settings() {
+        let message = client::Message {
+            text: "Hello, world!".to_string(),
+            options: client::GenerationOptions::new()
+                .safety_setting(HarmCategory::Harassment, HarmBlockThreshold::BlockNone)
+                .safety_setting(HarmCategory::Harassment, HarmBlockThreshold::BlockOnlyHigh),
+            ..Default::default()
+        };
+        let request = build_request(message, "gemini-2.0-flash");
+        assert_eq!(
+            serde_json::to_value(&request.safety_settings).unwrap(),
+            serde_json::json!([
+                {"category": "HARM_CATEGORY_HARASSMENT", "threshold": "BLOCK_ONLY_HIGH"}
+            ])
+        );
+    }
+
+    #[test]
+    /// Tests parsing files returned by the upload and get endpoints of the File API.
+    fn test_parse_uploaded_file() {
+        let file = r#"
+        {
+          "name": "files/abc-123",
+          "displayName": "fax.pdf",
+          "mimeType": "application/pdf",
+          "sizeBytes": "48213",
+          "uri": "https://generativelanguage.googleapis.com/v1beta/files/abc-123",
+          "state": "ACTIVE"
+        }
+        "#;
+        let uploaded = format!(r#"{{"file": {file}}}"#);
+        for res in [file, uploaded.as_str()] {
+            let file = serde_json::from_str::<FileResponse>(res)
+                .unwrap()
+                .into_file(StatusCode::OK)
+                .unwrap();
+            assert_eq!(file.name, "files/abc-123");
+            assert_eq!(file.state.as_deref(), Some("ACTIVE"));
+
+            let document = file.to_document();
+            assert_eq!(document.url.as_deref(), Some(file.uri.as_str()));
+            assert_eq!(document.mime_type, "application/pdf");
+        }
+
+        let res =
+            r#"{"error": {"code": 404, "message": "File not found.", "status": "NOT_FOUND"}}"#;
+        let err = serde_json::from_str::<FileResponse>(res)
+            .unwrap()
+            .into_file(StatusCode::NOT_FOUND)
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<ApiError>().unwrap().status, 404);
+    }
+
+    #[test]
+    /// Tests caching a system prompt and an uploaded document.
+    fn test_cached_content_request() {
+        let message = client::Message {
+            system: Some("You read faxes.".to_string()),
+            documents: Some(vec![client::Document {
+                data: String::new(),
+                mime_type: "application/pdf".to_string(),
+                name: None,
+                url: Some("https://generativelanguage.googleapis.com/v1beta/files/abc".to_string()),
+                cache: false,
+            }]),
+            ..Default::default()
+        };
+        let request =
+            cached_content_request(message, "gemini-1.5-flash-002", Duration::from_secs(600));
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({
+                "model": "models/gemini-1.5-flash-002",
+                "contents": [{
+                    "parts": [{
+                        "fileData": {
+                            "mimeType": "application/pdf",
+                            "fileUri": "https://generativelanguage.googleapis.com/v1beta/files/abc"
+                        }
+                    }],
+                    "role": "user"
+                }],
+                "systemInstruction": {"parts": [{"text": "You read faxes."}]},
+                "ttl": "600s"
+            })
+        );
+    }
+
+    #[test]
+    /// Tests that requests using cached content do not send the default system instruction.
+    fn test_build_request_with_cached_content() {
+        let message = client::Message {
+            text: "Who sent this fax?".to_string(),
+            options: client::GenerationOptions::new().cached_content("cachedContents/abc"),
+            ..Default::default()
+        };
+        let request = build_request(message, "gemini-2.0-flash");
+        assert_eq!(
+            request.cached_content.as_deref(),
+            Some("cachedContents/abc")
+        );
+        assert!(request.system_instruction.is_none());
+    }
+
+    #[test]
+    /// Tests rejecting a system prompt along with cached content.
+    fn test_check_cached_content() {
+        let message = client::Message {
+            system: Some("You read faxes.".to_string()),
+            options: client::GenerationOptions::new().cached_content("cachedContents/abc"),
+            ..Default::default()
+        };
+        let err = check_cached_content(&message).unwrap_err();
+        assert!(err.to_string().contains("cannot set a system prompt"));
+
+        let message = client::Message {
+            options: client::GenerationOptions::new().cached_content("cachedContents/abc"),
+            ..Default::default()
+        };
+        assert!(check_cached_content(&message).is_ok());
+    }
+
+    #[test]
+    /// Tests counting thinking tokens as output tokens.
+    fn test_usage() {
+        let usage = serde_json::from_str::<UsageMetadata>(
+            r#"{"promptTokenCount": 10, "candidatesTokenCount": 5, "thoughtsTokenCount": 20, "totalTokenCount": 35}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            client::Usage::from(&usage),
+            client::Usage {
+                input_tokens: 10,
+                output_tokens: 25,
+                reasoning_tokens: Some(20),
+                cache_creation_input_tokens: None,
+                cache_read_input_tokens: None,
+            }
+        );
+    }
+
+    #[test]
+    /// Tests reading a page of the models endpoint, without the `models/` prefix.
+    fn test_parse_models() {
+        let res = r#"
+        {
+          "models": [
+            {
+              "name": "models/gemini-2.0-flash-001",
+              "version": "2.0",
+              "displayName": "Gemini 2.0 Flash 001",
+              "inputTokenLimit": 1048576,
+              "outputTokenLimit": 8192,
+              "supportedGenerationMethods": ["generateContent", "countTokens"]
+            }
+          ],
+          "nextPageToken": "Cg9nZW1pbmktMi4w"
+        }
+        "#;
+        let ModelsResponse::Page(page) = serde_json::from_str(res).unwrap() else {
+            panic!("response is not a page of models");
+        };
+        assert_eq!(page.next_page_token.as_deref(), Some("Cg9nZW1pbmktMi4w"));
+        let model = page.models.into_iter().next().unwrap().into_available();
+        assert_eq!(model.id, "gemini-2.0-flash-001");
+        assert_eq!(model.display_name.as_deref(), Some("Gemini 2.0 Flash 001"));
+        assert_eq!(model.info.unwrap().name, "gemini-2.0-flash");
+    }
+
+    #[test]
+    /// Tests wrapping a request for the `countTokens` endpoint, and parsing its response.
+    fn test_count_tokens() {
+        let message = client::Message {
+            text: "Hello, world!".to_string(),
+            ..Default::default()
+        };
+        let request = CountTokensRequest {
+            generate_content_request: GenerateContentRequest {
+                model: "models/gemini-2.0-flash".to_string(),
+                request: build_request(message, "gemini-2.0-flash"),
+            },
+        };
+        let request = serde_json::to_value(request).unwrap();
+        let inner = &request["generateContentRequest"];
+        assert_eq!(inner["model"], "models/gemini-2.0-flash");
+        assert_eq!(inner["contents"][0]["parts"][0]["text"], "Hello, world!");
+
+        let response = r#"{"totalTokens": 5, "promptTokensDetails": []}"#;
+        assert!(matches!(
+            serde_json::from_str::<CountTokensResponse>(response).unwrap(),
+            CountTokensResponse::Count { total_tokens: 5 }
+        ));
+    }
+
+    #[test]
+    /// Tests parsing a response from the `predict` endpoint of an Imagen model.
+    fn test_parse_predictions() {
+        let res = r#"
+        {
+          "predictions": [
+            { "bytesBase64Encoded": "/9j/4AAQ", "mimeType": "image/jpeg" },
+            { "bytesBase64Encoded": "iVBORw0KGgo=" }
+          ]
+        }
+        "#;
+        match serde_json::from_str::<PredictResponse>(res).unwrap() {
+            PredictResponse::Predictions { predictions } => {
+                assert_eq!(predictions.len(), 2);
+                assert_eq!(predictions[0].mime_type.as_deref(), Some("image/jpeg"));
+                assert!(predictions[1].mime_type.is_none());
+            }
+            PredictResponse::Error { error } => panic!("unexpected error: {}", error.message),
+        }
+    }
+
     #[test]
     /// Tests creating a new `Google` instance.
     fn test_gemini_new() {
@@ -440,7 +2053,7 @@ mod tests {
         let message = client::Message {
             text: "Hello, world!".to_string(),
             images: None,
-            model: None,
+            ..Default::default()
         };
         let request = build_request(message, "gemini-1.5-pro");
         assert_eq!(request.contents.len(), 1);
@@ -457,7 +2070,7 @@ mod tests {
         let message = client::Message {
             text: "Hello, world!".to_string(),
             images: None,
-            model: None,
+            ..Default::default()
         };
         let request = build_request(message, "gemini-2.0-pro");
         assert_eq!(request.contents.len(), 1);
@@ -476,8 +2089,9 @@ mod tests {
             images: Some(vec![client::Image {
                 data: "data".to_string(),
                 mime_type: "image/png".to_string(),
+                url: None,
             }]),
-            model: None,
+            ..Default::default()
         };
         let request = build_request(message, "gemini-2.0-pro-vision");
         assert_eq!(request.contents.len(), 1);
@@ -489,6 +2103,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_build_request_with_image_url() {
+        let message = client::Message {
+            text: "Hello, world!".to_string(),
+            images: Some(vec![client::Image {
+                data: String::new(),
+                mime_type: "image/png".to_string(),
+                url: Some("https://example.com/image.png".to_string()),
+            }]),
+            ..Default::default()
+        };
+        let request = build_request(message, "gemini-2.0-flash");
+        assert_eq!(
+            serde_json::to_value(&request.contents[0].parts[0]).unwrap(),
+            serde_json::json!({
+                "fileData": {
+                    "mimeType": "image/png",
+                    "fileUri": "https://example.com/image.png"
+                }
+            })
+        );
+    }
+
     #[test]
     fn test_deserialize_gemini_2_response() {
         let response = r#"
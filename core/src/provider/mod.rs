@@ -1,16 +1,288 @@
 use async_trait::async_trait;
+use reqwest::StatusCode;
 
 mod anthropic;
+mod balancer;
 mod google;
+mod ollama;
 mod openai;
 
 pub use anthropic::Anthropic;
-pub use google::Google;
-pub use openai::OpenAI;
+pub use balancer::{Balancer, MemberHealth, Strategy};
+pub use google::{
+    CachedContent, CachedContentUsage, Google, HarmBlockThreshold, HarmCategory, SafetySetting,
+    UploadedFile,
+};
+pub use ollama::Ollama;
+pub use openai::{OpenAI, OpenAIApi};
 
-use crate::client::{Message, Response};
+use crate::{
+    client::{
+        AvailableModel, EmbeddingRequest, Embeddings, GeneratedImage, ImageGenerationRequest,
+        Message, Response, SafetyRating, Transcription, TranscriptionRequest,
+    },
+    image::ImageLimits,
+};
 
 #[async_trait]
-pub trait AIProvider {
+pub trait AIProvider: Send + Sync {
     async fn send_message(&self, message: Message) -> anyhow::Result<Response>;
+
+    /// Returns the name of the provider, e.g. `openai`.
+    fn name(&self) -> &str {
+        "custom"
+    }
+
+    /// Returns the model targeted by the provider, if it targets a single one.
+    fn model(&self) -> Option<&str> {
+        None
+    }
+
+    /// Returns `true` if the provider accepts images by URL, otherwise they are downloaded and
+    /// inlined before being sent.
+    fn supports_image_urls(&self) -> bool {
+        false
+    }
+
+    /// Returns the limits the provider puts on the images of a message.
+    fn image_limits(&self) -> ImageLimits {
+        ImageLimits::default()
+    }
+
+    /// Returns the maximum number of tokens the provider requests a model to generate, before
+    /// adding any reasoning budget, if it sets one.
+    fn max_tokens(&self, _model: &str) -> Option<usize> {
+        None
+    }
+
+    /// Transcribes audio to text.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the provider does not support transcription, which is the default.
+    async fn transcribe(&self, request: TranscriptionRequest) -> anyhow::Result<Transcription> {
+        Err(anyhow::anyhow!(
+            "{} does not support transcription with {}",
+            self.name(),
+            request.model
+        ))
+    }
+
+    /// Counts the input tokens of a message, as the model would bill them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the provider does not support counting tokens, which is the default.
+    async fn count_tokens(&self, message: Message) -> anyhow::Result<usize> {
+        Err(anyhow::anyhow!(
+            "{} does not support counting the tokens of {}",
+            self.name(),
+            message
+                .model
+                .as_deref()
+                .or(self.model())
+                .unwrap_or("its models")
+        ))
+    }
+
+    /// Lists the models served by the provider, with their catalog entry when they are known.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the provider does not support listing models, which is the default.
+    async fn list_models(&self) -> anyhow::Result<Vec<AvailableModel>> {
+        Err(anyhow::anyhow!(
+            "{} does not support listing models",
+            self.name()
+        ))
+    }
+
+    /// Returns the provider as an `EmbeddingProvider`, if it supports embeddings.
+    fn as_embedding_provider(&self) -> Option<&dyn EmbeddingProvider> {
+        None
+    }
+
+    /// Returns the provider as an `ImageGenerationProvider`, if it can generate images.
+    fn as_image_generator(&self) -> Option<&dyn ImageGenerationProvider> {
+        None
+    }
+}
+
+/// A provider that computes embeddings, i.e. vectors representing the meaning of texts.
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Computes the embeddings of a batch of texts, in order.
+    ///
+    /// Vectors are returned as computed by the provider, `Client::embed` normalizes them.
+    async fn embed(&self, request: EmbeddingRequest) -> anyhow::Result<Embeddings>;
+
+    /// Returns the maximum number of texts embedded in a single request.
+    fn max_batch_size(&self) -> usize {
+        100
+    }
+}
+
+/// A provider that generates images from a prompt.
+#[async_trait]
+pub trait ImageGenerationProvider: Send + Sync {
+    /// Generates the requested number of images.
+    async fn generate_image(
+        &self,
+        request: ImageGenerationRequest,
+    ) -> anyhow::Result<Vec<GeneratedImage>>;
+}
+
+/// A boxed future, as returned by the `async_trait` methods of providers.
+type BoxFuture<'a, T> = std::pin::Pin<Box<dyn std::future::Future<Output = T> + Send + 'a>>;
+
+/// Creates the provider that serves the given model.
+///
+/// # Arguments
+///
+/// * `model` - A string slice that holds the name of the model.
+/// * `api_key` - The API key to use, or `None` to read it from the provider's environment variable.
+/// * `http` - The HTTP client used to reach the provider.
+///
+/// # Errors
+///
+/// Returns an error if the model is unsupported or its API key is not set.
+///
+pub fn for_model(
+    model: &str,
+    api_key: Option<String>,
+    http: &reqwest::Client,
+) -> anyhow::Result<Box<dyn AIProvider>> {
+    match vendor(model) {
+        Some(vendor) => for_vendor(vendor, model, api_key, http),
+        None => Err(anyhow::anyhow!("unsupported model: {model}")),
+    }
+}
+
+/// Creates the provider of the given vendor, targeting the given model.
+///
+/// # Arguments
+///
+/// * `vendor` - The name of the provider, one of `VENDORS`.
+/// * `model` - A string slice that holds the name of the model.
+/// * `api_key` - The API key to use, or `None` to read it from the provider's environment variable.
+/// * `http` - The HTTP client used to reach the provider.
+///
+/// # Errors
+///
+/// Returns an error if the vendor is unknown or its API key is not set.
+///
+pub fn for_vendor(
+    vendor: &str,
+    model: &str,
+    api_key: Option<String>,
+    http: &reqwest::Client,
+) -> anyhow::Result<Box<dyn AIProvider>> {
+    let api_key = |var: &str| match &api_key {
+        Some(api_key) => Ok(api_key.clone()),
+        None => std::env::var(var).map_err(|_| anyhow::anyhow!("{var} is not set")),
+    };
+
+    let provider: Box<dyn AIProvider> = match vendor {
+        OpenAI::NAME => Box::new(
+            OpenAI::new(api_key(OpenAI::API_KEY_ENV)?, model).with_http_client(http.clone()),
+        ),
+        Anthropic::NAME => Box::new(
+            Anthropic::new(api_key(Anthropic::API_KEY_ENV)?, model).with_http_client(http.clone()),
+        ),
+        Google::NAME => Box::new(
+            Google::new(api_key(Google::API_KEY_ENV)?, model).with_http_client(http.clone()),
+        ),
+        _ => return Err(anyhow::anyhow!("unsupported provider: {vendor}")),
+    };
+
+    Ok(provider)
+}
+
+/// The names of the providers serving models directly, as opposed to custom providers.
+pub const VENDORS: &[&str] = &[OpenAI::NAME, Anthropic::NAME, Google::NAME];
+
+/// Returns the name of the provider serving the given model, e.g. `openai` for `gpt-4o`.
+///
+/// # Arguments
+///
+/// * `model` - A string slice that holds the name of the model.
+///
+pub fn vendor(model: &str) -> Option<&'static str> {
+    if model.starts_with("gpt")
+        || model.starts_with("whisper")
+        || model.starts_with("dall-e")
+        || openai::is_reasoning_model(model)
+    {
+        return Some(OpenAI::NAME);
+    }
+
+    if model.starts_with("claude") {
+        return Some(Anthropic::NAME);
+    }
+
+    if model.starts_with("gemini") || model.starts_with("imagen") {
+        return Some(Google::NAME);
+    }
+
+    None
+}
+
+/// Represents an error response returned by a provider's HTTP API.
+///
+/// Providers wrap it in an `anyhow::Error`, so callers can recover the HTTP status with
+/// `error.downcast_ref::<ApiError>()`.
+#[derive(thiserror::Error, Debug)]
+#[error("{message}")]
+pub struct ApiError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl ApiError {
+    /// Creates a new `ApiError` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - The HTTP status code of the response.
+    /// * `message` - The error message.
+    ///
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status: status.as_u16(),
+            message: message.into(),
+        }
+    }
+
+    /// Returns `true` if the error is transient, i.e. the provider was rate limited (429) or
+    /// failed on its side (5xx).
+    pub fn is_transient(&self) -> bool {
+        self.status == 429 || self.status >= 500
+    }
+}
+
+/// Represents a prompt or response blocked by the safety filters of a provider.
+///
+/// Like `ApiError`, it is wrapped in an `anyhow::Error` and can be recovered with
+/// `error.downcast_ref::<ContentBlockedError>()`.
+#[derive(thiserror::Error, Debug)]
+#[error("content blocked: {reason}")]
+pub struct ContentBlockedError {
+    /// The reason given by the provider, e.g. `SAFETY`.
+    pub reason: String,
+    pub safety_ratings: Vec<SafetyRating>,
+}
+
+/// Reads the JSON body of a provider response, keeping the HTTP status around.
+///
+/// Non-JSON bodies on error statuses (e.g. a gateway's HTML 502 page) are turned into an
+/// `ApiError` so the status is not lost.
+async fn read_json(response: reqwest::Response) -> anyhow::Result<(StatusCode, serde_json::Value)> {
+    let status = response.status();
+    let body = response.text().await?;
+
+    match serde_json::from_str(&body) {
+        Ok(json) => Ok((status, json)),
+        Err(_) if !status.is_success() => Err(ApiError::new(status, body).into()),
+        Err(err) => Err(err.into()),
+    }
 }
@@ -0,0 +1,343 @@
+use async_trait::async_trait;
+use log::{debug, trace};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::{self, Timeouts},
+    document,
+};
+
+use super::{read_json, AIProvider, ApiError, EmbeddingProvider};
+
+const BASE_URL: &str = "http://localhost:11434/";
+const EMBEDDING_MODEL: &str = "nomic-embed-text";
+/// Number of inputs sent in a single request to the embed endpoint.
+const MAX_EMBEDDING_INPUTS: usize = 512;
+
+/// Represents an Ollama server running models locally.
+pub struct Ollama {
+    client: Client,
+    model: String,
+    base_url: String,
+}
+
+impl Ollama {
+    /// The name of the provider, as returned by `AIProvider::name`.
+    pub const NAME: &'static str = "ollama";
+
+    /// Creates a new `Ollama` instance reaching the server on `localhost:11434`.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - A string slice that holds the name of the model, e.g. `llama3.1`.
+    ///
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            client: Timeouts::default().http_client(),
+            model: model.into(),
+            base_url: BASE_URL.to_string(),
+        }
+    }
+
+    /// Sets the model for the `Ollama` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - A string slice that holds the name of the model.
+    ///
+    pub fn with_model(self, model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            ..self
+        }
+    }
+
+    /// Sets the HTTP client used by the `Ollama` instance, e.g. one built with custom timeouts.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` instance.
+    ///
+    pub fn with_http_client(self, client: Client) -> Self {
+        Self { client, ..self }
+    }
+
+    /// Sets the URL of the Ollama server.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - The URL of the server, e.g. `http://gpu-box:11434/`.
+    ///
+    pub fn with_base_url(self, base_url: impl Into<String>) -> Self {
+        let mut base_url = base_url.into();
+        if !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+        Self { base_url, ..self }
+    }
+}
+
+#[async_trait]
+impl AIProvider for Ollama {
+    /// Sends a message to the chat endpoint of the Ollama server.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - A `client::Message` instance containing the message to be sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response contains an error.
+    ///
+    async fn send_message(&self, message: client::Message) -> anyhow::Result<client::Response> {
+        message.options.check_single_candidate(Self::NAME)?;
+        message.options.check_no_logprobs(Self::NAME)?;
+        if message
+            .audio
+            .as_ref()
+            .is_some_and(|audio| !audio.is_empty())
+        {
+            anyhow::bail!("Ollama models do not accept audio");
+        }
+
+        let mut content = message.text;
+        for doc in message.documents.unwrap_or_default() {
+            content.push_str("\n\n");
+            content.push_str(&document::read_text(doc).await?);
+        }
+
+        let images = message
+            .images
+            .unwrap_or_default()
+            .into_iter()
+            .map(|image| image.data)
+            .collect();
+
+        let system = message.system.map(|system| ChatMessage {
+            role: "system".to_string(),
+            content: system,
+            images: Vec::new(),
+        });
+        let mut messages = system
+            .into_iter()
+            .chain(
+                message
+                    .history
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(ChatMessage::from),
+            )
+            .collect::<Vec<_>>();
+        messages.push(ChatMessage {
+            role: "user".to_string(),
+            content,
+            images,
+        });
+
+        let request = ChatRequest {
+            model: message.model.unwrap_or_else(|| self.model.clone()),
+            messages,
+            stream: false,
+        };
+
+        trace!(
+            "JSON Request: {}",
+            serde_json::to_string_pretty(&request).unwrap()
+        );
+
+        let response = self
+            .client
+            .post(format!("{}api/chat", self.base_url))
+            .json(&request)
+            .send()
+            .await?;
+
+        let (status, response) = read_json(response).await?;
+        trace!(
+            "JSON Response: {}",
+            serde_json::to_string_pretty(&response).unwrap()
+        );
+
+        let response = serde_json::from_value::<ChatResponse>(response)?;
+        debug!("Ollama Response: {:#?}", response);
+
+        match response {
+            ChatResponse::Message { message } => Ok(client::Response::new(message.content)),
+            ChatResponse::Error { error } => Err(ApiError::new(status, error).into()),
+        }
+    }
+
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn model(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+
+    fn as_embedding_provider(&self) -> Option<&dyn EmbeddingProvider> {
+        Some(self)
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for Ollama {
+    /// Computes embeddings with the embed endpoint of the Ollama server.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A `client::EmbeddingRequest` instance containing the texts.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response contains an error.
+    ///
+    async fn embed(&self, request: client::EmbeddingRequest) -> anyhow::Result<client::Embeddings> {
+        let request = EmbedRequest {
+            model: request.model.unwrap_or_else(|| EMBEDDING_MODEL.to_string()),
+            input: request.texts,
+            dimensions: request.dimensions,
+        };
+
+        let response = self
+            .client
+            .post(format!("{}api/embed", self.base_url))
+            .json(&request)
+            .send()
+            .await?;
+
+        let (status, response) = read_json(response).await?;
+        match serde_json::from_value::<EmbedResponse>(response)? {
+            EmbedResponse::Embeddings {
+                embeddings,
+                prompt_eval_count,
+            } => {
+                let usage =
+                    prompt_eval_count.map(|prompt_tokens| client::EmbeddingUsage { prompt_tokens });
+                Ok(client::Embeddings::new(embeddings, usage))
+            }
+            EmbedResponse::Error { error } => Err(ApiError::new(status, error).into()),
+        }
+    }
+
+    fn max_batch_size(&self) -> usize {
+        MAX_EMBEDDING_INPUTS
+    }
+}
+
+#[derive(Serialize, Debug)]
+/// Represents a request to the chat endpoint of the Ollama server.
+struct ChatRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    stream: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+/// Represents a chat message, with its images base64 encoded.
+struct ChatMessage {
+    role: String,
+    content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
+}
+
+impl From<client::Turn> for ChatMessage {
+    /// Replays a previous turn with its text.
+    fn from(turn: client::Turn) -> Self {
+        let role = match turn.role {
+            client::Role::User => "user",
+            client::Role::Assistant => "assistant",
+        };
+        Self {
+            role: role.to_string(),
+            content: turn.text(),
+            images: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+/// Represents a response from the chat endpoint of the Ollama server.
+enum ChatResponse {
+    Message { message: ChatMessage },
+    Error { error: String },
+}
+
+#[derive(Serialize, Debug)]
+/// Represents a request to the embed endpoint of the Ollama server.
+struct EmbedRequest {
+    model: String,
+    input: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+/// Represents a response from the embed endpoint of the Ollama server.
+enum EmbedResponse {
+    Embeddings {
+        embeddings: Vec<Vec<f32>>,
+        prompt_eval_count: Option<usize>,
+    },
+    Error {
+        error: String,
+    },
+}
+
+#[cfg(test)]
+/// Unit tests for the Ollama module.
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Tests parsing chat responses from the Ollama server.
+    fn test_parse_chat() {
+        let res = r#"
+        {
+          "model": "llama3.1",
+          "created_at": "2024-07-22T20:33:28.123Z",
+          "message": { "role": "assistant", "content": "Hi there!" },
+          "done": true
+        }
+        "#;
+        let res = serde_json::from_str::<ChatResponse>(res).unwrap();
+        assert!(matches!(res, ChatResponse::Message { message } if message.content == "Hi there!"));
+
+        let res = r#"{"error": "model 'llama9' not found"}"#;
+        let res = serde_json::from_str::<ChatResponse>(res).unwrap();
+        assert!(matches!(res, ChatResponse::Error { error } if error.contains("llama9")));
+    }
+
+    #[test]
+    /// Tests parsing embed responses from the Ollama server.
+    fn test_parse_embed() {
+        let res = r#"
+        {
+          "model": "nomic-embed-text",
+          "embeddings": [[0.1, 0.2], [0.3, 0.4]],
+          "prompt_eval_count": 8
+        }
+        "#;
+        match serde_json::from_str::<EmbedResponse>(res).unwrap() {
+            EmbedResponse::Embeddings {
+                embeddings,
+                prompt_eval_count,
+            } => {
+                assert_eq!(embeddings.len(), 2);
+                assert_eq!(prompt_eval_count, Some(8));
+            }
+            EmbedResponse::Error { error } => panic!("unexpected error: {error}"),
+        }
+    }
+
+    #[test]
+    /// Tests that base URLs always end with a slash.
+    fn test_with_base_url() {
+        let ollama = Ollama::new("llama3.1").with_base_url("http://gpu-box:11434");
+        assert_eq!(ollama.base_url, "http://gpu-box:11434/");
+    }
+}
@@ -1,25 +1,57 @@
 #![allow(unused)]
 use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
 use log::{debug, trace};
-use reqwest::Client;
+use reqwest::{multipart, Client};
 use serde::{Deserialize, Serialize};
 
-use crate::client;
+use crate::{
+    audio::AudioFormat,
+    client::{self, Timeouts},
+    document,
+    image::ImageLimits,
+    models, tokens,
+};
 
-use super::AIProvider;
+use super::{read_json, AIProvider, ApiError, EmbeddingProvider, ImageGenerationProvider};
+
+mod responses;
 
 const MAX_TOKENS: u32 = 4096;
 const BASE_URL: &str = "https://api.openai.com/v1/";
-const MODELS: &[&str] = &["gpt-4o", "gpt-4-turbo", "gpt-4", "gpt-3.5-turbo"];
+/// The model used by `OpenAI::default`, see `models` for the catalog.
+const DEFAULT_MODEL: &str = "gpt-4o";
+const EMBEDDING_MODEL: &str = "text-embedding-3-small";
+const IMAGE_MODEL: &str = "gpt-image-1";
+/// Maximum number of inputs accepted by the embeddings endpoint.
+const MAX_EMBEDDING_INPUTS: usize = 2048;
 
 /// Represents an OpenAI client for interacting with the OpenAI API.
 pub struct OpenAI {
     client: Client,
     api_key: String,
     model: String,
+    base_url: String,
+    api: OpenAIApi,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Represents the API used by the `OpenAI` provider to send messages.
+pub enum OpenAIApi {
+    /// The `/chat/completions` endpoint, also served by OpenAI-compatible servers.
+    #[default]
+    ChatCompletions,
+    /// The `/responses` endpoint, which chains conversations with
+    /// `GenerationOptions::previous_response_id` and returns reasoning summaries.
+    Responses,
 }
 
 impl OpenAI {
+    /// The name of the provider, as returned by `AIProvider::name`.
+    pub const NAME: &'static str = "openai";
+    /// The environment variable holding the default API key.
+    pub const API_KEY_ENV: &'static str = "OPENAI_API_KEY";
+
     /// Creates a new `OpenAI` instance.
     ///
     /// # Arguments
@@ -29,9 +61,11 @@ impl OpenAI {
     ///
     pub fn new(api_key: impl Into<String>, model: impl Into<String>) -> Self {
         Self {
-            client: Client::new(),
+            client: Timeouts::default().http_client(),
             api_key: api_key.into(),
             model: model.into(),
+            base_url: BASE_URL.to_string(),
+            api: OpenAIApi::default(),
         }
     }
 
@@ -47,53 +81,175 @@ impl OpenAI {
             ..self
         }
     }
-}
 
-impl Default for OpenAI {
-    fn default() -> Self {
-        Self::new(
-            std::env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY is not set"),
-            MODELS[0],
-        )
+    /// Sets the HTTP client used by the `OpenAI` instance, e.g. one built with custom timeouts.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A `reqwest::Client` instance.
+    ///
+    pub fn with_http_client(self, client: Client) -> Self {
+        Self { client, ..self }
     }
-}
 
-#[async_trait]
-impl AIProvider for OpenAI {
-    /// Sends a message to the OpenAI API.
+    /// Sets the base URL of the API, to use an OpenAI-compatible server (e.g. vLLM or LiteLLM).
     ///
     /// # Arguments
     ///
-    /// * `message` - A `client::Message` instance containing the message to be sent.
+    /// * `base_url` - The URL the endpoints are relative to, e.g. `http://localhost:8000/v1/`.
     ///
-    /// # Errors
+    /// # Examples
     ///
-    /// Returns an error if the request fails or the response contains an error.
+    /// ```no_run
+    /// use aipim::provider::OpenAI;
     ///
-    async fn send_message(&self, message: client::Message) -> anyhow::Result<client::Response> {
-        let mut content = Content::Complex(vec![ComplexContent::Text(Text {
-            typ: "text".to_string(),
-            text: message.text,
-        })]);
+    /// let provider = OpenAI::new("token", "mistral-7b").with_base_url("http://localhost:8000/v1");
+    /// ```
+    pub fn with_base_url(self, base_url: impl Into<String>) -> Self {
+        let mut base_url = base_url.into();
+        if !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+        Self { base_url, ..self }
+    }
+
+    /// Sets the API used to send messages, `OpenAIApi::ChatCompletions` by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `api` - The API to use.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aipim::client::Client;
+    /// use aipim::provider::{OpenAI, OpenAIApi};
+    ///
+    /// let client = Client::from_provider(OpenAI::default().with_api(OpenAIApi::Responses));
+    /// ```
+    pub fn with_api(self, api: OpenAIApi) -> Self {
+        Self { api, ..self }
+    }
+
+    /// Sends a message to the `/chat/completions` endpoint.
+    async fn send_chat_completion(
+        &self,
+        message: client::Message,
+    ) -> anyhow::Result<client::Response> {
+        let model = message.model.unwrap_or_else(|| self.model.clone());
+        // Documents come first, then images and audio, and the text last, like with the
+        // Responses API.
+        let mut content = Content::Complex(Vec::new());
+
+        // The OpenAI API reads PDF documents natively, e.g. scanned faxes. Compatible servers may
+        // not, so the text of their documents is extracted locally, like other documents.
+        for doc in message.documents.unwrap_or_default() {
+            if doc.mime_type == document::PDF && doc.url.is_none() && self.base_url == BASE_URL {
+                content.push(ComplexContent::File(File {
+                    typ: "file".to_string(),
+                    file: FileData {
+                        file_data: format!("data:{};base64,{}", doc.mime_type, doc.data),
+                        filename: doc.name.unwrap_or_else(|| "document.pdf".to_string()),
+                    },
+                }));
+                continue;
+            }
+
+            let name = doc.name.clone();
+            let text = document::read_text(doc).await?;
+            let text = match name {
+                Some(name) => format!("Document {name}:\n\n{text}"),
+                None => text,
+            };
+            content.push(ComplexContent::Text(Text {
+                typ: "text".to_string(),
+                text,
+            }));
+        }
 
         for image in message.images.unwrap_or_default() {
+            let url = match image.url {
+                Some(url) => url,
+                None => format!("data:{};base64,{}", image.mime_type, image.data),
+            };
             content.push(ComplexContent::Image(Image {
                 typ: "image_url".to_string(),
-                image_url: ImageUrl {
-                    url: format!("data:image/jpeg;base64,{}", image.data),
+                image_url: ImageUrl { url },
+            }));
+        }
+
+        for audio in message.audio.unwrap_or_default() {
+            let format = match AudioFormat::from_mime_type(&audio.mime_type) {
+                Some(format @ (AudioFormat::Wav | AudioFormat::Mp3)) => format,
+                _ => anyhow::bail!(
+                    "OpenAI only accepts WAV and MP3 audio, not {}",
+                    audio.mime_type
+                ),
+            };
+            content.push(ComplexContent::InputAudio(InputAudio {
+                typ: "input_audio".to_string(),
+                input_audio: AudioData {
+                    data: audio.data,
+                    format: format.extension().to_string(),
                 },
             }));
         }
 
+        content.push(ComplexContent::Text(Text {
+            typ: "text".to_string(),
+            text: message.text,
+        }));
+
         let chat_message = ChatMessage {
             role: "user".to_string(),
             content,
         };
 
+        let system = message.system.map(|system| ChatMessage {
+            role: "system".to_string(),
+            content: Content::Simple(system),
+        });
+        let mut messages = system
+            .into_iter()
+            .chain(
+                message
+                    .history
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(ChatMessage::from),
+            )
+            .collect::<Vec<_>>();
+        messages.push(chat_message);
+
+        // Reasoning models reject `max_tokens` and count their reasoning towards
+        // `max_completion_tokens`, so the budget is added to it. Other models reject
+        // `reasoning_effort`, so it is dropped.
+        let reasoning = message
+            .options
+            .reasoning
+            .filter(|_| accepts_reasoning(&model));
+        let (max_tokens, max_completion_tokens) = match reasoning {
+            Some(reasoning) => (
+                None,
+                Some(MAX_TOKENS.saturating_add(reasoning.budget_tokens()) as usize),
+            ),
+            None if is_reasoning_model(&model) => (None, Some(MAX_TOKENS as usize)),
+            None => (Some(MAX_TOKENS as usize), None),
+        };
+
         let request = Request {
-            model: self.model.clone(),
-            messages: vec![chat_message],
-            max_tokens: MAX_TOKENS as usize,
+            model,
+            messages,
+            max_tokens,
+            max_completion_tokens,
+            n: message.options.n,
+            reasoning_effort: reasoning.map(|reasoning| reasoning.effort().as_str().to_string()),
+            response_format: message
+                .options
+                .response_format
+                .map(ChatResponseFormat::from),
+            logprobs: message.options.logprobs.map(|_| true),
+            top_logprobs: message.options.logprobs.filter(|&top_k| top_k > 0),
         };
 
         trace!(
@@ -103,13 +259,13 @@ impl AIProvider for OpenAI {
 
         let response = self
             .client
-            .post(format!("{}chat/completions", BASE_URL))
+            .post(format!("{}chat/completions", self.base_url))
             .header("Authorization", &format!("Bearer {}", self.api_key))
             .json(&request)
             .send()
             .await?;
 
-        let response: serde_json::Value = response.json().await?;
+        let (status, response) = read_json(response).await?;
         trace!(
             "JSON Response: {}",
             serde_json::to_string_pretty(&response).unwrap()
@@ -120,12 +276,11 @@ impl AIProvider for OpenAI {
 
         match response {
             Response::Message(message) => {
-                let content = &message.choices[0].message.content;
-                let text = content.as_text().ok_or_else(|| {
-                    anyhow::anyhow!("unsupported response content type: {:?}", content)
-                })?;
-
-                Ok(client::Response::new(text))
+                let usage = client::Usage::from(&message.usage);
+                let id = message.id.clone();
+                Ok(client::Response::from_candidates(message.candidates())
+                    .with_id(id)
+                    .with_usage(usage))
             }
             Response::Error { error } => {
                 let code = if let Some(code) = error.code {
@@ -133,26 +288,318 @@ impl AIProvider for OpenAI {
                 } else {
                     "".to_string()
                 };
-                Err(anyhow::anyhow!(
-                    "{}{} ({})",
-                    code,
-                    error.message,
-                    error.param
-                ))
+                Err(ApiError::new(
+                    status,
+                    format!("{}{} ({})", code, error.message, error.param),
+                )
+                .into())
             }
         }
     }
 }
 
-unsafe impl Send for OpenAI {}
-unsafe impl Sync for OpenAI {}
+impl Default for OpenAI {
+    fn default() -> Self {
+        Self::new(
+            std::env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY is not set"),
+            DEFAULT_MODEL,
+        )
+    }
+}
+
+#[async_trait]
+impl AIProvider for OpenAI {
+    /// Sends a message to the OpenAI API, with the endpoint selected by `with_api`.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - A `client::Message` instance containing the message to be sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response contains an error.
+    ///
+    async fn send_message(&self, message: client::Message) -> anyhow::Result<client::Response> {
+        match self.api {
+            OpenAIApi::ChatCompletions => self.send_chat_completion(message).await,
+            OpenAIApi::Responses => self.send_response(message).await,
+        }
+    }
+
+    /// Counts the input tokens of a message locally, as OpenAI has no endpoint for it.
+    ///
+    /// Models without a known encoding, e.g. on OpenAI-compatible servers, are estimated.
+    async fn count_tokens(&self, message: client::Message) -> anyhow::Result<usize> {
+        let model = message.model.clone().unwrap_or_else(|| self.model.clone());
+        // Counting extracts the text of PDF documents, which is CPU-bound.
+        let count = move || tokens::count_message_tokens(&model, &message);
+        Ok(tokio::task::spawn_blocking(count).await?)
+    }
+
+    /// Lists the models of the `/models` endpoint, including embedding, audio and image models.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response contains an error.
+    ///
+    async fn list_models(&self) -> anyhow::Result<Vec<client::AvailableModel>> {
+        let response = self
+            .client
+            .get(format!("{}models", self.base_url))
+            .header("Authorization", &format!("Bearer {}", self.api_key))
+            .send()
+            .await?;
+
+        let (status, response) = read_json(response).await?;
+        match serde_json::from_value::<ModelsResponse>(response)? {
+            ModelsResponse::Models { data } => Ok(data
+                .into_iter()
+                .map(|model| client::AvailableModel::new(model.id, Self::NAME))
+                .collect()),
+            ModelsResponse::Error { error } => Err(ApiError::new(status, error.message).into()),
+        }
+    }
+
+    /// Transcribes audio with the `/audio/transcriptions` endpoint of the OpenAI API.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A `client::TranscriptionRequest` instance containing the audio.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response contains an error.
+    ///
+    async fn transcribe(
+        &self,
+        request: client::TranscriptionRequest,
+    ) -> anyhow::Result<client::Transcription> {
+        let format = AudioFormat::from_mime_type(&request.audio.mime_type).ok_or_else(|| {
+            anyhow::anyhow!("unsupported audio type: {}", request.audio.mime_type)
+        })?;
+        let data = general_purpose::STANDARD.decode(&request.audio.data)?;
+        let file = multipart::Part::bytes(data)
+            .file_name(format!("audio.{}", format.extension()))
+            .mime_str(format.mime_type())?;
+
+        let mut form = multipart::Form::new()
+            .part("file", file)
+            .text("model", request.model)
+            .text("response_format", "json");
+        if let Some(language) = request.language {
+            form = form.text("language", language);
+        }
+        if let Some(prompt) = request.prompt {
+            form = form.text("prompt", prompt);
+        }
+
+        let response = self
+            .client
+            .post(format!("{}audio/transcriptions", self.base_url))
+            .header("Authorization", &format!("Bearer {}", self.api_key))
+            .multipart(form)
+            .send()
+            .await?;
+
+        let (status, response) = read_json(response).await?;
+        trace!(
+            "JSON Response: {}",
+            serde_json::to_string_pretty(&response).unwrap()
+        );
+
+        match serde_json::from_value::<TranscriptionResponse>(response)? {
+            TranscriptionResponse::Transcription { text } => Ok(client::Transcription { text }),
+            TranscriptionResponse::Error { error } => {
+                Err(ApiError::new(status, error.message).into())
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn model(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+
+    fn supports_image_urls(&self) -> bool {
+        true
+    }
+
+    fn as_embedding_provider(&self) -> Option<&dyn EmbeddingProvider> {
+        Some(self)
+    }
+
+    fn as_image_generator(&self) -> Option<&dyn ImageGenerationProvider> {
+        Some(self)
+    }
+
+    fn image_limits(&self) -> ImageLimits {
+        ImageLimits::OPENAI
+    }
+
+    fn max_tokens(&self, _model: &str) -> Option<usize> {
+        Some(MAX_TOKENS as usize)
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAI {
+    /// Computes embeddings with the `/embeddings` endpoint of the OpenAI API.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A `client::EmbeddingRequest` instance containing the texts.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response contains an error.
+    ///
+    async fn embed(&self, request: client::EmbeddingRequest) -> anyhow::Result<client::Embeddings> {
+        let request = EmbeddingRequest {
+            model: request.model.unwrap_or_else(|| EMBEDDING_MODEL.to_string()),
+            input: request.texts,
+            dimensions: request.dimensions,
+            encoding_format: "float".to_string(),
+        };
+
+        let response = self
+            .client
+            .post(format!("{}embeddings", self.base_url))
+            .header("Authorization", &format!("Bearer {}", self.api_key))
+            .json(&request)
+            .send()
+            .await?;
+
+        let (status, response) = read_json(response).await?;
+        trace!(
+            "JSON Response: {}",
+            serde_json::to_string_pretty(&response).unwrap()
+        );
+
+        match serde_json::from_value::<EmbeddingResponse>(response)? {
+            EmbeddingResponse::Embeddings { mut data, usage } => {
+                data.sort_by_key(|embedding| embedding.index);
+                let vectors = data.into_iter().map(|embedding| embedding.embedding);
+                let usage = usage.map(|usage| client::EmbeddingUsage {
+                    prompt_tokens: usage.prompt_tokens,
+                });
+                Ok(client::Embeddings::new(vectors.collect(), usage))
+            }
+            EmbeddingResponse::Error { error } => Err(ApiError::new(status, error.message).into()),
+        }
+    }
+
+    fn max_batch_size(&self) -> usize {
+        MAX_EMBEDDING_INPUTS
+    }
+}
+
+#[async_trait]
+impl ImageGenerationProvider for OpenAI {
+    /// Generates images with the `/images/generations` endpoint of the OpenAI API.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A `client::ImageGenerationRequest` instance containing the prompt.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response contains an error.
+    ///
+    async fn generate_image(
+        &self,
+        request: client::ImageGenerationRequest,
+    ) -> anyhow::Result<Vec<client::GeneratedImage>> {
+        let model = request.model.unwrap_or_else(|| IMAGE_MODEL.to_string());
+        // DALL·E models return URLs by default, while GPT image models always return base64.
+        let response_format = model.starts_with("dall-e").then(|| "b64_json".to_string());
+        let request = ImageRequest {
+            model,
+            prompt: request.prompt,
+            n: request.n,
+            size: request.size.to_string(),
+            response_format,
+        };
+
+        let response = self
+            .client
+            .post(format!("{}images/generations", self.base_url))
+            .header("Authorization", &format!("Bearer {}", self.api_key))
+            .json(&request)
+            .send()
+            .await?;
+
+        let (status, response) = read_json(response).await?;
+        match serde_json::from_value::<ImageResponse>(response)? {
+            ImageResponse::Images { data } => data
+                .into_iter()
+                .map(|image| {
+                    let mut generated = client::GeneratedImage::from_base64(&image.b64_json, None)?;
+                    generated.revised_prompt = image.revised_prompt;
+                    Ok(generated)
+                })
+                .collect(),
+            ImageResponse::Error { error } => Err(ApiError::new(status, error.message).into()),
+        }
+    }
+}
 
 #[derive(Serialize, Debug)]
 /// Represents a request to the OpenAI API.
 struct Request {
     model: String,
     messages: Vec<ChatMessage>,
-    max_tokens: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ChatResponseFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logprobs: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_logprobs: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// Represents the format of the answer of a chat completion.
+enum ChatResponseFormat {
+    JsonObject,
+    JsonSchema { json_schema: JsonSchema },
+}
+
+#[derive(Serialize, Debug)]
+/// Represents a JSON schema the answer must match.
+struct JsonSchema {
+    name: String,
+    schema: serde_json::Value,
+    strict: bool,
+}
+
+impl From<client::ResponseFormat> for ChatResponseFormat {
+    fn from(format: client::ResponseFormat) -> Self {
+        match format {
+            client::ResponseFormat::Json => ChatResponseFormat::JsonObject,
+            client::ResponseFormat::JsonSchema {
+                name,
+                schema,
+                strict,
+            } => ChatResponseFormat::JsonSchema {
+                json_schema: JsonSchema {
+                    name,
+                    schema,
+                    strict,
+                },
+            },
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -162,6 +609,20 @@ struct ChatMessage {
     content: Content,
 }
 
+impl From<client::Turn> for ChatMessage {
+    /// Replays a previous turn with its text, as chat completions do not take thinking blocks.
+    fn from(turn: client::Turn) -> Self {
+        let role = match turn.role {
+            client::Role::User => "user",
+            client::Role::Assistant => "assistant",
+        };
+        Self {
+            role: role.to_string(),
+            content: Content::Simple(turn.text()),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(untagged)]
 /// Represents the content of a chat message.
@@ -178,6 +639,22 @@ impl Content {
         }
     }
 
+    /// Returns the text parts of the content as content blocks.
+    pub fn blocks(&self) -> Vec<client::ContentBlock> {
+        match self {
+            Content::Simple(text) => vec![client::ContentBlock::Text { text: text.clone() }],
+            Content::Complex(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ComplexContent::Text(part) => Some(client::ContentBlock::Text {
+                        text: part.text.clone(),
+                    }),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
     pub fn push(&mut self, content: ComplexContent) {
         if let Content::Complex(vec) = self {
             vec.push(content);
@@ -186,11 +663,14 @@ impl Content {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-#[serde(tag = "type")]
-/// Represents complex content types for a chat message.
+#[serde(untagged)]
+/// Represents complex content types for a chat message, told apart by their fields since each
+/// holds its own `type`.
 enum ComplexContent {
     Text(Text),
     Image(Image),
+    InputAudio(InputAudio),
+    File(File),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -209,12 +689,133 @@ struct Image {
     typ: String,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+/// Represents a file, e.g. a PDF document, for a chat message.
+struct File {
+    file: FileData,
+    #[serde(rename = "type")]
+    typ: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+/// Represents the content of a file, as a data URL.
+struct FileData {
+    file_data: String,
+    filename: String,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 /// Represents the URL of an image.
 struct ImageUrl {
     url: String,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+/// Represents audio content for a chat message.
+struct InputAudio {
+    input_audio: AudioData,
+    #[serde(rename = "type")]
+    typ: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+/// Represents base64 encoded audio and its format, `wav` or `mp3`.
+struct AudioData {
+    data: String,
+    format: String,
+}
+
+#[derive(Serialize, Debug)]
+/// Represents a request to the embeddings endpoint of the OpenAI API.
+struct EmbeddingRequest {
+    model: String,
+    input: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
+    encoding_format: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+/// Represents a response from the embeddings endpoint of the OpenAI API.
+enum EmbeddingResponse {
+    Embeddings {
+        data: Vec<Embedding>,
+        usage: Option<EmbeddingUsage>,
+    },
+    Error {
+        error: ErrorMessage,
+    },
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+/// Represents a response from the models endpoint of the OpenAI API.
+enum ModelsResponse {
+    Models { data: Vec<Model> },
+    Error { error: ErrorMessage },
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents a model listed by the models endpoint.
+struct Model {
+    id: String,
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents a single vector returned by the embeddings endpoint.
+struct Embedding {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents the usage information of the embeddings endpoint.
+struct EmbeddingUsage {
+    prompt_tokens: usize,
+}
+
+#[derive(Serialize, Debug)]
+/// Represents a request to the images endpoint of the OpenAI API.
+struct ImageRequest {
+    model: String,
+    prompt: String,
+    n: usize,
+    size: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+/// Represents a response from the images endpoint of the OpenAI API.
+enum ImageResponse {
+    Images { data: Vec<ImageData> },
+    Error { error: ErrorMessage },
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents a single generated image.
+struct ImageData {
+    b64_json: String,
+    revised_prompt: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+/// Represents a response from the transcriptions endpoint of the OpenAI API.
+enum TranscriptionResponse {
+    Transcription { text: String },
+    Error { error: ErrorMessage },
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents an error of the transcriptions, embeddings and images endpoints, whose `param` is
+/// usually `null`.
+struct ErrorMessage {
+    message: String,
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
 /// Represents a response from the OpenAI API.
@@ -235,6 +836,75 @@ struct Message {
     usage: Usage,
 }
 
+impl Message {
+    /// Returns the content blocks and finish reason of every choice.
+    fn candidates(self) -> Vec<client::Candidate> {
+        self.choices
+            .into_iter()
+            .map(|choice| {
+                let message = choice.message;
+                let mut content = message
+                    .content
+                    .as_ref()
+                    .map(Content::blocks)
+                    .unwrap_or_default();
+                if let Some(refusal) = message.refusal {
+                    content.push(client::ContentBlock::Text { text: refusal });
+                }
+                content.extend(message.tool_calls.into_iter().map(ToolCall::into_block));
+
+                client::Candidate {
+                    content,
+                    finish_reason: Some(choice.finish_reason),
+                    safety_ratings: Vec::new(),
+                    logprobs: choice
+                        .logprobs
+                        .and_then(|logprobs| logprobs.content)
+                        .map(|tokens| tokens.into_iter().map(Into::into).collect()),
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents the log probabilities of a choice, `null` for refusals.
+struct ChoiceLogprobs {
+    content: Option<Vec<LogprobToken>>,
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents a generated token with its log probability, as returned by both APIs.
+struct LogprobToken {
+    token: String,
+    logprob: f32,
+    #[serde(default)]
+    top_logprobs: Vec<TopLogprobToken>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TopLogprobToken {
+    token: String,
+    logprob: f32,
+}
+
+impl From<LogprobToken> for client::TokenLogprob {
+    fn from(token: LogprobToken) -> Self {
+        Self {
+            token: token.token,
+            logprob: token.logprob,
+            top_logprobs: token
+                .top_logprobs
+                .into_iter()
+                .map(|top| client::TopLogprob {
+                    token: top.token,
+                    logprob: top.logprob,
+                })
+                .collect(),
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
 /// Represents an error in the response from the OpenAI API.
 struct Error {
@@ -249,17 +919,113 @@ struct Error {
 /// Represents a choice in the response from the OpenAI API.
 struct Choice {
     index: usize,
-    message: ChatMessage,
-    logprobs: Option<bool>,
+    message: ResponseMessage,
+    logprobs: Option<ChoiceLogprobs>,
     finish_reason: String,
 }
 
+#[derive(Deserialize, Debug)]
+/// Represents the message of a choice, whose content is `null` when the model only calls tools.
+struct ResponseMessage {
+    role: String,
+    content: Option<Content>,
+    refusal: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCall>,
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents a call to a tool requested by the model.
+struct ToolCall {
+    id: String,
+    function: FunctionCall,
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents the function called by a tool call, with its arguments encoded as JSON.
+struct FunctionCall {
+    name: String,
+    arguments: String,
+}
+
+impl ToolCall {
+    /// Converts the tool call to a content block, keeping arguments that are not valid JSON as a
+    /// string.
+    fn into_block(self) -> client::ContentBlock {
+        let arguments = serde_json::from_str(&self.function.arguments)
+            .unwrap_or(serde_json::Value::String(self.function.arguments));
+        client::ContentBlock::ToolCall {
+            id: Some(self.id),
+            name: self.function.name,
+            arguments,
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
 /// Represents the usage information in the response from the OpenAI API.
 struct Usage {
     prompt_tokens: usize,
     completion_tokens: usize,
     total_tokens: usize,
+    completion_tokens_details: Option<CompletionTokensDetails>,
+    prompt_tokens_details: Option<PromptTokensDetails>,
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents the breakdown of the prompt tokens, reporting the tokens read from the cache that
+/// OpenAI maintains automatically.
+struct PromptTokensDetails {
+    cached_tokens: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents the breakdown of the completion tokens, reported for reasoning models.
+struct CompletionTokensDetails {
+    reasoning_tokens: Option<usize>,
+}
+
+impl From<&Usage> for client::Usage {
+    fn from(usage: &Usage) -> Self {
+        Self {
+            input_tokens: usage.prompt_tokens,
+            output_tokens: usage.completion_tokens,
+            reasoning_tokens: usage
+                .completion_tokens_details
+                .as_ref()
+                .and_then(|details| details.reasoning_tokens),
+            cache_creation_input_tokens: None,
+            cache_read_input_tokens: usage
+                .prompt_tokens_details
+                .as_ref()
+                .and_then(|details| details.cached_tokens),
+        }
+    }
+}
+
+/// Returns `true` for the o-series reasoning models, e.g. `o1` or `o3-mini`.
+///
+/// # Arguments
+///
+/// * `model` - A string slice that holds the name of the model.
+///
+pub(crate) fn is_reasoning_model(model: &str) -> bool {
+    let mut chars = model.chars();
+    chars.next() == Some('o') && chars.next().is_some_and(|c| c.is_ascii_digit())
+}
+
+/// Returns `true` if the model accepts reasoning settings, according to the catalog, or for
+/// o-series models missing from it.
+///
+/// # Arguments
+///
+/// * `model` - A string slice that holds the name of the model.
+///
+pub(crate) fn accepts_reasoning(model: &str) -> bool {
+    models::info(model).map_or_else(
+        || is_reasoning_model(model),
+        |info| info.capabilities.reasoning,
+    )
 }
 
 #[cfg(test)]
@@ -372,6 +1138,248 @@ mod tests {
         }
     }
 
+    #[test]
+    /// Tests parsing responses from the transcriptions endpoint of the OpenAI API.
+    fn test_parse_transcription() {
+        let res = r#"{"text": "Thanks for calling."}"#;
+        let res = serde_json::from_str::<TranscriptionResponse>(res).unwrap();
+        assert!(
+            matches!(res, TranscriptionResponse::Transcription { text } if text == "Thanks for calling.")
+        );
+
+        let res = r#"
+        {
+          "error": {
+            "message": "Invalid file format.",
+            "type": "invalid_request_error",
+            "param": null,
+            "code": null
+          }
+        }
+        "#;
+        let res = serde_json::from_str::<TranscriptionResponse>(res).unwrap();
+        assert!(
+            matches!(res, TranscriptionResponse::Error { error } if error.message == "Invalid file format.")
+        );
+    }
+
+    #[test]
+    /// Tests parsing a response from the embeddings endpoint of the OpenAI API.
+    fn test_parse_embeddings() {
+        let res = r#"
+        {
+          "object": "list",
+          "data": [
+            { "object": "embedding", "index": 1, "embedding": [0.3, 0.4] },
+            { "object": "embedding", "index": 0, "embedding": [0.1, 0.2] }
+          ],
+          "model": "text-embedding-3-small",
+          "usage": { "prompt_tokens": 5, "total_tokens": 5 }
+        }
+        "#;
+        match serde_json::from_str::<EmbeddingResponse>(res).unwrap() {
+            EmbeddingResponse::Embeddings { data, usage } => {
+                assert_eq!(data.len(), 2);
+                assert_eq!(data[1].index, 0);
+                assert_eq!(usage.unwrap().prompt_tokens, 5);
+            }
+            EmbeddingResponse::Error { error } => panic!("unexpected error: {}", error.message),
+        }
+    }
+
+    #[test]
+    /// Tests reading every choice of a response as a candidate.
+    fn test_candidates() {
+        let res = r#"
+        {
+          "choices": [
+            { "finish_reason": "stop", "index": 0, "message": { "content": "391", "role": "assistant" } },
+            { "finish_reason": "length", "index": 1, "message": { "content": "39", "role": "assistant" } }
+          ],
+          "created": 1719328775,
+          "id": "chatcmpl-9e2FDY8pjRfZqufnqa4XSu5f26aUy",
+          "model": "gpt-4o-2024-05-13",
+          "object": "chat.completion",
+          "system_fingerprint": "fp_8c6b918852",
+          "usage": { "completion_tokens": 2, "prompt_tokens": 20, "total_tokens": 22 }
+        }
+        "#;
+        let Response::Message(message) = serde_json::from_str::<Response>(res).unwrap() else {
+            panic!("response is not a message");
+        };
+        let usage = client::Usage::from(&message.usage);
+        let response = client::Response::from_candidates(message.candidates()).with_usage(usage);
+        assert_eq!(response.text(), "391");
+        assert_eq!(response.usage.unwrap().reasoning_tokens, None);
+        assert_eq!(response.candidates().len(), 2);
+        assert_eq!(
+            response.candidates()[1].finish_reason.as_deref(),
+            Some("length")
+        );
+    }
+
+    #[test]
+    /// Tests reading the log probabilities of a choice with their top alternatives.
+    fn test_candidate_logprobs() {
+        let res = r#"
+        {
+          "choices": [
+            {
+              "finish_reason": "stop",
+              "index": 0,
+              "message": { "content": "Yes", "role": "assistant" },
+              "logprobs": {
+                "content": [
+                  {
+                    "token": "Yes",
+                    "logprob": -0.01,
+                    "bytes": [89, 101, 115],
+                    "top_logprobs": [
+                      { "token": "Yes", "logprob": -0.01, "bytes": [89, 101, 115] },
+                      { "token": "No", "logprob": -4.6, "bytes": [78, 111] }
+                    ]
+                  }
+                ]
+              }
+            }
+          ],
+          "created": 1719328775,
+          "id": "chatcmpl-1",
+          "model": "gpt-4o-2024-05-13",
+          "object": "chat.completion",
+          "system_fingerprint": "fp_8c6b918852",
+          "usage": { "completion_tokens": 1, "prompt_tokens": 20, "total_tokens": 21 }
+        }
+        "#;
+        let Response::Message(message) = serde_json::from_str::<Response>(res).unwrap() else {
+            panic!("response is not a message");
+        };
+        let response = client::Response::from_candidates(message.candidates());
+        let logprobs = response.logprobs().unwrap();
+        assert_eq!(logprobs.len(), 1);
+        assert_eq!(logprobs[0].token, "Yes");
+        assert!(logprobs[0].probability() > 0.98);
+        assert_eq!(
+            logprobs[0].top_logprobs[1],
+            client::TopLogprob {
+                token: "No".to_string(),
+                logprob: -4.6,
+            }
+        );
+    }
+
+    #[test]
+    /// Tests reading complex content, refusals and tool calls as content blocks.
+    fn test_candidate_blocks() {
+        let res = r#"
+        {
+          "choices": [
+            {
+              "finish_reason": "tool_calls",
+              "index": 0,
+              "message": {
+                "role": "assistant",
+                "content": [
+                  { "type": "text", "text": "Checking " },
+                  { "type": "text", "text": "the weather." }
+                ],
+                "tool_calls": [
+                  {
+                    "id": "call_1",
+                    "type": "function",
+                    "function": { "name": "weather", "arguments": "{\"city\":\"Lisbon\"}" }
+                  }
+                ]
+              }
+            },
+            {
+              "finish_reason": "stop",
+              "index": 1,
+              "message": { "role": "assistant", "content": null, "refusal": "I can't help." }
+            }
+          ],
+          "created": 1719328775,
+          "id": "chatcmpl-9e2FDY8pjRfZqufnqa4XSu5f26aUy",
+          "model": "gpt-4o-2024-05-13",
+          "object": "chat.completion",
+          "system_fingerprint": "fp_8c6b918852",
+          "usage": { "completion_tokens": 2, "prompt_tokens": 20, "total_tokens": 22 }
+        }
+        "#;
+        let Response::Message(message) = serde_json::from_str::<Response>(res).unwrap() else {
+            panic!("response is not a message");
+        };
+        let response = client::Response::from_candidates(message.candidates());
+        assert_eq!(response.text(), "Checking the weather.");
+        assert_eq!(
+            response.content[2],
+            client::ContentBlock::ToolCall {
+                id: Some("call_1".to_string()),
+                name: "weather".to_string(),
+                arguments: serde_json::json!({"city": "Lisbon"}),
+            }
+        );
+        assert_eq!(response.candidates()[1].text(), "I can't help.");
+    }
+
+    #[test]
+    /// Tests parsing a response from the images endpoint of the OpenAI API.
+    fn test_parse_images() {
+        let res = r#"
+        {
+          "created": 1713833628,
+          "data": [
+            { "b64_json": "iVBORw0KGgo=", "revised_prompt": "A lighthouse at dawn" }
+          ]
+        }
+        "#;
+        match serde_json::from_str::<ImageResponse>(res).unwrap() {
+            ImageResponse::Images { data } => {
+                let image = client::GeneratedImage::from_base64(&data[0].b64_json, None).unwrap();
+                assert_eq!(image.mime_type, "image/png");
+                assert_eq!(
+                    data[0].revised_prompt.as_deref(),
+                    Some("A lighthouse at dawn")
+                );
+            }
+            ImageResponse::Error { error } => panic!("unexpected error: {}", error.message),
+        }
+    }
+
+    #[test]
+    /// Tests serializing audio content for a chat message.
+    fn test_serialize_input_audio() {
+        let audio = ComplexContent::InputAudio(InputAudio {
+            typ: "input_audio".to_string(),
+            input_audio: AudioData {
+                data: "data".to_string(),
+                format: "wav".to_string(),
+            },
+        });
+        let json = serde_json::to_value(audio).unwrap();
+        assert_eq!(json["type"], "input_audio");
+        assert_eq!(json["input_audio"]["format"], "wav");
+    }
+
+    #[test]
+    /// Tests serializing a PDF document for a chat message.
+    fn test_serialize_file() {
+        let file = ComplexContent::File(File {
+            typ: "file".to_string(),
+            file: FileData {
+                file_data: "data:application/pdf;base64,JVBERi0=".to_string(),
+                filename: "fax.pdf".to_string(),
+            },
+        });
+        let json = serde_json::to_value(file).unwrap();
+        assert_eq!(json["type"], "file");
+        assert_eq!(json["file"]["filename"], "fax.pdf");
+        assert!(json["file"]["file_data"]
+            .as_str()
+            .unwrap()
+            .starts_with("data:application/pdf;base64,"));
+    }
+
     #[test]
     /// Tests parsing a message response from the OpenAI API.
     fn test_response_message() {
@@ -403,7 +1411,11 @@ mod tests {
         let response = serde_json::from_str::<Response>(res).unwrap();
         if let Response::Message(message) = response {
             assert_eq!(
-                message.choices[0].message.content.as_text(),
+                message.choices[0]
+                    .message
+                    .content
+                    .as_ref()
+                    .and_then(Content::as_text),
                 Some("response")
             );
         } else {
@@ -430,4 +1442,81 @@ mod tests {
         };
         assert_eq!(error.code, Some("invalid_request_error".to_string()));
     }
+
+    #[test]
+    /// Tests reading the reasoning tokens of o-series models.
+    fn test_reasoning_usage() {
+        let usage = serde_json::from_str::<Usage>(
+            r#"{
+              "prompt_tokens": 20,
+              "completion_tokens": 300,
+              "total_tokens": 320,
+              "completion_tokens_details": { "reasoning_tokens": 256 }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            client::Usage::from(&usage),
+            client::Usage {
+                input_tokens: 20,
+                output_tokens: 300,
+                reasoning_tokens: Some(256),
+                cache_creation_input_tokens: None,
+                cache_read_input_tokens: None,
+            }
+        );
+    }
+
+    #[test]
+    /// Tests serializing the formats of chat completion answers.
+    fn test_serialize_response_format() {
+        assert_eq!(
+            serde_json::to_value(ChatResponseFormat::from(client::ResponseFormat::Json)).unwrap(),
+            serde_json::json!({"type": "json_object"})
+        );
+        let format = client::ResponseFormat::json_schema("fax", serde_json::json!({}));
+        assert_eq!(
+            serde_json::to_value(ChatResponseFormat::from(format)).unwrap(),
+            serde_json::json!({
+                "type": "json_schema",
+                "json_schema": {"name": "fax", "schema": {}, "strict": true}
+            })
+        );
+    }
+
+    #[test]
+    /// Tests reading the models endpoint, with catalog entries for known models.
+    fn test_parse_models() {
+        let res = r#"
+        {
+          "object": "list",
+          "data": [
+            { "id": "gpt-4o-2024-08-06", "object": "model", "created": 1722814719, "owned_by": "system" },
+            { "id": "whisper-1", "object": "model", "created": 1677532384, "owned_by": "openai-internal" }
+          ]
+        }
+        "#;
+        let ModelsResponse::Models { data } = serde_json::from_str(res).unwrap() else {
+            panic!("response is not a list of models");
+        };
+        assert_eq!(data.len(), 2);
+        let model = client::AvailableModel::new(&data[0].id, OpenAI::NAME);
+        assert_eq!(model.info.unwrap().name, "gpt-4o");
+        assert!(client::AvailableModel::new(&data[1].id, OpenAI::NAME)
+            .info
+            .is_none());
+    }
+
+    #[test]
+    /// Tests recognizing o-series reasoning models, and the models accepting reasoning settings.
+    fn test_is_reasoning_model() {
+        assert!(is_reasoning_model("o1"));
+        assert!(is_reasoning_model("o3-mini"));
+        assert!(!is_reasoning_model("gpt-4o"));
+        assert!(!is_reasoning_model("omni"));
+
+        assert!(accepts_reasoning("o3-mini-2025-01-31"));
+        assert!(accepts_reasoning("o9"));
+        assert!(!accepts_reasoning("gpt-4o"));
+    }
 }
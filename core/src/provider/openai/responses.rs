@@ -0,0 +1,644 @@
+use log::{debug, trace};
+use serde::{Deserialize, Serialize};
+
+use crate::{client, document};
+
+use super::{
+    accepts_reasoning, is_reasoning_model, read_json, ApiError, LogprobToken, OpenAI, MAX_TOKENS,
+};
+
+impl OpenAI {
+    /// Sends a message to the `/responses` endpoint.
+    pub(super) async fn send_response(
+        &self,
+        message: client::Message,
+    ) -> anyhow::Result<client::Response> {
+        message.options.check_single_candidate(OpenAI::NAME)?;
+        let model = message.model.clone().unwrap_or_else(|| self.model.clone());
+        let request = build_request(message, model)?;
+        trace!(
+            "JSON Request: {}",
+            serde_json::to_string_pretty(&request).unwrap()
+        );
+
+        let response = self
+            .client
+            .post(format!("{}responses", self.base_url))
+            .header("Authorization", &format!("Bearer {}", self.api_key))
+            .json(&request)
+            .send()
+            .await?;
+
+        let (status, response) = read_json(response).await?;
+        trace!(
+            "JSON Response: {}",
+            serde_json::to_string_pretty(&response).unwrap()
+        );
+
+        let response = serde_json::from_value::<Response>(response)?;
+        debug!("OpenAI Response: {:#?}", response);
+
+        match response {
+            Response::Success(response) => Ok(response.into_response()),
+            Response::Error { error } => {
+                let message = match error.code {
+                    Some(code) => format!("{}: {}", code, error.message),
+                    None => error.message,
+                };
+                Err(ApiError::new(status, message).into())
+            }
+        }
+    }
+}
+
+/// Builds the request to the `/responses` endpoint, sending the system prompt as instructions.
+fn build_request(message: client::Message, model: String) -> anyhow::Result<Request> {
+    if message
+        .audio
+        .as_ref()
+        .is_some_and(|audio| !audio.is_empty())
+    {
+        anyhow::bail!("the OpenAI Responses API does not accept audio");
+    }
+
+    // Documents come first, then images, and the text last, like with Chat Completions.
+    let mut content = Vec::new();
+
+    // PDF documents are read natively, while the text of other documents is sent as is.
+    for doc in message.documents.unwrap_or_default() {
+        let part = match doc.url {
+            Some(file_url) => InputContent::File {
+                filename: None,
+                file_data: None,
+                file_url: Some(file_url),
+            },
+            None if doc.mime_type == document::PDF => InputContent::File {
+                file_data: Some(format!("data:{};base64,{}", doc.mime_type, doc.data)),
+                filename: Some(doc.name.unwrap_or_else(|| "document.pdf".to_string())),
+                file_url: None,
+            },
+            None => {
+                let text = document::text(&doc)?;
+                let text = match doc.name {
+                    Some(name) => format!("Document {name}:\n\n{text}"),
+                    None => text,
+                };
+                InputContent::Text { text }
+            }
+        };
+        content.push(part);
+    }
+
+    for image in message.images.unwrap_or_default() {
+        let image_url = match image.url {
+            Some(url) => url,
+            None => format!("data:{};base64,{}", image.mime_type, image.data),
+        };
+        content.push(InputContent::Image { image_url });
+    }
+
+    content.push(InputContent::Text { text: message.text });
+
+    let mut input = message
+        .history
+        .unwrap_or_default()
+        .into_iter()
+        .map(InputMessage::from)
+        .collect::<Vec<_>>();
+    input.push(InputMessage {
+        role: "user".to_string(),
+        content: Input::Parts(content),
+    });
+
+    // Reasoning tokens count towards `max_output_tokens`, so the budget is added to it. Other
+    // models reject reasoning settings, so they are dropped.
+    let reasoning = message
+        .options
+        .reasoning
+        .filter(|_| accepts_reasoning(&model));
+    let max_output_tokens = MAX_TOKENS.saturating_add(reasoning.map_or(0, |r| r.budget_tokens()));
+    let reasoning = match reasoning {
+        Some(reasoning) => Some(ReasoningConfig {
+            effort: Some(reasoning.effort().as_str().to_string()),
+            summary: "auto".to_string(),
+        }),
+        None if is_reasoning_model(&model) => Some(ReasoningConfig {
+            effort: None,
+            summary: "auto".to_string(),
+        }),
+        None => None,
+    };
+
+    Ok(Request {
+        model,
+        input,
+        instructions: message.system,
+        max_output_tokens: max_output_tokens as usize,
+        previous_response_id: message.options.previous_response_id,
+        reasoning,
+        text: message.options.response_format.map(|format| TextConfig {
+            format: TextFormat::from(format),
+        }),
+        // Log probabilities are only returned when included explicitly.
+        include: match message.options.logprobs {
+            Some(_) => vec!["message.output_text.logprobs".to_string()],
+            None => Vec::new(),
+        },
+        top_logprobs: message.options.logprobs,
+    })
+}
+
+#[derive(Serialize, Debug)]
+/// Represents a request to the `/responses` endpoint.
+struct Request {
+    model: String,
+    input: Vec<InputMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instructions: Option<String>,
+    max_output_tokens: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_response_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning: Option<ReasoningConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<TextConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    include: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_logprobs: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+/// Represents a message of the input, either plain text or typed parts.
+struct InputMessage {
+    role: String,
+    content: Input,
+}
+
+impl From<client::Turn> for InputMessage {
+    /// Replays a previous turn with its text. Conversations kept by OpenAI are continued with
+    /// `previous_response_id` instead.
+    fn from(turn: client::Turn) -> Self {
+        let role = match turn.role {
+            client::Role::User => "user",
+            client::Role::Assistant => "assistant",
+        };
+        Self {
+            role: role.to_string(),
+            content: Input::Text(turn.text()),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+enum Input {
+    Text(String),
+    Parts(Vec<InputContent>),
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type")]
+/// Represents a typed part of an input message.
+enum InputContent {
+    #[serde(rename = "input_text")]
+    Text { text: String },
+    #[serde(rename = "input_image")]
+    Image { image_url: String },
+    #[serde(rename = "input_file")]
+    File {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        filename: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        file_data: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        file_url: Option<String>,
+    },
+}
+
+#[derive(Serialize, Debug)]
+/// Represents the reasoning settings, always asking for a summary of the reasoning.
+struct ReasoningConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    effort: Option<String>,
+    summary: String,
+}
+
+#[derive(Serialize, Debug)]
+/// Represents the format of the answer.
+struct TextConfig {
+    format: TextFormat,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TextFormat {
+    JsonObject,
+    JsonSchema {
+        name: String,
+        schema: serde_json::Value,
+        strict: bool,
+    },
+}
+
+impl From<client::ResponseFormat> for TextFormat {
+    fn from(format: client::ResponseFormat) -> Self {
+        match format {
+            client::ResponseFormat::Json => TextFormat::JsonObject,
+            client::ResponseFormat::JsonSchema {
+                name,
+                schema,
+                strict,
+            } => TextFormat::JsonSchema {
+                name,
+                schema,
+                strict,
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+/// Represents a response from the `/responses` endpoint. Successful responses are tried first,
+/// as they also hold an `error` field set to `null`.
+enum Response {
+    Success(SuccessResponse),
+    Error { error: Error },
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents an error returned by the `/responses` endpoint.
+struct Error {
+    message: String,
+    code: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents a successful response, whose output holds reasoning, messages and tool calls in
+/// the order they were generated.
+struct SuccessResponse {
+    id: String,
+    status: String,
+    #[serde(default)]
+    output: Vec<OutputItem>,
+    incomplete_details: Option<IncompleteDetails>,
+    usage: Option<Usage>,
+}
+
+impl SuccessResponse {
+    /// Converts the response, finishing with the reason it is incomplete if it is.
+    fn into_response(self) -> client::Response {
+        let finish_reason = match self.incomplete_details {
+            Some(details) => details.reason,
+            None => self.status,
+        };
+        let mut logprobs = None::<Vec<client::TokenLogprob>>;
+        let mut content = Vec::new();
+        for mut item in self.output {
+            if let Some(tokens) = item.take_logprobs() {
+                logprobs
+                    .get_or_insert_with(Vec::new)
+                    .extend(tokens.into_iter().map(Into::into));
+            }
+            content.extend(item.into_blocks());
+        }
+
+        let response = client::Response::from_candidates(vec![client::Candidate {
+            content,
+            finish_reason: Some(finish_reason),
+            safety_ratings: Vec::new(),
+            logprobs,
+        }])
+        .with_id(self.id);
+        match self.usage {
+            Some(usage) => response.with_usage(usage.into()),
+            None => response,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct IncompleteDetails {
+    reason: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// Represents an item of the output of a response.
+enum OutputItem {
+    Reasoning {
+        #[serde(default)]
+        summary: Vec<SummaryText>,
+    },
+    Message {
+        #[serde(default)]
+        content: Vec<OutputContent>,
+    },
+    FunctionCall {
+        call_id: String,
+        name: String,
+        arguments: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+impl OutputItem {
+    /// Takes the log probabilities of the text of a message, returned when requested.
+    fn take_logprobs(&mut self) -> Option<Vec<LogprobToken>> {
+        let OutputItem::Message { content } = self else {
+            return None;
+        };
+        let tokens = content
+            .iter_mut()
+            .filter_map(|content| match content {
+                OutputContent::OutputText { logprobs, .. } => logprobs.take(),
+                _ => None,
+            })
+            .flatten()
+            .collect::<Vec<_>>();
+        (!tokens.is_empty()).then_some(tokens)
+    }
+
+    /// Converts the item to content blocks, returning reasoning summaries as thinking blocks.
+    fn into_blocks(self) -> Vec<client::ContentBlock> {
+        match self {
+            OutputItem::Reasoning { summary } => summary
+                .into_iter()
+                .map(|summary| client::ContentBlock::Thinking {
+                    text: summary.text,
+                    signature: None,
+                })
+                .collect(),
+            OutputItem::Message { content } => content
+                .into_iter()
+                .filter_map(|content| match content {
+                    OutputContent::OutputText { text, .. } => {
+                        Some(client::ContentBlock::Text { text })
+                    }
+                    OutputContent::Refusal { refusal } => {
+                        Some(client::ContentBlock::Text { text: refusal })
+                    }
+                    OutputContent::Other => None,
+                })
+                .collect(),
+            OutputItem::FunctionCall {
+                call_id,
+                name,
+                arguments,
+            } => vec![client::ContentBlock::ToolCall {
+                id: Some(call_id),
+                name,
+                arguments: serde_json::from_str(&arguments)
+                    .unwrap_or(serde_json::Value::String(arguments)),
+            }],
+            OutputItem::Other => Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct SummaryText {
+    text: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OutputContent {
+    OutputText {
+        text: String,
+        logprobs: Option<Vec<LogprobToken>>,
+    },
+    Refusal {
+        refusal: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug)]
+/// Represents the usage information of a response.
+struct Usage {
+    input_tokens: usize,
+    output_tokens: usize,
+    input_tokens_details: Option<InputTokensDetails>,
+    output_tokens_details: Option<OutputTokensDetails>,
+}
+
+#[derive(Deserialize, Debug)]
+struct InputTokensDetails {
+    cached_tokens: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OutputTokensDetails {
+    reasoning_tokens: Option<usize>,
+}
+
+impl From<Usage> for client::Usage {
+    fn from(usage: Usage) -> Self {
+        Self {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            reasoning_tokens: usage
+                .output_tokens_details
+                .and_then(|details| details.reasoning_tokens),
+            cache_creation_input_tokens: None,
+            cache_read_input_tokens: usage
+                .input_tokens_details
+                .and_then(|details| details.cached_tokens),
+        }
+    }
+}
+
+#[cfg(test)]
+/// Unit tests for the Responses API.
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Tests building a request chained to a previous response with structured output.
+    fn test_build_request() {
+        let message = client::Message {
+            system: Some("You read faxes.".to_string()),
+            text: "Who sent it?".to_string(),
+            documents: Some(vec![client::Document {
+                data: "JVBERi0=".to_string(),
+                mime_type: "application/pdf".to_string(),
+                name: Some("fax.pdf".to_string()),
+                url: None,
+                cache: false,
+            }]),
+            images: Some(vec![client::Image {
+                data: "iVBORw0=".to_string(),
+                mime_type: "image/png".to_string(),
+                url: None,
+            }]),
+            options: client::GenerationOptions::new()
+                .reasoning(client::Reasoning::Effort(client::ReasoningEffort::Low))
+                .previous_response_id("resp_1")
+                .response_format(client::ResponseFormat::json_schema(
+                    "fax",
+                    serde_json::json!({"type": "object"}),
+                )),
+            ..Default::default()
+        };
+        let request = build_request(message, "o4-mini".to_string()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({
+                "model": "o4-mini",
+                "input": [{
+                    "role": "user",
+                    "content": [
+                        {
+                            "type": "input_file",
+                            "filename": "fax.pdf",
+                            "file_data": "data:application/pdf;base64,JVBERi0="
+                        },
+                        {"type": "input_image", "image_url": "data:image/png;base64,iVBORw0="},
+                        {"type": "input_text", "text": "Who sent it?"}
+                    ]
+                }],
+                "instructions": "You read faxes.",
+                "max_output_tokens": 6144,
+                "previous_response_id": "resp_1",
+                "reasoning": {"effort": "low", "summary": "auto"},
+                "text": {
+                    "format": {
+                        "type": "json_schema",
+                        "name": "fax",
+                        "schema": {"type": "object"},
+                        "strict": true
+                    }
+                }
+            })
+        );
+
+        // Models without reasoning reject its settings.
+        let message = client::Message {
+            text: "Hi".to_string(),
+            options: client::GenerationOptions::new()
+                .reasoning(client::Reasoning::Effort(client::ReasoningEffort::Low)),
+            ..Default::default()
+        };
+        let request = build_request(message, "gpt-4o".to_string()).unwrap();
+        let request = serde_json::to_value(&request).unwrap();
+        assert!(request.get("reasoning").is_none());
+        assert_eq!(request["max_output_tokens"], 4096);
+    }
+
+    #[test]
+    /// Tests reading reasoning summaries, messages and usage in order.
+    fn test_parse_response() {
+        let res = r#"
+        {
+          "id": "resp_2",
+          "object": "response",
+          "status": "completed",
+          "error": null,
+          "incomplete_details": null,
+          "output": [
+            {
+              "type": "reasoning",
+              "id": "rs_1",
+              "summary": [{ "type": "summary_text", "text": "The header names the sender." }]
+            },
+            {
+              "type": "message",
+              "id": "msg_1",
+              "role": "assistant",
+              "content": [{ "type": "output_text", "text": "{\"sender\":\"ACME\"}", "annotations": [] }]
+            }
+          ],
+          "usage": {
+            "input_tokens": 3600,
+            "input_tokens_details": { "cached_tokens": 3584 },
+            "output_tokens": 120,
+            "output_tokens_details": { "reasoning_tokens": 96 },
+            "total_tokens": 3720
+          }
+        }
+        "#;
+        let Response::Success(response) = serde_json::from_str::<Response>(res).unwrap() else {
+            panic!("response is not a success");
+        };
+        let response = response.into_response();
+        assert_eq!(response.id.as_deref(), Some("resp_2"));
+        assert_eq!(response.thinking(), "The header names the sender.");
+        assert_eq!(response.text(), "{\"sender\":\"ACME\"}");
+        assert_eq!(
+            response.candidates()[0].finish_reason.as_deref(),
+            Some("completed")
+        );
+
+        let usage = response.usage.unwrap();
+        assert_eq!(usage.reasoning_tokens, Some(96));
+        assert_eq!(usage.cache_read_input_tokens, Some(3584));
+    }
+
+    #[test]
+    /// Tests requesting and reading the log probabilities of the output text.
+    fn test_logprobs() {
+        let message = client::Message {
+            text: "Is it spam?".to_string(),
+            options: client::GenerationOptions::new().logprobs(1),
+            ..Default::default()
+        };
+        let request =
+            serde_json::to_value(build_request(message, "gpt-4o".to_string()).unwrap()).unwrap();
+        assert_eq!(
+            request["include"],
+            serde_json::json!(["message.output_text.logprobs"])
+        );
+        assert_eq!(request["top_logprobs"], 1);
+
+        let res = r#"
+        {
+          "id": "resp_3",
+          "status": "completed",
+          "output": [
+            {
+              "type": "message",
+              "content": [
+                {
+                  "type": "output_text",
+                  "text": "No",
+                  "logprobs": [
+                    { "token": "No", "logprob": -0.02, "top_logprobs": [{ "token": "No", "logprob": -0.02 }] }
+                  ]
+                }
+              ]
+            }
+          ]
+        }
+        "#;
+        let Response::Success(response) = serde_json::from_str::<Response>(res).unwrap() else {
+            panic!("response is not a success");
+        };
+        let response = response.into_response();
+        let logprobs = response.logprobs().unwrap();
+        assert_eq!(logprobs[0].token, "No");
+        assert_eq!(logprobs[0].top_logprobs.len(), 1);
+    }
+
+    #[test]
+    /// Tests parsing an error response.
+    fn test_parse_error() {
+        let res = r#"
+        {
+          "error": {
+            "message": "Previous response with id 'resp_0' not found.",
+            "type": "invalid_request_error",
+            "param": "previous_response_id",
+            "code": "previous_response_not_found"
+          }
+        }
+        "#;
+        let response = serde_json::from_str::<Response>(res).unwrap();
+        assert!(
+            matches!(response, Response::Error { error } if error.code.as_deref() == Some("previous_response_not_found"))
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{
+    client::{Message, Turn},
+    document,
+    limiter::{self, TOKENS_PER_IMAGE},
+};
+
+/// Tokens wrapping every message in the chat format of OpenAI models.
+const TOKENS_PER_MESSAGE: usize = 3;
+/// Tokens priming the reply of the assistant in the chat format of OpenAI models.
+const TOKENS_PER_REPLY: usize = 3;
+
+/// Counts the tokens of a text for the given model.
+///
+/// OpenAI models are counted exactly with their BPE encoding when the `tiktoken` feature is
+/// enabled, other models are estimated like `limiter::estimate_text_tokens`.
+///
+/// # Arguments
+///
+/// * `model` - The name of the model.
+/// * `text` - The text to count.
+///
+/// # Examples
+///
+/// ```
+/// use aipim::tokens;
+///
+/// assert_eq!(tokens::count_text_tokens("gpt-4o", "Hello, world!"), 4);
+/// ```
+#[cfg_attr(not(feature = "tiktoken"), allow(unused_variables))]
+pub fn count_text_tokens(model: &str, text: &str) -> usize {
+    #[cfg(feature = "tiktoken")]
+    if let Some(bpe) = bpe(model) {
+        return bpe.encode_with_special_tokens(text).len();
+    }
+
+    limiter::estimate_text_tokens(text)
+}
+
+/// Counts the input tokens of a message locally, without calling the provider.
+///
+/// The system prompt, history, text and readable documents are counted with
+/// `count_text_tokens` in the chat format of OpenAI models, while images are estimated. Use
+/// `Client::count_tokens` for the exact count of Anthropic and Gemini models.
+///
+/// # Arguments
+///
+/// * `model` - The name of the model.
+/// * `message` - The message to count.
+///
+pub fn count_message_tokens(model: &str, message: &Message) -> usize {
+    let turns = message
+        .history
+        .iter()
+        .flatten()
+        .map(|turn| count_turn_tokens(model, turn))
+        .sum::<usize>();
+    let system = message.system.as_deref().map_or(0, |system| {
+        count_text_tokens(model, system) + TOKENS_PER_MESSAGE
+    });
+    // Documents that cannot be read locally, e.g. referenced by URL, are not counted.
+    let documents = message
+        .documents
+        .iter()
+        .flatten()
+        .filter_map(|doc| document::text(doc).ok())
+        .map(|text| count_text_tokens(model, &text))
+        .sum::<usize>();
+    let images = message.images.as_ref().map_or(0, Vec::len) * TOKENS_PER_IMAGE;
+
+    system
+        + turns
+        + documents
+        + images
+        + count_text_tokens(model, &message.text)
+        + TOKENS_PER_MESSAGE
+        + TOKENS_PER_REPLY
+}
+
+/// Counts the tokens of a turn of the history of a message, like `count_message_tokens`.
+///
+/// # Arguments
+///
+/// * `model` - The name of the model.
+/// * `turn` - The turn to count.
+///
+pub fn count_turn_tokens(model: &str, turn: &Turn) -> usize {
+    count_text_tokens(model, &turn.text()) + TOKENS_PER_MESSAGE
+}
+
+/// Returns the BPE encoding of an OpenAI model, if it uses a chat encoding.
+#[cfg(feature = "tiktoken")]
+fn bpe(model: &str) -> Option<&'static tiktoken_rs::CoreBPE> {
+    use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+
+    match get_tokenizer(model)? {
+        Tokenizer::O200kBase => Some(tiktoken_rs::o200k_base_singleton()),
+        Tokenizer::Cl100kBase => Some(tiktoken_rs::cl100k_base_singleton()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+/// Unit tests for the tokens module.
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Tests counting texts with the encoding of OpenAI models, and estimating the others.
+    fn test_count_text_tokens() {
+        assert_eq!(
+            count_text_tokens("claude-3-haiku-20240307", "a".repeat(10).as_str()),
+            3
+        );
+
+        #[cfg(feature = "tiktoken")]
+        {
+            assert_eq!(count_text_tokens("gpt-4o", "Hello, world!"), 4);
+            assert_eq!(count_text_tokens("gpt-4", "Hello, world!"), 4);
+        }
+    }
+
+    #[test]
+    /// Tests counting the system prompt, history and text of a message.
+    fn test_count_message_tokens() {
+        let message = Message {
+            system: Some("a".repeat(8)),
+            text: "a".repeat(8),
+            history: Some(vec![Turn::user("a".repeat(8))]),
+            ..Default::default()
+        };
+        assert_eq!(
+            count_message_tokens("llama3.1", &message),
+            2 * 3 + 3 * TOKENS_PER_MESSAGE + TOKENS_PER_REPLY
+        );
+    }
+}